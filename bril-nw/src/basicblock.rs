@@ -5,7 +5,7 @@ use std::{
     rc::Rc,
 };
 
-use crate::bril::types::{Function, FunctionArg, Instruction, OpCode};
+use crate::bril::types::{Function, FunctionArg, Instruction, OpCode, Type};
 
 lazy_static! {
     static ref TERMINATOR_INSTS: HashSet<OpCode> = {
@@ -18,19 +18,20 @@ lazy_static! {
     };
 }
 
-const BLOCK_NAME_PFX: &'static str = "block_";
+const BLOCK_NAME_PFX: &str = "block_";
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BasicBlock {
     id: usize,
     name: RefCell<String>,
     pub instrs: Vec<Rc<Instruction>>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct FunctionBlocks {
     name: String,
     args: Vec<Rc<FunctionArg>>,
+    return_type: Type,
     blocks: Vec<BasicBlock>,
     block_id_to_idx: HashMap<usize, usize>,
     block_name_to_id: HashMap<String, usize>,
@@ -112,13 +113,16 @@ impl FunctionBlocksLoader {
             return Err(self.load_errors);
         }
 
-        Ok(FunctionBlocks::new(
+        let mut function_blocks = FunctionBlocks::new(
             &self.function.name,
             self.function.args.clone(),
             self.blocks,
             self.block_id_to_idx,
             self.block_name_to_id,
-        ))
+        );
+        function_blocks.set_return_type(self.function.return_type.clone());
+
+        Ok(function_blocks)
     }
 
     fn add_block(&mut self, cur_block_instrs: &mut Vec<Rc<Instruction>>) {
@@ -180,6 +184,7 @@ impl FunctionBlocks {
         FunctionBlocks {
             name: name.to_string(),
             args,
+            return_type: Type::Unit,
             blocks,
             block_id_to_idx,
             block_name_to_id,
@@ -194,39 +199,27 @@ impl FunctionBlocks {
     }
 
     pub fn get_block_by_id(&self, id: usize) -> Option<&BasicBlock> {
-        let idx = self.block_id_to_idx.get(&id);
-        if let None = idx {
-            return None;
-        }
+        let idx = self.block_id_to_idx.get(&id)?;
 
-        Some(&self.blocks[*idx.unwrap()])
+        Some(&self.blocks[*idx])
     }
 
     pub fn get_block_by_name(&self, name: &str) -> Option<&BasicBlock> {
-        let id = self.block_name_to_id.get(name);
-        if let None = id {
-            return None;
-        }
+        let id = self.block_name_to_id.get(name)?;
 
-        Some(self.get_block_by_id(*id.unwrap()).unwrap())
+        Some(self.get_block_by_id(*id).unwrap())
     }
 
     pub fn get_block_idx_by_name(&self, name: &str) -> Option<usize> {
-        let id = self.block_name_to_id.get(name);
-        if let None = id {
-            return None;
-        }
+        let id = self.block_name_to_id.get(name)?;
 
-        Some(*id.unwrap())
+        Some(*id)
     }
 
     pub fn get_mut_block_by_id(&mut self, id: usize) -> Option<&mut BasicBlock> {
-        let idx = self.block_id_to_idx.get(&id);
-        if let None = idx {
-            return None;
-        }
+        let idx = self.block_id_to_idx.get(&id)?;
 
-        Some(&mut self.blocks[*idx.unwrap()])
+        Some(&mut self.blocks[*idx])
     }
 
     pub fn get_args(&self) -> &Vec<Rc<FunctionArg>> {
@@ -237,31 +230,162 @@ impl FunctionBlocks {
         &self.name
     }
 
+    // the function's header in bril text form, e.g. @main(x: int): int
+    pub fn get_signature(&self) -> String {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| format!("{}: {}", arg.name, arg.arg_type))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        if self.return_type == Type::Unit {
+            format!("@{}({})", self.name, args)
+        } else {
+            format!("@{}({}): {}", self.name, args, self.return_type)
+        }
+    }
+
+    pub fn get_return_type(&self) -> Type {
+        self.return_type.clone()
+    }
+
+    pub fn set_return_type(&mut self, return_type: Type) {
+        self.return_type = return_type;
+    }
+
     pub fn get_block_name(&self, id: usize) -> Option<String> {
         self.get_block_by_id(id).map(|b| b.get_name())
     }
+
+    // appends a new block to the end of the function and returns its id.
+    // if the first instr is a label, then that is the block's name
+    pub fn add_block(&mut self, instrs: Vec<Rc<Instruction>>) -> usize {
+        self.insert_block(self.blocks.len(), instrs)
+    }
+
+    // same as add_block, but puts the new block at position idx in program order.
+    // careful: blocks that fell through into the block now at idx fall into the new block instead
+    pub fn insert_block(&mut self, idx: usize, instrs: Vec<Rc<Instruction>>) -> usize {
+        let new_id = self
+            .blocks
+            .iter()
+            .map(|b| b.get_id() + 1)
+            .max()
+            .unwrap_or(0);
+
+        let block_name = match instrs.first().and_then(|i| i.get_label()) {
+            Some(label) => label.to_string(),
+            None => format!("{}{}", BLOCK_NAME_PFX, new_id),
+        };
+
+        let newbb = BasicBlock::new(new_id, instrs);
+        newbb.set_name(&block_name);
+
+        self.block_name_to_id.insert(block_name, new_id);
+        self.blocks.insert(idx, newbb);
+
+        for (i, block) in self.blocks.iter().enumerate().skip(idx) {
+            self.block_id_to_idx.insert(block.get_id(), i);
+        }
+
+        new_id
+    }
+
+    // puts the blocks in the order given by their ids. order must contain every block exactly once.
+    // this doesn't touch any instrs, so blocks that fall through now fall into a different block
+    pub fn reorder_blocks(&mut self, order: &[usize]) {
+        assert_eq!(
+            order.len(),
+            self.blocks.len(),
+            "order must list every block"
+        );
+
+        let mut blocks_by_id: HashMap<usize, BasicBlock> =
+            self.blocks.drain(..).map(|b| (b.get_id(), b)).collect();
+        for (idx, id) in order.iter().enumerate() {
+            let block = blocks_by_id
+                .remove(id)
+                .expect("order must list every block once");
+            self.block_id_to_idx.insert(*id, idx);
+            self.blocks.push(block);
+        }
+    }
+
+    // a name based on base_name that no block in this function uses yet
+    pub fn create_unique_block_name(&self, base_name: &str) -> String {
+        if !self.block_name_to_id.contains_key(base_name) {
+            return base_name.to_string();
+        }
+
+        (1..)
+            .map(|i| format!("{}.{}", base_name, i))
+            .find(|name| !self.block_name_to_id.contains_key(name))
+            .unwrap()
+    }
+
+    // a variable name that isn't an arg and isn't written or read anywhere in the function
+    pub fn create_unique_var_name(&self, base_name: &str) -> String {
+        let mut used_names: HashSet<String> =
+            self.args.iter().map(|arg| arg.name.clone()).collect();
+        for block in &self.blocks {
+            for instr in &block.instrs {
+                used_names.extend(instr.get_dest().map(|d| d.to_string()));
+                used_names.extend(instr.get_args().into_iter().flatten().cloned());
+            }
+        }
+
+        (0..)
+            .map(|i| match i {
+                0 => base_name.to_string(),
+                _ => format!("{}.{}", base_name, i),
+            })
+            .find(|name| !used_names.contains(name))
+            .unwrap()
+    }
+
+    // blocks without a label can only be reached by falling through to them.
+    // this gives the block a label instr (using its generated name) so it can be jumped to
+    pub fn get_or_create_block_label(&mut self, id: usize) -> Option<String> {
+        let block = self.get_mut_block_by_id(id)?;
+        if !block.instrs.first().is_some_and(|i| i.is_label()) {
+            let name = block.get_name();
+            block.instrs.insert(0, Instruction::new_label(&name));
+        }
+
+        Some(block.get_name())
+    }
+
+    // ends every block that falls through into the next one with an explicit jmp,
+    // and a last block that falls off the end of the function with an explicit ret.
+    // afterwards blocks can be reordered or appended without changing what the function does
+    pub fn make_fallthroughs_explicit(&mut self) {
+        for i in 0..self.blocks.len() {
+            let ends_in_terminator = self.blocks[i]
+                .instrs
+                .last()
+                .is_some_and(|instr| instr.is_jump() || instr.is_ret());
+            if ends_in_terminator {
+                continue;
+            }
+
+            let terminator = if i + 1 < self.blocks.len() {
+                let next_name = self
+                    .get_or_create_block_label(self.blocks[i + 1].get_id())
+                    .unwrap();
+                Instruction::new_effect(OpCode::Jump, vec![], vec![], vec![next_name])
+            } else {
+                Instruction::new_effect(OpCode::Ret, vec![], vec![], vec![])
+            };
+
+            self.blocks[i].instrs.push(terminator);
+        }
+    }
 }
 
 impl fmt::Display for FunctionBlocks {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "@{}(", self.get_name())?;
-        if !self.args.is_empty() {
-            for (i, arg) in self.args.iter().enumerate() {
-                write!(
-                    f,
-                    "{}: {}{}",
-                    &arg.name,
-                    arg.arg_type,
-                    if i < self.args.len() - 1 {
-                        ", "
-                    } else {
-                        ") {\n"
-                    }
-                )?;
-            }
-        } else {
-            write!(f, ") {{\n")?;
-        }
+        writeln!(f, "{} {{", self.get_signature())?;
 
         for block in &self.blocks {
             write!(f, "{}", block)?;
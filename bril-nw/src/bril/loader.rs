@@ -9,11 +9,29 @@ lazy_static! {
         OpCode::Id,
         OpCode::Add,
         OpCode::Mul,
+        OpCode::Sub,
+        OpCode::Div,
+        OpCode::Equal,
         OpCode::LessThan,
-        OpCode::Phi
+        OpCode::GreaterThan,
+        OpCode::LessThanOrEqual,
+        OpCode::GreaterThanOrEqual,
+        OpCode::Not,
+        OpCode::And,
+        OpCode::Or,
+        OpCode::Phi,
+        OpCode::Alloc,
+        OpCode::Load,
+        OpCode::PtrAdd
+    ]);
+    static ref EFFECT_INSTS: HashSet<OpCode> = HashSet::from([
+        OpCode::Print,
+        OpCode::Ret,
+        OpCode::Branch,
+        OpCode::Jump,
+        OpCode::Free,
+        OpCode::Store
     ]);
-    static ref EFFECT_INSTS: HashSet<OpCode> =
-        HashSet::from([OpCode::Print, OpCode::Ret, OpCode::Branch, OpCode::Jump]);
     static ref CONST_INSTS: HashSet<OpCode> = HashSet::from([OpCode::Const]);
 }
 
@@ -35,7 +53,7 @@ pub enum BrilLoadError {
 
 pub fn load_bril(loaded_str: &str) -> Result<Program, BrilLoadError> {
     let parsed = json::parse(loaded_str).map_err(|_e| BrilLoadError::JSONParse)?;
-    Ok(load_bril_from_obj(parsed)?)
+    load_bril_from_obj(parsed)
 }
 
 fn load_bril_from_obj(obj: JsonValue) -> Result<Program, BrilLoadError> {
@@ -96,6 +114,16 @@ fn load_bril_type(type_v: &JsonValue) -> Result<Type, BrilLoadError> {
         return Ok(Type::Unit);
     }
 
+    // pointer types are written as {"ptr": <pointee type>}
+    if type_v.is_object() {
+        let pointee = &type_v["ptr"];
+        if pointee.is_null() || type_v.len() != 1 {
+            return Err(BrilLoadError::InvalidTypeString);
+        }
+
+        return Ok(Type::Ptr(Box::new(load_bril_type(pointee)?)));
+    }
+
     if !type_v.is_string() {
         return Err(BrilLoadError::InvalidTypeString);
     }
@@ -139,13 +167,20 @@ fn load_bril_instr(instr_v: &JsonValue) -> Result<Rc<Instruction>, BrilLoadError
     let op_str = op.as_str().unwrap();
 
     let real_op: Result<OpCode, ()> = op_str.try_into();
-    if let Err(_) = real_op {
+    if real_op.is_err() {
         return Err(BrilLoadError::UnrecognizedInstr(op_str.to_string()));
     }
 
     let real_op = real_op.unwrap();
 
-    return if CONST_INSTS.contains(&real_op) {
+    // call is the only op that may or may not produce a value
+    if real_op == OpCode::Call {
+        if instr_v["dest"].is_null() {
+            load_bril_effect_instr(real_op, instr_v)
+        } else {
+            load_bril_value_instr(real_op, instr_v)
+        }
+    } else if CONST_INSTS.contains(&real_op) {
         load_bril_const_instr(real_op, instr_v)
     } else if EFFECT_INSTS.contains(&real_op) {
         load_bril_effect_instr(real_op, instr_v)
@@ -153,7 +188,7 @@ fn load_bril_instr(instr_v: &JsonValue) -> Result<Rc<Instruction>, BrilLoadError
         load_bril_value_instr(real_op, instr_v)
     } else {
         Err(BrilLoadError::UnrecognizedInstr(op_str.to_string()))
-    };
+    }
 }
 
 fn load_bril_const_instr(
@@ -170,7 +205,7 @@ fn load_bril_const_instr(
 
     let dest_str = dest.as_str().unwrap().to_string();
     let instr_type = load_bril_type(instr_type_str)?;
-    let loaded_value = load_bril_value(value, instr_type)?;
+    let loaded_value = load_bril_value(value, &instr_type)?;
 
     Ok(Instruction::new_const(
         op,
@@ -246,14 +281,14 @@ fn load_string_array(arr: &JsonValue) -> Result<Vec<String>, BrilLoadError> {
     Ok(loaded_strs)
 }
 
-fn load_bril_value(value_v: &JsonValue, expected_type: Type) -> Result<Value, BrilLoadError> {
-    if expected_type == Type::Int {
+fn load_bril_value(value_v: &JsonValue, expected_type: &Type) -> Result<Value, BrilLoadError> {
+    if *expected_type == Type::Int {
         if !value_v.is_number() {
             return Err(BrilLoadError::TypeMismatch);
         }
 
         return Ok(Value::Int(value_v.as_i32().unwrap()));
-    } else if expected_type == Type::Bool {
+    } else if *expected_type == Type::Bool {
         if !value_v.is_boolean() {
             return Err(BrilLoadError::TypeMismatch);
         }
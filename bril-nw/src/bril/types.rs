@@ -19,11 +19,13 @@ pub struct FunctionArg {
     pub arg_type: Type,
 }
 
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Type {
     Int,
     Bool,
     Unit,
+    // a pointer to memory holding values of the inner type
+    Ptr(Box<Type>),
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -38,12 +40,27 @@ pub enum OpCode {
     Const,
     Add,
     Mul,
+    Sub,
+    Div,
+    Equal,
     LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    Not,
+    And,
+    Or,
     Print,
     Jump,
     Branch,
     Ret,
     Phi,
+    Call,
+    Alloc,
+    Free,
+    Store,
+    Load,
+    PtrAdd,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -93,17 +110,42 @@ impl TryFrom<&str> for OpCode {
             "const" => Ok(OpCode::Const),
             "add" => Ok(OpCode::Add),
             "mul" => Ok(OpCode::Mul),
+            "sub" => Ok(OpCode::Sub),
+            "div" => Ok(OpCode::Div),
+            "eq" => Ok(OpCode::Equal),
             "lt" => Ok(OpCode::LessThan),
+            "gt" => Ok(OpCode::GreaterThan),
+            "le" => Ok(OpCode::LessThanOrEqual),
+            "ge" => Ok(OpCode::GreaterThanOrEqual),
+            "not" => Ok(OpCode::Not),
+            "and" => Ok(OpCode::And),
+            "or" => Ok(OpCode::Or),
             "jmp" => Ok(OpCode::Jump),
             "br" => Ok(OpCode::Branch),
             "ret" => Ok(OpCode::Ret),
             "print" => Ok(OpCode::Print),
             "phi" => Ok(OpCode::Phi),
+            "call" => Ok(OpCode::Call),
+            "alloc" => Ok(OpCode::Alloc),
+            "free" => Ok(OpCode::Free),
+            "store" => Ok(OpCode::Store),
+            "load" => Ok(OpCode::Load),
+            "ptradd" => Ok(OpCode::PtrAdd),
             _ => Err(()),
         }
     }
 }
 
+impl OpCode {
+    // whether swapping the two operands gives the same result
+    pub fn is_commutative(&self) -> bool {
+        matches!(
+            self,
+            OpCode::Add | OpCode::Mul | OpCode::Equal | OpCode::And | OpCode::Or
+        )
+    }
+}
+
 impl fmt::Display for OpCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -111,12 +153,27 @@ impl fmt::Display for OpCode {
             OpCode::Const => write!(f, "const"),
             OpCode::Add => write!(f, "add"),
             OpCode::Mul => write!(f, "mul"),
+            OpCode::Sub => write!(f, "sub"),
+            OpCode::Div => write!(f, "div"),
+            OpCode::Equal => write!(f, "eq"),
             OpCode::LessThan => write!(f, "lt"),
+            OpCode::GreaterThan => write!(f, "gt"),
+            OpCode::LessThanOrEqual => write!(f, "le"),
+            OpCode::GreaterThanOrEqual => write!(f, "ge"),
+            OpCode::Not => write!(f, "not"),
+            OpCode::And => write!(f, "and"),
+            OpCode::Or => write!(f, "or"),
             OpCode::Jump => write!(f, "jmp"),
             OpCode::Branch => write!(f, "br"),
             OpCode::Ret => write!(f, "ret"),
             OpCode::Print => write!(f, "print"),
             OpCode::Phi => write!(f, "phi"),
+            OpCode::Call => write!(f, "call"),
+            OpCode::Alloc => write!(f, "alloc"),
+            OpCode::Free => write!(f, "free"),
+            OpCode::Store => write!(f, "store"),
+            OpCode::Load => write!(f, "load"),
+            OpCode::PtrAdd => write!(f, "ptradd"),
         }
     }
 }
@@ -136,6 +193,7 @@ impl fmt::Display for Type {
             Type::Bool => write!(f, "bool"),
             Type::Int => write!(f, "int"),
             Type::Unit => write!(f, "()"),
+            Type::Ptr(inner) => write!(f, "ptr<{}>", inner),
         }
     }
 }
@@ -283,31 +341,19 @@ impl Instruction {
     }
 
     pub fn is_label(&self) -> bool {
-        match self {
-            Instruction::Label(_) => true,
-            _ => false,
-        }
+        matches!(self, Instruction::Label(_))
     }
 
     pub fn is_const(&self) -> bool {
-        match self {
-            Instruction::Const(_) => true,
-            _ => false,
-        }
+        matches!(self, Instruction::Const(_))
     }
 
     pub fn is_value(&self) -> bool {
-        match self {
-            Instruction::Value(_) => true,
-            _ => false,
-        }
+        matches!(self, Instruction::Value(_))
     }
 
     pub fn is_effect(&self) -> bool {
-        match self {
-            Instruction::Effect(_) => true,
-            _ => false,
-        }
+        matches!(self, Instruction::Effect(_))
     }
 
     pub fn is_jump(&self) -> bool {
@@ -316,7 +362,7 @@ impl Instruction {
         }
 
         let op = self.get_op_code().unwrap();
-        return op == OpCode::Branch || op == OpCode::Jump;
+        op == OpCode::Branch || op == OpCode::Jump
     }
 
     pub fn is_ret(&self) -> bool {
@@ -325,7 +371,35 @@ impl Instruction {
         }
 
         let op = self.get_op_code().unwrap();
-        return op == OpCode::Ret;
+        op == OpCode::Ret
+    }
+
+    pub fn is_call(&self) -> bool {
+        self.get_op_code() == Some(OpCode::Call)
+    }
+
+    pub fn is_phi(&self) -> bool {
+        self.get_op_code() == Some(OpCode::Phi)
+    }
+
+    // whether the instr does anything besides computing its dest, so it can't be removed or
+    // merged with an identical instr even if its dest is never used.
+    // calls are assumed to have side effects since the callee could do anything
+    pub fn has_side_effects(&self) -> bool {
+        match self.get_op_code() {
+            Some(op) => matches!(
+                op,
+                OpCode::Print
+                    | OpCode::Jump
+                    | OpCode::Branch
+                    | OpCode::Ret
+                    | OpCode::Call
+                    | OpCode::Alloc
+                    | OpCode::Free
+                    | OpCode::Store
+            ),
+            None => false,
+        }
     }
 
     pub fn get_op_code(&self) -> Option<OpCode> {
@@ -409,8 +483,8 @@ impl Instruction {
 
     pub fn get_type(&self) -> Option<Type> {
         match self {
-            Instruction::Const(c) => Some(c.instr_type),
-            Instruction::Value(v) => Some(v.instr_type),
+            Instruction::Const(c) => Some(c.instr_type.clone()),
+            Instruction::Value(v) => Some(v.instr_type.clone()),
             _ => None,
         }
     }
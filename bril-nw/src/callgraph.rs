@@ -0,0 +1,332 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    bril::types::Program,
+    cfg::{dot::escape, scc::find_strongly_connected_components},
+};
+
+pub const MAIN_FUNCTION_NAME: &str = "main";
+
+/*
+    Which functions call which, built from the funcs operand of every call instr in the program.
+
+    Functions are numbered in program order. Calls to functions that aren't defined in the program
+    are left out, since there's nothing to analyze on the other end.
+
+    Recursion shows up as cycles: a function calling itself, or a group of functions calling each
+    other (a strongly connected component with more than one function in it).
+*/
+#[derive(Debug)]
+pub struct CallGraph {
+    function_names: Vec<String>,
+    function_ids: HashMap<String, usize>,
+    callees: HashMap<usize, BTreeSet<usize>>,
+    callers: HashMap<usize, BTreeSet<usize>>,
+    // strongly connected components, callees first, and function id -> index of its component
+    components: Vec<Vec<usize>>,
+    component_ids: Vec<usize>,
+}
+
+impl CallGraph {
+    pub fn create_from_program(program: &Program) -> Self {
+        let function_names: Vec<String> =
+            program.functions.iter().map(|f| f.name.clone()).collect();
+        let function_ids: HashMap<String, usize> = function_names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect();
+
+        let mut callees: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+        let mut callers: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+        for (caller, function) in program.functions.iter().enumerate() {
+            for instr in function.instrs.iter().filter(|i| i.is_call()) {
+                for callee_name in instr.get_funcs_copy().unwrap() {
+                    let Some(callee) = function_ids.get(&callee_name) else {
+                        continue;
+                    };
+
+                    callees.entry(caller).or_default().insert(*callee);
+                    callers.entry(*callee).or_default().insert(caller);
+                }
+            }
+        }
+
+        let ids: Vec<usize> = (0..function_names.len()).collect();
+        let components = find_strongly_connected_components(&ids, |id| {
+            callees.get(&id).cloned().unwrap_or_default()
+        });
+
+        let mut component_ids = vec![0; function_names.len()];
+        for (component_id, component) in components.iter().enumerate() {
+            for id in component {
+                component_ids[*id] = component_id;
+            }
+        }
+
+        CallGraph {
+            function_names,
+            function_ids,
+            callees,
+            callers,
+            components,
+            component_ids,
+        }
+    }
+
+    // in program order
+    pub fn get_function_names(&self) -> &Vec<String> {
+        &self.function_names
+    }
+
+    pub fn get_callees(&self, function_name: &str) -> Vec<&str> {
+        self.get_neighbours(&self.callees, function_name)
+    }
+
+    pub fn get_callers(&self, function_name: &str) -> Vec<&str> {
+        self.get_neighbours(&self.callers, function_name)
+    }
+
+    // callers that aren't (indirectly) called back by the function, i.e. outside its recursive group
+    pub fn get_outside_callers(&self, function_name: &str) -> Vec<&str> {
+        let Some(id) = self.function_ids.get(function_name) else {
+            return vec![];
+        };
+
+        let component_id = self.component_ids[*id];
+        self.callers.get(id).map_or(vec![], |callers| {
+            callers
+                .iter()
+                .filter(|caller| self.component_ids[**caller] != component_id)
+                .map(|caller| self.function_names[*caller].as_str())
+                .collect()
+        })
+    }
+
+    fn get_neighbours(
+        &self,
+        edges: &HashMap<usize, BTreeSet<usize>>,
+        function_name: &str,
+    ) -> Vec<&str> {
+        self.function_ids
+            .get(function_name)
+            .and_then(|id| edges.get(id))
+            .map_or(vec![], |neighbours| {
+                neighbours
+                    .iter()
+                    .map(|id| self.function_names[*id].as_str())
+                    .collect()
+            })
+    }
+
+    // groups of functions that (indirectly) call each other. callees come before their callers,
+    // except within a group
+    pub fn find_strongly_connected_components(&self) -> Vec<Vec<&str>> {
+        self.components
+            .iter()
+            .map(|component| {
+                component
+                    .iter()
+                    .map(|id| self.function_names[*id].as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn is_recursive(&self, function_name: &str) -> bool {
+        let Some(id) = self.function_ids.get(function_name) else {
+            return false;
+        };
+
+        self.callees.get(id).is_some_and(|c| c.contains(id))
+            || self.components[self.component_ids[*id]].len() > 1
+    }
+
+    // every function, with callees before their callers where recursion allows it.
+    // the order to run interprocedural analyses in, so callee summaries are ready when needed
+    pub fn get_bottom_up_order(&self) -> Vec<&str> {
+        self.find_strongly_connected_components()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    // functions that can't be reached through any chain of calls starting at @main, in program
+    // order. without a @main, every function is unreachable
+    pub fn find_unreachable_functions(&self) -> Vec<&str> {
+        let mut reachable: HashSet<usize> = HashSet::new();
+        let mut open_set: Vec<usize> = self
+            .function_ids
+            .get(MAIN_FUNCTION_NAME)
+            .into_iter()
+            .copied()
+            .collect();
+
+        while let Some(id) = open_set.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+
+            open_set.extend(self.callees.get(&id).into_iter().flatten());
+        }
+
+        self.function_names
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !reachable.contains(id))
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    // recursive groups are drawn in boxes, unreachable functions with dashed outlines
+    pub fn to_dot(&self) -> String {
+        let unreachable: HashSet<&str> = self.find_unreachable_functions().into_iter().collect();
+
+        let mut result = String::new();
+        writeln!(result, "digraph \"call graph\" {{").unwrap();
+        writeln!(result, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for (id, name) in self.function_names.iter().enumerate() {
+            let style = if unreachable.contains(name.as_str()) {
+                ", style=dashed"
+            } else {
+                ""
+            };
+
+            writeln!(
+                result,
+                "    f{} [label=\"@{}\"{}];",
+                id,
+                escape(name),
+                style
+            )
+            .unwrap();
+        }
+
+        let recursive_components = self
+            .find_strongly_connected_components()
+            .into_iter()
+            .filter(|component| component.len() > 1);
+        for (i, component) in recursive_components.enumerate() {
+            writeln!(result, "    subgraph cluster_{} {{", i).unwrap();
+            writeln!(result, "        label=\"recursive\";").unwrap();
+            for name in component {
+                writeln!(result, "        f{};", self.function_ids[name]).unwrap();
+            }
+            writeln!(result, "    }}").unwrap();
+        }
+
+        for caller in 0..self.function_names.len() {
+            for callee in self.callees.get(&caller).into_iter().flatten() {
+                writeln!(result, "    f{} -> f{};", caller, callee).unwrap();
+            }
+        }
+
+        result.push_str("}\n");
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bril::loader::load_bril;
+
+    use super::CallGraph;
+
+    // main calls even and print_all. even and odd call each other, fact calls itself but is never
+    // called. print_all calls a function that doesn't exist
+    const PROGRAM: &str = r#"{
+        "functions": [
+            {
+                "name": "main",
+                "instrs": [
+                    { "op": "const", "dest": "n", "type": "int", "value": 4 },
+                    { "op": "call", "dest": "e", "type": "bool", "args": ["n"], "funcs": ["even"] },
+                    { "op": "call", "args": ["n"], "funcs": ["print_all"] }
+                ]
+            },
+            {
+                "name": "even",
+                "args": [{ "name": "n", "type": "int" }],
+                "type": "bool",
+                "instrs": [
+                    { "op": "call", "dest": "r", "type": "bool", "args": ["n"], "funcs": ["odd"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            },
+            {
+                "name": "odd",
+                "args": [{ "name": "n", "type": "int" }],
+                "type": "bool",
+                "instrs": [
+                    { "op": "call", "dest": "r", "type": "bool", "args": ["n"], "funcs": ["even"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            },
+            {
+                "name": "fact",
+                "args": [{ "name": "n", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "call", "dest": "r", "type": "int", "args": ["n"], "funcs": ["fact"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            },
+            {
+                "name": "print_all",
+                "args": [{ "name": "n", "type": "int" }],
+                "instrs": [
+                    { "op": "call", "args": ["n"], "funcs": ["missing"] },
+                    { "op": "print", "args": ["n"] }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_call_graph_queries() {
+        let program = load_bril(PROGRAM).unwrap();
+        let call_graph = CallGraph::create_from_program(&program);
+
+        assert_eq!(call_graph.get_callees("main"), vec!["even", "print_all"]);
+        assert_eq!(call_graph.get_callers("even"), vec!["main", "odd"]);
+        assert_eq!(call_graph.get_callees("print_all"), Vec::<&str>::new());
+        assert_eq!(call_graph.get_callers("nope"), Vec::<&str>::new());
+        assert_eq!(call_graph.get_outside_callers("even"), vec!["main"]);
+        assert_eq!(call_graph.get_outside_callers("fact"), Vec::<&str>::new());
+
+        assert!(call_graph.is_recursive("even"));
+        assert!(call_graph.is_recursive("fact"));
+        assert!(!call_graph.is_recursive("main"));
+
+        assert_eq!(
+            call_graph.find_strongly_connected_components(),
+            vec![
+                vec!["even", "odd"],
+                vec!["print_all"],
+                vec!["main"],
+                vec!["fact"]
+            ]
+        );
+        assert_eq!(
+            call_graph.get_bottom_up_order(),
+            vec!["even", "odd", "print_all", "main", "fact"]
+        );
+        assert_eq!(call_graph.find_unreachable_functions(), vec!["fact"]);
+    }
+
+    #[test]
+    fn test_call_graph_dot() {
+        let program = load_bril(PROGRAM).unwrap();
+        let dot = CallGraph::create_from_program(&program).to_dot();
+
+        assert!(dot.contains("    f0 [label=\"@main\"];"));
+        assert!(dot.contains("    f3 [label=\"@fact\", style=dashed];"));
+        assert!(dot.contains("        f1;\n        f2;\n"));
+        assert!(dot.contains("    f3 -> f3;"));
+        assert!(dot.contains("    f0 -> f4;"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{basicblock::BasicBlock, bril::types::Instruction, cfg::ControlFlowGraph};
+
+use super::{
+    available_expressions::{Expression, ExpressionNumbering},
+    bitset::{GenKillTarget, IndexedSet},
+    solve, DataFlowAnalysis, DataFlowResults, Direction,
+};
+
+pub type AnticipatedExprs = IndexedSet<Expression>;
+
+/*
+    An expression e is ANTICIPATED (or VERY BUSY) at a program point iff every path from that point
+    computes e before any of e's operands are redefined. Computing e at that point instead doesn't
+    add work to any path, and gives the same value the later computations would.
+
+    The backward counterpart of available expressions, with the same canonical expressions. Nothing
+    is anticipated after a ret.
+*/
+pub struct AnticipatedExpressions<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    numbering: ExpressionNumbering,
+}
+
+impl<'g, 'a> AnticipatedExpressions<'g, 'a> {
+    pub fn new(cfg: &'g ControlFlowGraph<'a>) -> Self {
+        AnticipatedExpressions {
+            cfg,
+            numbering: ExpressionNumbering::create(
+                cfg,
+                None,
+                Direction::Backward,
+                transfer_anticipated,
+            ),
+        }
+    }
+
+    pub fn analyze(&self) -> DataFlowResults<AnticipatedExprs> {
+        solve(self, self.cfg)
+    }
+}
+
+// in = USE U (out - KILL). the instruction reads its operands before writing its dest, so its own
+// expression is anticipated before it even if it overwrites an operand
+fn transfer_anticipated(
+    numbering: &ExpressionNumbering,
+    instr: &Instruction,
+    target: &mut impl GenKillTarget,
+) {
+    numbering.kill_uses(instr, target);
+
+    if let Some(expr) = numbering.get_expression(instr) {
+        target.gen(numbering.get_index(&expr));
+    }
+}
+
+impl DataFlowAnalysis for AnticipatedExpressions<'_, '_> {
+    type Fact = AnticipatedExprs;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_full(&self.numbering.expressions)
+    }
+
+    fn boundary(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_empty(&self.numbering.expressions)
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.intersect_with(other);
+    }
+
+    fn transfer_instr(
+        &self,
+        _block_id: usize,
+        _instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        transfer_anticipated(&self.numbering, instr, fact.get_mut_bits());
+    }
+
+    fn transfer_block(&self, block: &BasicBlock, fact: &mut Self::Fact) {
+        self.numbering.blocks[&block.get_id()].apply(fact.get_mut_bits());
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    basicblock::BasicBlock, bril::types::Instruction, cfg::ControlFlowGraph,
+    opt::local::LVNCanonicalExpression, purity::FunctionPurities,
+};
+
+use super::{
+    bitset::{BitSet, GenKill, GenKillTarget, IndexedSet, Universe},
+    solve, DataFlowAnalysis, DataFlowResults, Direction,
+};
+
+pub type Expression = LVNCanonicalExpression<String>;
+pub type AvailableExprs = IndexedSet<Expression>;
+
+/*
+    An expression e is AVAILABLE at a program point iff every path from the entry to that point
+    computes e, and none of e's operands are redefined after the last computation on each path.
+    Recomputing an available expression is redundant, as long as its value was kept around.
+
+    Expressions are in the same canonical form LVN uses, with variables as operands, so
+    `add a b` and `add b a` are the same expression. Only pure computations are expressions:
+    calls, ids and phis are left out.
+
+    This is a "must" analysis: facts are intersected where paths meet, so every block starts out
+    with every expression in the function available.
+*/
+pub struct AvailableExpressions<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    numbering: ExpressionNumbering,
+}
+
+// every expression computed in the function, numbered
+pub(crate) struct ExpressionNumbering {
+    purity: Option<FunctionPurities>,
+    pub(crate) expressions: Rc<Universe<Expression>>,
+    // var -> expressions with var as an operand
+    pub(crate) uses: HashMap<String, BitSet>,
+    // block id -> transfer over the whole block
+    pub(crate) blocks: HashMap<usize, GenKill>,
+}
+
+impl ExpressionNumbering {
+    // transfer_instr gives the transfer of a single instruction, for combining into block transfers
+    pub(crate) fn create(
+        cfg: &ControlFlowGraph,
+        purity: Option<&FunctionPurities>,
+        direction: Direction,
+        transfer_instr: impl Fn(&ExpressionNumbering, &Instruction, &mut GenKill),
+    ) -> Self {
+        let function = cfg.get_function();
+        let expressions: Rc<Universe<Expression>> = Rc::new(
+            function
+                .get_blocks()
+                .iter()
+                .flat_map(|b| b.instrs.iter())
+                .filter_map(|instr| LVNCanonicalExpression::from_instr_with_purity(instr, purity))
+                .collect(),
+        );
+
+        let mut uses: HashMap<String, BitSet> = HashMap::new();
+        for (idx, expr) in expressions.iter() {
+            for arg in expr.get_args() {
+                uses.entry(arg.clone())
+                    .or_insert_with(|| BitSet::new_empty(expressions.len()))
+                    .insert(idx);
+            }
+        }
+
+        let mut numbering = ExpressionNumbering {
+            purity: purity.cloned(),
+            expressions,
+            uses,
+            blocks: HashMap::new(),
+        };
+
+        for block in function.get_blocks() {
+            let transfer = GenKill::create_for_block(
+                numbering.expressions.len(),
+                block,
+                direction,
+                |_, instr, target| transfer_instr(&numbering, instr, target),
+            );
+            numbering.blocks.insert(block.get_id(), transfer);
+        }
+
+        numbering
+    }
+
+    // kills every expression using the dest of instr
+    pub(crate) fn kill_uses(&self, instr: &Instruction, target: &mut impl GenKillTarget) {
+        if let Some(uses) = instr.get_dest().and_then(|dest| self.uses.get(dest)) {
+            target.kill(uses);
+        }
+    }
+
+    // the expression instr computes, if any
+    pub(crate) fn get_expression(&self, instr: &Instruction) -> Option<Expression> {
+        LVNCanonicalExpression::from_instr_with_purity(instr, self.purity.as_ref())
+    }
+
+    pub(crate) fn get_index(&self, expr: &Expression) -> usize {
+        self.expressions.get_index(expr).unwrap()
+    }
+}
+
+impl<'g, 'a> AvailableExpressions<'g, 'a> {
+    pub fn new(cfg: &'g ControlFlowGraph<'a>) -> Self {
+        Self::create(cfg, None)
+    }
+
+    // calls to pure functions are expressions too
+    pub fn with_purity(cfg: &'g ControlFlowGraph<'a>, purity: &FunctionPurities) -> Self {
+        Self::create(cfg, Some(purity))
+    }
+
+    fn create(cfg: &'g ControlFlowGraph<'a>, purity: Option<&FunctionPurities>) -> Self {
+        AvailableExpressions {
+            cfg,
+            numbering: ExpressionNumbering::create(
+                cfg,
+                purity,
+                Direction::Forward,
+                transfer_available,
+            ),
+        }
+    }
+
+    pub fn analyze(&self) -> DataFlowResults<AvailableExprs> {
+        solve(self, self.cfg)
+    }
+}
+
+// out = GEN U (in - KILL). an instruction kills every expression using its dest, and generates its
+// own expression unless it overwrote one of its operands
+fn transfer_available(
+    numbering: &ExpressionNumbering,
+    instr: &Instruction,
+    target: &mut impl GenKillTarget,
+) {
+    numbering.kill_uses(instr, target);
+
+    if let (Some(dest), Some(expr)) = (instr.get_dest(), numbering.get_expression(instr)) {
+        if !expr.get_args().iter().any(|arg| arg == dest) {
+            target.gen(numbering.get_index(&expr));
+        }
+    }
+}
+
+impl DataFlowAnalysis for AvailableExpressions<'_, '_> {
+    type Fact = AvailableExprs;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_full(&self.numbering.expressions)
+    }
+
+    // nothing has been computed before the function runs
+    fn boundary(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_empty(&self.numbering.expressions)
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.intersect_with(other);
+    }
+
+    fn transfer_instr(
+        &self,
+        _block_id: usize,
+        _instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        transfer_available(&self.numbering, instr, fact.get_mut_bits());
+    }
+
+    fn transfer_block(&self, block: &BasicBlock, fact: &mut Self::Fact) {
+        self.numbering.blocks[&block.get_id()].apply(fact.get_mut_bits());
+    }
+}
@@ -0,0 +1,471 @@
+use std::{borrow::Borrow, collections::HashMap, fmt, hash::Hash, iter::FromIterator, rc::Rc};
+
+use crate::{basicblock::BasicBlock, bril::types::Instruction};
+
+use super::Direction;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+// a fixed size set of small integers, one bit each. operations on two sets expect them to be
+// the same size
+#[derive(Clone, Eq, Hash, PartialEq)]
+pub struct BitSet {
+    size: usize,
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new_empty(size: usize) -> Self {
+        BitSet {
+            size,
+            words: vec![0; size.div_ceil(WORD_BITS)],
+        }
+    }
+
+    pub fn new_full(size: usize) -> Self {
+        let mut result = BitSet {
+            size,
+            words: vec![u64::MAX; size.div_ceil(WORD_BITS)],
+        };
+
+        // bits past the end stay clear, so sets with the same members are always equal
+        if !size.is_multiple_of(WORD_BITS) {
+            *result.words.last_mut().unwrap() = (1 << (size % WORD_BITS)) - 1;
+        }
+
+        result
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        idx < self.size && self.words[idx / WORD_BITS] & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    // these all return whether the set changed
+    pub fn insert(&mut self, idx: usize) -> bool {
+        assert!(
+            idx < self.size,
+            "{} is out of range for {} bits",
+            idx,
+            self.size
+        );
+
+        let word = &mut self.words[idx / WORD_BITS];
+        let before = *word;
+        *word |= 1 << (idx % WORD_BITS);
+        *word != before
+    }
+
+    pub fn remove(&mut self, idx: usize) -> bool {
+        if idx >= self.size {
+            return false;
+        }
+
+        let word = &mut self.words[idx / WORD_BITS];
+        let before = *word;
+        *word &= !(1 << (idx % WORD_BITS));
+        *word != before
+    }
+
+    pub fn union_with(&mut self, other: &BitSet) -> bool {
+        self.combine_with(other, |a, b| a | b)
+    }
+
+    pub fn intersect_with(&mut self, other: &BitSet) -> bool {
+        self.combine_with(other, |a, b| a & b)
+    }
+
+    pub fn subtract(&mut self, other: &BitSet) -> bool {
+        self.combine_with(other, |a, b| a & !b)
+    }
+
+    fn combine_with(&mut self, other: &BitSet, op: impl Fn(u64, u64) -> u64) -> bool {
+        debug_assert_eq!(self.size, other.size);
+
+        let mut changed = false;
+        for (word, other_word) in self.words.iter_mut().zip(other.words.iter()) {
+            let before = *word;
+            *word = op(*word, *other_word);
+            changed |= *word != before;
+        }
+
+        changed
+    }
+
+    // the members, smallest first
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_idx, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(word_idx * WORD_BITS + bit)
+            })
+        })
+    }
+}
+
+impl fmt::Debug for BitSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/*
+    Numbers a set of items 0..n in sorted order, so sets of them can be stored as bits.
+    Numbering in sorted order means IndexedSets iterate in the same order a BTreeSet would.
+*/
+#[derive(Debug)]
+pub struct Universe<T> {
+    items: Vec<T>,
+    indices: HashMap<T, usize>,
+}
+
+impl<T: Clone + Eq + Hash + Ord> Universe<T> {
+    pub fn new(items: impl IntoIterator<Item = T>) -> Self {
+        let mut items: Vec<T> = items.into_iter().collect();
+        items.sort();
+        items.dedup();
+
+        let indices = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| (item.clone(), idx))
+            .collect();
+
+        Universe { items, indices }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get_index<Q>(&self, item: &Q) -> Option<usize>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.indices.get(item).copied()
+    }
+
+    pub fn get_item(&self, idx: usize) -> &T {
+        &self.items[idx]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.items.iter().enumerate()
+    }
+
+    // the indices of all items matching pred
+    pub fn find_all(&self, pred: impl Fn(&T) -> bool) -> BitSet {
+        let mut result = BitSet::new_empty(self.len());
+        for (idx, item) in self.iter() {
+            if pred(item) {
+                result.insert(idx);
+            }
+        }
+
+        result
+    }
+}
+
+impl<T> FromIterator<T> for Universe<T>
+where
+    T: Clone + Eq + Hash + Ord,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Universe::new(iter)
+    }
+}
+
+/*
+    A subset of a Universe, e.g. a dataflow fact. Reads like a BTreeSet of the items, but unions,
+    intersections and comparisons work on whole words of bits at a time.
+
+    Sets of different universes can't be combined, and compare as different.
+*/
+#[derive(Clone)]
+pub struct IndexedSet<T> {
+    universe: Rc<Universe<T>>,
+    bits: BitSet,
+}
+
+impl<T: Clone + Eq + Hash + Ord> IndexedSet<T> {
+    pub fn new_empty(universe: &Rc<Universe<T>>) -> Self {
+        IndexedSet {
+            universe: universe.clone(),
+            bits: BitSet::new_empty(universe.len()),
+        }
+    }
+
+    pub fn new_full(universe: &Rc<Universe<T>>) -> Self {
+        IndexedSet {
+            universe: universe.clone(),
+            bits: BitSet::new_full(universe.len()),
+        }
+    }
+
+    pub fn get_universe(&self) -> &Rc<Universe<T>> {
+        &self.universe
+    }
+
+    pub fn get_bits(&self) -> &BitSet {
+        &self.bits
+    }
+
+    pub fn get_mut_bits(&mut self) -> &mut BitSet {
+        &mut self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    pub fn contains<Q>(&self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.universe
+            .get_index(item)
+            .is_some_and(|idx| self.bits.contains(idx))
+    }
+
+    // items outside the universe can't be added
+    pub fn insert<Q>(&mut self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let idx = self
+            .universe
+            .get_index(item)
+            .expect("item is in the universe");
+        self.bits.insert(idx)
+    }
+
+    pub fn remove<Q>(&mut self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        match self.universe.get_index(item) {
+            Some(idx) => self.bits.remove(idx),
+            None => false,
+        }
+    }
+
+    pub fn union_with(&mut self, other: &IndexedSet<T>) -> bool {
+        debug_assert!(Rc::ptr_eq(&self.universe, &other.universe));
+        self.bits.union_with(&other.bits)
+    }
+
+    pub fn intersect_with(&mut self, other: &IndexedSet<T>) -> bool {
+        debug_assert!(Rc::ptr_eq(&self.universe, &other.universe));
+        self.bits.intersect_with(&other.bits)
+    }
+
+    pub fn iter(&self) -> IndexedSetIter<'_, T> {
+        IndexedSetIter {
+            universe: &self.universe,
+            indices: Box::new(self.bits.iter()),
+        }
+    }
+}
+
+impl<T> PartialEq for IndexedSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.universe, &other.universe) && self.bits == other.bits
+    }
+}
+
+impl<T: fmt::Debug + Clone + Eq + Hash + Ord> fmt::Debug for IndexedSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+pub struct IndexedSetIter<'a, T> {
+    universe: &'a Universe<T>,
+    indices: Box<dyn Iterator<Item = usize> + 'a>,
+}
+
+impl<'a, T> Iterator for IndexedSetIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.indices.next().map(|idx| &self.universe.items[idx])
+    }
+}
+
+impl<'a, T: Clone + Eq + Hash + Ord> IntoIterator for &'a IndexedSet<T> {
+    type Item = &'a T;
+    type IntoIter = IndexedSetIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// what gen/kill transfers act on: a fact, or a GenKill combining several transfers
+pub trait GenKillTarget {
+    fn kill(&mut self, items: &BitSet);
+
+    fn gen(&mut self, idx: usize);
+}
+
+impl GenKillTarget for BitSet {
+    fn kill(&mut self, items: &BitSet) {
+        self.subtract(items);
+    }
+
+    fn gen(&mut self, idx: usize) {
+        self.insert(idx);
+    }
+}
+
+/*
+    The combined effect of a sequence of gen/kill transfers: out = GEN U (in - KILL).
+
+    Built up one transfer at a time, in the order the analysis applies them (so in reverse program
+    order for backward problems). Killing removes the killed items from GEN, generating adds to
+    GEN, so a later transfer always wins over an earlier one.
+*/
+#[derive(Clone, Debug)]
+pub struct GenKill {
+    gen: BitSet,
+    kill: BitSet,
+}
+
+impl GenKill {
+    pub fn new(size: usize) -> Self {
+        GenKill {
+            gen: BitSet::new_empty(size),
+            kill: BitSet::new_empty(size),
+        }
+    }
+
+    // combines the transfers of every instruction in the block, given as
+    // transfer_instr(instr_idx, instr, target)
+    pub fn create_for_block(
+        size: usize,
+        block: &BasicBlock,
+        direction: Direction,
+        transfer_instr: impl Fn(usize, &Instruction, &mut GenKill),
+    ) -> Self {
+        let mut result = GenKill::new(size);
+        let instrs = block.instrs.iter().enumerate();
+        match direction {
+            Direction::Forward => {
+                for (idx, instr) in instrs {
+                    transfer_instr(idx, instr, &mut result);
+                }
+            }
+            Direction::Backward => {
+                for (idx, instr) in instrs.rev() {
+                    transfer_instr(idx, instr, &mut result);
+                }
+            }
+        }
+
+        result
+    }
+
+    // whether fact changed. an item that is killed and generated again doesn't count as a change
+    pub fn apply(&self, fact: &mut BitSet) -> bool {
+        debug_assert_eq!(fact.size, self.gen.size);
+
+        let mut changed = false;
+        let words = self.gen.words.iter().zip(self.kill.words.iter());
+        for (word, (gen, kill)) in fact.words.iter_mut().zip(words) {
+            let before = *word;
+            *word = (*word & !kill) | gen;
+            changed |= *word != before;
+        }
+
+        changed
+    }
+}
+
+impl GenKillTarget for GenKill {
+    fn kill(&mut self, items: &BitSet) {
+        self.kill.union_with(items);
+        self.gen.subtract(items);
+    }
+
+    fn gen(&mut self, idx: usize) {
+        self.gen.insert(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{BitSet, GenKill, GenKillTarget, IndexedSet, Universe};
+
+    #[test]
+    fn test_bitset() {
+        let mut a = BitSet::new_empty(130);
+        assert!(a.insert(0));
+        assert!(a.insert(64));
+        assert!(a.insert(129));
+        assert!(!a.insert(64));
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![0, 64, 129]);
+
+        let mut full = BitSet::new_full(130);
+        assert_eq!(full.len(), 130);
+        assert!(!full.union_with(&a));
+        assert!(full.subtract(&a));
+        assert_eq!(full.len(), 127);
+        assert!(!full.contains(129));
+
+        assert!(a.intersect_with(&full) && a.is_empty());
+        assert_eq!(a, BitSet::new_empty(130));
+    }
+
+    #[test]
+    fn test_indexed_set_gen_kill() {
+        let universe: Rc<Universe<String>> =
+            Rc::new(["b", "a", "c", "a"].iter().map(|s| s.to_string()).collect());
+        assert_eq!(universe.len(), 3);
+
+        let mut set = IndexedSet::new_empty(&universe);
+        set.insert("c");
+        set.insert("a");
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec!["a", "c"]);
+        assert!(set.contains("a") && !set.contains("b") && !set.contains("d"));
+
+        // kill a and c, then generate c again
+        let mut transfer = GenKill::new(universe.len());
+        transfer.kill(&universe.find_all(|v| v != "b"));
+        transfer.gen(universe.get_index("c").unwrap());
+
+        assert!(transfer.apply(set.get_mut_bits()));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec!["c"]);
+        assert!(!transfer.apply(set.get_mut_bits()));
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    bril::types::{Instruction, OpCode, Value},
+    cfg::ControlFlowGraph,
+};
+
+use super::{solve, DataFlowAnalysis, DataFlowResults, Direction};
+
+/*
+    The flat constant lattice for one variable:
+
+          Undefined
+       /   /  |  \   \
+     ... -1   0   1  ...   (one Constant per value)
+       \   \  |  /   /
+         NotConstant
+
+    Undefined means no definition of the variable has been seen yet, so it can still become any
+    constant. Merging two different constants, or a constant with anything unknown (e.g. a function
+    arg or the result of a call), gives NotConstant.
+*/
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConstantValue {
+    Undefined,
+    Constant(Value),
+    NotConstant,
+}
+
+impl ConstantValue {
+    pub fn merge(self, other: ConstantValue) -> ConstantValue {
+        match (self, other) {
+            (ConstantValue::Undefined, v) | (v, ConstantValue::Undefined) => v,
+            (ConstantValue::Constant(a), ConstantValue::Constant(b)) if a == b => self,
+            _ => ConstantValue::NotConstant,
+        }
+    }
+
+    pub fn get_constant(&self) -> Option<Value> {
+        match self {
+            ConstantValue::Constant(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+// variable -> its value. variables that aren't in here are Undefined
+pub type ConstantFacts = BTreeMap<String, ConstantValue>;
+
+pub fn get_constant_value(facts: &ConstantFacts, var: &str) -> ConstantValue {
+    facts.get(var).copied().unwrap_or(ConstantValue::Undefined)
+}
+
+// the result of op on constant args, if op can be evaluated at compile time.
+// ints wrap around on overflow. dividing by zero can't be folded
+pub fn fold(op: OpCode, args: &[Value]) -> Option<Value> {
+    match (op, args) {
+        (OpCode::Id, [v]) => Some(*v),
+        (OpCode::Not, [Value::Bool(a)]) => Some(Value::Bool(!a)),
+        (OpCode::And, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(*a && *b)),
+        (OpCode::Or, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(*a || *b)),
+        (OpCode::Equal, [Value::Int(a), Value::Int(b)]) => Some(Value::Bool(a == b)),
+        (OpCode::Equal, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(a == b)),
+        (_, [Value::Int(a), Value::Int(b)]) => {
+            let (a, b) = (*a, *b);
+            match op {
+                OpCode::Add => Some(Value::Int(a.wrapping_add(b))),
+                OpCode::Sub => Some(Value::Int(a.wrapping_sub(b))),
+                OpCode::Mul => Some(Value::Int(a.wrapping_mul(b))),
+                OpCode::Div => a.checked_div(b).map(Value::Int),
+                OpCode::LessThan => Some(Value::Bool(a < b)),
+                OpCode::GreaterThan => Some(Value::Bool(a > b)),
+                OpCode::LessThanOrEqual => Some(Value::Bool(a <= b)),
+                OpCode::GreaterThanOrEqual => Some(Value::Bool(a >= b)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// the value instr writes to its dest, given the values of its args
+pub fn evaluate(instr: &Instruction, facts: &ConstantFacts) -> ConstantValue {
+    if let Some(value) = instr.get_const_value() {
+        return ConstantValue::Constant(value);
+    }
+
+    let op = instr.get_op_code().unwrap();
+    let args: Vec<ConstantValue> = instr
+        .get_args_copy()
+        .iter()
+        .map(|arg| get_constant_value(facts, arg))
+        .collect();
+
+    match op {
+        // phi operands were all merged into the facts coming into the block
+        OpCode::Phi => args
+            .into_iter()
+            .fold(ConstantValue::Undefined, ConstantValue::merge),
+        OpCode::Call => ConstantValue::NotConstant,
+        _ => {
+            if args.contains(&ConstantValue::NotConstant) {
+                return ConstantValue::NotConstant;
+            }
+
+            let values: Option<Vec<Value>> = args.iter().map(|a| a.get_constant()).collect();
+            match values {
+                Some(values) => {
+                    fold(op, &values).map_or(ConstantValue::NotConstant, ConstantValue::Constant)
+                }
+                None => ConstantValue::Undefined,
+            }
+        }
+    }
+}
+
+/*
+    Forward constant propagation over the cfg: which variables hold the same constant on every
+    path to a program point. Works on any FunctionBlocks, in ssa form or not.
+
+    On its own nothing is known about the function's args or what calls return. Interprocedural
+    analyses can supply both, e.g. args that are the same constant at every call site.
+*/
+#[derive(Default)]
+pub struct ConstantPropagation {
+    // arg name -> its value on entry. args that aren't in here are NotConstant
+    arg_values: HashMap<String, ConstantValue>,
+    // function name -> what every call to it returns. calls to others are NotConstant
+    return_values: HashMap<String, ConstantValue>,
+}
+
+impl ConstantPropagation {
+    pub fn new() -> Self {
+        ConstantPropagation {
+            arg_values: HashMap::new(),
+            return_values: HashMap::new(),
+        }
+    }
+
+    pub fn with_call_values(
+        arg_values: HashMap<String, ConstantValue>,
+        return_values: HashMap<String, ConstantValue>,
+    ) -> Self {
+        ConstantPropagation {
+            arg_values,
+            return_values,
+        }
+    }
+
+    pub fn analyze(&self, cfg: &ControlFlowGraph) -> DataFlowResults<ConstantFacts> {
+        solve(self, cfg)
+    }
+
+    fn get_return_value(&self, call: &Instruction) -> ConstantValue {
+        call.get_funcs_copy()
+            .and_then(|funcs| {
+                funcs
+                    .first()
+                    .and_then(|f| self.return_values.get(f))
+                    .copied()
+            })
+            .unwrap_or(ConstantValue::NotConstant)
+    }
+}
+
+impl DataFlowAnalysis for ConstantPropagation {
+    type Fact = ConstantFacts;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        BTreeMap::new()
+    }
+
+    fn boundary(&self, cfg: &ControlFlowGraph) -> Self::Fact {
+        cfg.get_function()
+            .get_args()
+            .iter()
+            .map(|a| {
+                let value = self.arg_values.get(&a.name).copied();
+                (a.name.clone(), value.unwrap_or(ConstantValue::NotConstant))
+            })
+            .collect()
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        for (var, value) in other {
+            let merged = get_constant_value(fact, var).merge(*value);
+            fact.insert(var.clone(), merged);
+        }
+    }
+
+    fn transfer_instr(
+        &self,
+        _block_id: usize,
+        _instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        if let Some(dest) = instr.get_dest() {
+            let value = if instr.is_call() {
+                self.get_return_value(instr)
+            } else {
+                evaluate(instr, fact)
+            };
+            fact.insert(dest.to_string(), value);
+        }
+    }
+}
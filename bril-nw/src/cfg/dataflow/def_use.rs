@@ -0,0 +1,150 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::cfg::ControlFlowGraph;
+
+use super::{
+    reaching_definitions::{Definition, InstrLocation, ReachingDefinitions, ReachingFacts},
+    DataFlowResults,
+};
+
+/*
+    Use-def chains link every use of a variable to the definitions that reach it, and def-use
+    chains link every definition to the uses it reaches. Both are built from reaching definitions.
+
+    A phi operand is only used when coming from the block its label names, so only the definitions
+    leaving that block reach it. Uses that no definition reaches (e.g. of an undefined variable)
+    have an empty use-def chain.
+*/
+#[derive(Debug, Default)]
+pub struct DefUseChains {
+    // (instruction, used variable) -> definitions reaching it
+    use_def: HashMap<(InstrLocation, String), BTreeSet<Definition>>,
+    // definition -> instructions using it
+    def_use: HashMap<Definition, BTreeSet<InstrLocation>>,
+}
+
+impl DefUseChains {
+    pub fn create(cfg: &ControlFlowGraph) -> Self {
+        let analysis = ReachingDefinitions::new(cfg);
+        let results = analysis.analyze();
+
+        Self::create_from_results(cfg, &analysis, &results)
+    }
+
+    pub fn create_from_results(
+        cfg: &ControlFlowGraph,
+        analysis: &ReachingDefinitions,
+        results: &DataFlowResults<ReachingFacts>,
+    ) -> Self {
+        let mut chains = DefUseChains::default();
+        let function = cfg.get_function();
+
+        for block_id in cfg.get_all_block_ids() {
+            let block = function.get_block_by_id(*block_id).unwrap();
+            let facts = results.get_instr_facts(analysis, cfg, *block_id).unwrap();
+
+            for (instr_idx, instr) in block.instrs.iter().enumerate() {
+                let location = InstrLocation {
+                    block_id: *block_id,
+                    instr_idx,
+                };
+                let args = instr.get_args_copy();
+
+                if instr.is_phi() {
+                    let labels = instr.get_labels_copy().unwrap_or_default();
+                    for (var, label) in args.iter().zip(labels.iter()) {
+                        let reaching = function
+                            .get_block_by_name(label)
+                            .and_then(|pred| results.get_out(pred.get_id()))
+                            .unwrap_or(&facts[instr_idx]);
+                        chains.add_use(location, var, reaching);
+                    }
+                } else {
+                    for var in &args {
+                        chains.add_use(location, var, &facts[instr_idx]);
+                    }
+                }
+            }
+        }
+
+        chains
+    }
+
+    fn add_use(&mut self, location: InstrLocation, var: &str, reaching: &ReachingFacts) {
+        let definitions = self.use_def.entry((location, var.to_string())).or_default();
+
+        for definition in reaching.iter().filter(|d| d.var == var) {
+            definitions.insert(definition.clone());
+            self.def_use
+                .entry(definition.clone())
+                .or_default()
+                .insert(location);
+        }
+    }
+
+    // definitions of var that reach the instruction at location
+    pub fn get_definitions(&self, location: InstrLocation, var: &str) -> BTreeSet<Definition> {
+        self.use_def
+            .get(&(location, var.to_string()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // instructions that use definition
+    pub fn get_uses(&self, definition: &Definition) -> BTreeSet<InstrLocation> {
+        self.def_use.get(definition).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::cfg::{
+        dataflow::reaching_definitions::{
+            tests::{def, get_test_function},
+            InstrLocation,
+        },
+        ControlFlowGraph,
+    };
+
+    use super::DefUseChains;
+
+    fn at(block_id: usize, instr_idx: usize) -> InstrLocation {
+        InstrLocation {
+            block_id,
+            instr_idx,
+        }
+    }
+
+    #[test]
+    fn test_def_use_chains() {
+        let mut blocks = get_test_function();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+        let chains = DefUseChains::create(&cfg);
+
+        // the first add in the body sees the entry's x and the x from the last trip around
+        assert_eq!(
+            chains.get_definitions(at(2, 1), "x"),
+            BTreeSet::from([def("x", 0, 1), def("x", 2, 2)])
+        );
+        assert_eq!(
+            chains.get_definitions(at(2, 2), "x"),
+            BTreeSet::from([def("x", 2, 1)])
+        );
+        assert_eq!(
+            chains.get_definitions(at(3, 1), "x"),
+            BTreeSet::from([def("x", 0, 1), def("x", 2, 2)])
+        );
+
+        assert_eq!(
+            chains.get_uses(&def("x", 0, 1)),
+            BTreeSet::from([at(2, 1), at(3, 1)])
+        );
+        assert_eq!(chains.get_uses(&def("x", 2, 1)), BTreeSet::from([at(2, 2)]));
+        assert_eq!(
+            chains.get_uses(&def("x", 2, 2)),
+            BTreeSet::from([at(2, 1), at(3, 1)])
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    basicblock::BasicBlock, bril::types::Instruction, cfg::ControlFlowGraph,
+    ssa::UNDEFINED_VAR_NAME,
+};
+
+use super::{
+    bitset::{GenKill, GenKillTarget, IndexedSet, Universe},
+    live_variables::create_variable_universe,
+    solve, DataFlowAnalysis, DataFlowResults, Direction,
+};
+
+pub type DefinedVars = IndexedSet<String>;
+
+/*
+    A variable v is DEFINITELY ASSIGNED at a program point iff every path from the entry to that
+    point assigns v (function args are assigned before the entry runs).
+
+    A "must" analysis: facts are intersected where paths meet, so every block starts out with every
+    variable in the function assigned. Blocks that can't be reached keep it that way, so nothing
+    is reported for them.
+*/
+pub struct DefinedVariables<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    numbering: VariableNumbering,
+}
+
+struct VariableNumbering {
+    vars: Rc<Universe<String>>,
+    // block id -> transfer over the whole block
+    blocks: HashMap<usize, GenKill>,
+}
+
+// a use of var that some path from the entry reaches without assigning var
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndefinedUse {
+    pub block_id: usize,
+    pub block_name: String,
+    pub instr_idx: usize,
+    pub instr: Rc<Instruction>,
+    pub var: String,
+}
+
+impl<'g, 'a> DefinedVariables<'g, 'a> {
+    pub fn new(cfg: &'g ControlFlowGraph<'a>) -> Self {
+        DefinedVariables {
+            cfg,
+            numbering: VariableNumbering::create(cfg),
+        }
+    }
+
+    pub fn analyze(&self) -> DataFlowResults<DefinedVars> {
+        solve(self, self.cfg)
+    }
+
+    // every use of a variable that isn't definitely assigned, in program order.
+    // a phi operand only needs to be assigned at the end of the block its label names. operands the
+    // ssa conversion already marked as undefined aren't reported again
+    pub fn find_undefined_uses(&self) -> Vec<UndefinedUse> {
+        let cfg = self.cfg;
+        let results = self.analyze();
+        let function = cfg.get_function();
+
+        let mut result = Vec::new();
+        for block in function.get_blocks() {
+            let facts = results.get_instr_facts(self, cfg, block.get_id()).unwrap();
+
+            for (instr_idx, instr) in block.instrs.iter().enumerate() {
+                let args = instr.get_args_copy();
+                let labels = instr.get_labels_copy().unwrap_or_default();
+
+                for (arg_idx, var) in args.iter().enumerate() {
+                    let defined = if instr.is_phi() {
+                        var == UNDEFINED_VAR_NAME
+                            || labels
+                                .get(arg_idx)
+                                .and_then(|label| function.get_block_by_name(label))
+                                .and_then(|pred| results.get_out(pred.get_id()))
+                                .is_some_and(|out| out.contains(var))
+                    } else {
+                        facts[instr_idx].contains(var)
+                    };
+
+                    if !defined {
+                        result.push(UndefinedUse {
+                            block_id: block.get_id(),
+                            block_name: block.get_name(),
+                            instr_idx,
+                            instr: instr.clone(),
+                            var: var.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl VariableNumbering {
+    fn create(cfg: &ControlFlowGraph) -> Self {
+        let mut numbering = VariableNumbering {
+            vars: Rc::new(create_variable_universe(cfg)),
+            blocks: HashMap::new(),
+        };
+
+        for block in cfg.get_function().get_blocks() {
+            let transfer = GenKill::create_for_block(
+                numbering.vars.len(),
+                block,
+                Direction::Forward,
+                |_, instr, target| numbering.transfer_instr(instr, target),
+            );
+            numbering.blocks.insert(block.get_id(), transfer);
+        }
+
+        numbering
+    }
+
+    // assigning a var never unassigns anything
+    fn transfer_instr(&self, instr: &Instruction, target: &mut impl GenKillTarget) {
+        if let Some(dest) = instr.get_dest() {
+            target.gen(self.vars.get_index(dest).unwrap());
+        }
+    }
+}
+
+impl fmt::Display for UndefinedUse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} may be used before it is defined in .{}: {}",
+            self.var,
+            self.block_name,
+            self.instr.to_string().trim()
+        )
+    }
+}
+
+impl DataFlowAnalysis for DefinedVariables<'_, '_> {
+    type Fact = DefinedVars;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_full(&self.numbering.vars)
+    }
+
+    fn boundary(&self, cfg: &ControlFlowGraph) -> Self::Fact {
+        let mut result = IndexedSet::new_empty(&self.numbering.vars);
+        for arg in cfg.get_function().get_args() {
+            result.insert(&arg.name);
+        }
+
+        result
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.intersect_with(other);
+    }
+
+    fn transfer_instr(
+        &self,
+        _block_id: usize,
+        _instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        self.numbering.transfer_instr(instr, fact.get_mut_bits());
+    }
+
+    fn transfer_block(&self, block: &BasicBlock, fact: &mut Self::Fact) {
+        self.numbering.blocks[&block.get_id()].apply(fact.get_mut_bits());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::DefinedVariables;
+
+    #[test]
+    fn test_undefined_uses() {
+        // x is only assigned on one side of the branch, y on both
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["left".to_string(), "right".to_string()],
+            ),
+            Instruction::new_label("left"),
+            Instruction::new_const(OpCode::Const, "x".to_string(), Type::Int, Value::Int(1)),
+            Instruction::new_const(OpCode::Const, "y".to_string(), Type::Int, Value::Int(1)),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["join".to_string()]),
+            Instruction::new_label("right"),
+            Instruction::new_const(OpCode::Const, "y".to_string(), Type::Int, Value::Int(2)),
+            Instruction::new_label("join"),
+            Instruction::new_effect(
+                OpCode::Print,
+                vec!["x".to_string(), "y".to_string(), "cond".to_string()],
+                vec![],
+                vec![],
+            ),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("cond".to_string(), Type::Bool)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let undefined_uses = DefinedVariables::new(&cfg).find_undefined_uses();
+        assert_eq!(undefined_uses.len(), 1);
+        assert_eq!(undefined_uses[0].block_id, 3);
+        assert_eq!(undefined_uses[0].instr_idx, 1);
+        assert_eq!(undefined_uses[0].var, "x");
+        assert_eq!(
+            undefined_uses[0].to_string(),
+            "x may be used before it is defined in .join: print x y cond"
+        );
+    }
+}
@@ -0,0 +1,836 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    bril::types::{Instruction, OpCode, Type, Value},
+    cfg::{traversal::EdgeKind, ControlFlowGraph},
+};
+
+use super::{solve, DataFlowAnalysis, DataFlowResults, Direction};
+
+const INT_MIN: i64 = i32::MIN as i64;
+const INT_MAX: i64 = i32::MAX as i64;
+
+const NARROWING_PASSES: usize = 2;
+
+// all values from lo to hi, inclusive. bools are 0 for false and 1 for true
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Interval {
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Interval { lo, hi }
+    }
+
+    pub fn constant(value: i64) -> Self {
+        Interval::new(value, value)
+    }
+
+    pub fn any_int() -> Self {
+        Interval::new(INT_MIN, INT_MAX)
+    }
+
+    pub fn any_bool() -> Self {
+        Interval::new(0, 1)
+    }
+
+    pub fn any_of_type(var_type: Type) -> Self {
+        match var_type {
+            Type::Bool => Interval::any_bool(),
+            _ => Interval::any_int(),
+        }
+    }
+
+    pub fn get_constant(&self) -> Option<i64> {
+        (self.lo == self.hi).then_some(self.lo)
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+
+    pub fn join(self, other: Interval) -> Interval {
+        Interval::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    // None if no value is in both
+    pub fn intersect(self, other: Interval) -> Option<Interval> {
+        let result = Interval::new(self.lo.max(other.lo), self.hi.min(other.hi));
+        (result.lo <= result.hi).then_some(result)
+    }
+
+    // bounds that grew since last time jump straight to the end of the int range
+    fn widen(self, next: Interval) -> Interval {
+        Interval::new(
+            if next.lo < self.lo { INT_MIN } else { self.lo },
+            if next.hi > self.hi { INT_MAX } else { self.hi },
+        )
+    }
+
+    // bounds that were widened to the end of the int range take the bound from next instead
+    fn narrow(self, next: Interval) -> Interval {
+        Interval::new(
+            if self.lo == INT_MIN { next.lo } else { self.lo },
+            if self.hi == INT_MAX { next.hi } else { self.hi },
+        )
+    }
+
+    // ints wrap around, so a result that might not fit could be anything
+    fn from_bounds(lo: i64, hi: i64) -> Interval {
+        if lo < INT_MIN || hi > INT_MAX {
+            Interval::any_int()
+        } else {
+            Interval::new(lo, hi)
+        }
+    }
+
+    fn from_corners(corners: [Option<i64>; 4]) -> Interval {
+        let corners: Option<Vec<i64>> = corners.into_iter().collect();
+        match corners {
+            Some(c) => Interval::from_bounds(*c.iter().min().unwrap(), *c.iter().max().unwrap()),
+            None => Interval::any_int(),
+        }
+    }
+
+    fn from_bool(always: bool, never: bool) -> Interval {
+        if always {
+            Interval::constant(1)
+        } else if never {
+            Interval::constant(0)
+        } else {
+            Interval::any_bool()
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+// the result of an op on int or bool intervals
+fn evaluate_op(op: OpCode, args: &[Interval]) -> Option<Interval> {
+    let result = match (op, args) {
+        (OpCode::Add, [a, b]) => Interval::from_bounds(a.lo + b.lo, a.hi + b.hi),
+        (OpCode::Sub, [a, b]) => Interval::from_bounds(a.lo - b.hi, a.hi - b.lo),
+        (OpCode::Mul, [a, b]) => Interval::from_corners([
+            Some(a.lo * b.lo),
+            Some(a.lo * b.hi),
+            Some(a.hi * b.lo),
+            Some(a.hi * b.hi),
+        ]),
+        (OpCode::Div, [a, b]) if !b.contains(0) => Interval::from_corners([
+            a.lo.checked_div(b.lo),
+            a.lo.checked_div(b.hi),
+            a.hi.checked_div(b.lo),
+            a.hi.checked_div(b.hi),
+        ]),
+        (OpCode::Div, [_, _]) => Interval::any_int(),
+        (OpCode::LessThan, [a, b]) => Interval::from_bool(a.hi < b.lo, a.lo >= b.hi),
+        (OpCode::GreaterThan, [a, b]) => Interval::from_bool(a.lo > b.hi, a.hi <= b.lo),
+        (OpCode::LessThanOrEqual, [a, b]) => Interval::from_bool(a.hi <= b.lo, a.lo > b.hi),
+        (OpCode::GreaterThanOrEqual, [a, b]) => Interval::from_bool(a.lo >= b.hi, a.hi < b.lo),
+        (OpCode::Equal, [a, b]) => Interval::from_bool(
+            a.get_constant().is_some() && a.get_constant() == b.get_constant(),
+            a.intersect(*b).is_none(),
+        ),
+        (OpCode::Not, [a]) => Interval::new(1 - a.hi, 1 - a.lo),
+        (OpCode::And, [a, b]) => Interval::new(a.lo.min(b.lo), a.hi.min(b.hi)),
+        (OpCode::Or, [a, b]) => Interval::new(a.lo.max(b.lo), a.hi.max(b.hi)),
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+fn is_comparison(op: OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::LessThan
+            | OpCode::GreaterThan
+            | OpCode::LessThanOrEqual
+            | OpCode::GreaterThanOrEqual
+            | OpCode::Equal
+    )
+}
+
+// a bool var holding the result of comparing two int vars
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Comparison {
+    op: OpCode,
+    lhs: String,
+    rhs: String,
+}
+
+/*
+    Ranges of the int and bool vars at a program point. A var that isn't in here hasn't been
+    assigned on any path to this point yet.
+
+    Comparisons are remembered for as long as neither the bool holding the result nor the compared
+    vars change, so a br on the result can narrow down the compared vars along each of its edges.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntervalState {
+    ints: BTreeMap<String, Interval>,
+    bools: BTreeMap<String, Interval>,
+    comparisons: BTreeMap<String, Comparison>,
+}
+
+impl IntervalState {
+    pub fn get_range(&self, var: &str) -> Option<Interval> {
+        self.ints.get(var).or_else(|| self.bools.get(var)).copied()
+    }
+
+    pub fn get_int_ranges(&self) -> &BTreeMap<String, Interval> {
+        &self.ints
+    }
+
+    pub fn get_bool_ranges(&self) -> &BTreeMap<String, Interval> {
+        &self.bools
+    }
+
+    fn get_range_or(&self, var: &str, default: Interval) -> Interval {
+        self.get_range(var).unwrap_or(default)
+    }
+
+    fn join(&mut self, other: &IntervalState) {
+        for (ranges, other_ranges) in [
+            (&mut self.ints, &other.ints),
+            (&mut self.bools, &other.bools),
+        ] {
+            for (var, range) in other_ranges {
+                let joined = ranges.get(var).map_or(*range, |r| r.join(*range));
+                ranges.insert(var.clone(), joined);
+            }
+        }
+
+        self.comparisons
+            .retain(|var, comparison| other.comparisons.get(var) == Some(comparison));
+    }
+
+    fn assign(&mut self, var: &str, var_type: Type, range: Interval) {
+        self.ints.remove(var);
+        self.bools.remove(var);
+        self.comparisons
+            .retain(|v, c| v != var && c.lhs != var && c.rhs != var);
+
+        match var_type {
+            Type::Int => self.ints.insert(var.to_string(), range),
+            Type::Bool => self.bools.insert(var.to_string(), range),
+            _ => None,
+        };
+    }
+
+    // narrows the compared vars down to the values for which the comparison has the given
+    // result. false if there are none
+    fn refine(&mut self, comparison: &Comparison, result: bool) -> bool {
+        let Comparison { op, lhs, rhs } = comparison;
+
+        // a > b is b < a, and a >= b is b <= a
+        let (op, lhs, rhs) = match (*op, result) {
+            (OpCode::LessThan, true) | (OpCode::GreaterThanOrEqual, false) => {
+                (OpCode::LessThan, lhs, rhs)
+            }
+            (OpCode::LessThanOrEqual, true) | (OpCode::GreaterThan, false) => {
+                (OpCode::LessThanOrEqual, lhs, rhs)
+            }
+            (OpCode::GreaterThan, true) | (OpCode::LessThanOrEqual, false) => {
+                (OpCode::LessThan, rhs, lhs)
+            }
+            (OpCode::GreaterThanOrEqual, true) | (OpCode::LessThan, false) => {
+                (OpCode::LessThanOrEqual, rhs, lhs)
+            }
+            (OpCode::Equal, true) => (OpCode::Equal, lhs, rhs),
+            // a != b only says something when one side is a single value at an end of the other
+            _ => (OpCode::Not, lhs, rhs),
+        };
+
+        let a = self.get_range_or(lhs, Interval::any_int());
+        let b = self.get_range_or(rhs, Interval::any_int());
+        let refined = match op {
+            OpCode::LessThan => (
+                a.intersect(Interval::new(INT_MIN, b.hi - 1)),
+                b.intersect(Interval::new(a.lo + 1, INT_MAX)),
+            ),
+            OpCode::LessThanOrEqual => (
+                a.intersect(Interval::new(INT_MIN, b.hi)),
+                b.intersect(Interval::new(a.lo, INT_MAX)),
+            ),
+            OpCode::Equal => (a.intersect(b), a.intersect(b)),
+            _ => (exclude(a, b), exclude(b, a)),
+        };
+
+        let (Some(new_a), Some(new_b)) = refined else {
+            return false;
+        };
+        let new_b = if lhs == rhs {
+            match new_a.intersect(new_b) {
+                Some(range) => range,
+                None => return false,
+            }
+        } else {
+            new_b
+        };
+
+        self.ints.insert(lhs.clone(), new_a);
+        self.ints.insert(rhs.clone(), new_b);
+        true
+    }
+}
+
+// the values of a that can differ from b
+fn exclude(a: Interval, b: Interval) -> Option<Interval> {
+    match b.get_constant() {
+        Some(c) if a.lo == c && a.hi == c => None,
+        Some(c) if a.lo == c => Some(Interval::new(a.lo + 1, a.hi)),
+        Some(c) if a.hi == c => Some(Interval::new(a.lo, a.hi - 1)),
+        _ => Some(a),
+    }
+}
+
+// None for program points that can't be reached
+pub type IntervalFacts = Option<IntervalState>;
+
+// what a br does every time it runs. a taken br goes to its first label
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BranchOutcome {
+    AlwaysTaken,
+    NeverTaken,
+}
+
+/*
+    Forward analysis of the range of values every int var can hold, for bounds reasoning and
+    finding dead branches. Bools are tracked too, as ranges within [0, 1].
+
+    Loops could make ranges grow one step at a time, so at loop headers (targets of back edges)
+    bounds that grow are widened to the end of the int range. A couple of narrowing passes after
+    the fixpoint then pull widened bounds back in as far as the loop allows.
+
+    Along the edges of a br, the compared vars are narrowed down to the values that take that
+    edge, and edges that can never be taken carry no facts at all.
+
+    The loop headers depend on the cfg, so an analysis is created for one cfg and only solves that.
+*/
+pub struct IntervalAnalysis<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    loop_headers: HashSet<usize>,
+}
+
+impl<'g, 'a> IntervalAnalysis<'g, 'a> {
+    pub fn new(cfg: &'g ControlFlowGraph<'a>) -> Self {
+        let loop_headers = cfg
+            .classify_edges()
+            .into_iter()
+            .filter(|(_, kind)| *kind == EdgeKind::Back)
+            .map(|((_, to), _)| to)
+            .collect();
+
+        IntervalAnalysis { cfg, loop_headers }
+    }
+
+    pub fn analyze(&self) -> DataFlowResults<IntervalFacts> {
+        solve(self, self.cfg)
+    }
+
+    // block id -> outcome, for every reachable br that always goes the same way
+    pub fn find_constant_branches(
+        &self,
+        results: &DataFlowResults<IntervalFacts>,
+    ) -> BTreeMap<usize, BranchOutcome> {
+        let cfg = self.cfg;
+        let mut result = BTreeMap::new();
+        for block in cfg.get_function().get_blocks() {
+            let Some(instr) = block.instrs.last() else {
+                continue;
+            };
+            if instr.get_op_code() != Some(OpCode::Branch) {
+                continue;
+            }
+
+            let facts = results.get_instr_facts(self, cfg, block.get_id()).unwrap();
+            let Some(state) = &facts[block.instrs.len() - 1] else {
+                continue;
+            };
+
+            let cond = &instr.get_args().unwrap()[0];
+            match state
+                .get_range_or(cond, Interval::any_bool())
+                .get_constant()
+            {
+                Some(1) => result.insert(block.get_id(), BranchOutcome::AlwaysTaken),
+                Some(0) => result.insert(block.get_id(), BranchOutcome::NeverTaken),
+                _ => None,
+            };
+        }
+
+        result
+    }
+}
+
+fn evaluate(instr: &Instruction, state: &IntervalState) -> Interval {
+    let instr_type = instr.get_type().unwrap();
+    match instr.get_const_value() {
+        Some(Value::Int(i)) => return Interval::constant(i as i64),
+        Some(Value::Bool(b)) => return Interval::constant(b as i64),
+        None => (),
+    }
+
+    let op = instr.get_op_code().unwrap();
+    let args = instr.get_args_copy();
+    match op {
+        OpCode::Id => state.get_range_or(&args[0], Interval::any_of_type(instr_type)),
+        // the phi operands were all merged into the facts coming into the block
+        OpCode::Phi => args
+            .iter()
+            .filter_map(|arg| state.get_range(arg))
+            .reduce(Interval::join)
+            .unwrap_or(Interval::any_of_type(instr_type)),
+        _ => {
+            let ranges: Vec<Interval> = args
+                .iter()
+                .map(|arg| state.get_range_or(arg, Interval::any_int()))
+                .collect();
+
+            evaluate_op(op, &ranges).unwrap_or(Interval::any_of_type(instr_type))
+        }
+    }
+}
+
+impl DataFlowAnalysis for IntervalAnalysis<'_, '_> {
+    type Fact = IntervalFacts;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        None
+    }
+
+    // args could be anything
+    fn boundary(&self, cfg: &ControlFlowGraph) -> Self::Fact {
+        let mut state = IntervalState::default();
+        for arg in cfg.get_function().get_args() {
+            state.assign(
+                &arg.name,
+                arg.arg_type.clone(),
+                Interval::any_of_type(arg.arg_type.clone()),
+            );
+        }
+
+        Some(state)
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        match (fact.as_mut(), other) {
+            (_, None) => (),
+            (None, Some(_)) => *fact = other.clone(),
+            (Some(state), Some(other_state)) => state.join(other_state),
+        }
+    }
+
+    fn transfer_instr(
+        &self,
+        _block_id: usize,
+        _instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        let Some(state) = fact else {
+            return;
+        };
+        let Some(dest) = instr.get_dest() else {
+            return;
+        };
+
+        let range = evaluate(instr, state);
+        state.assign(dest, instr.get_type().unwrap(), range);
+
+        let op = instr.get_op_code().unwrap();
+        let args = instr.get_args_copy();
+        if is_comparison(op)
+            && !args
+                .iter()
+                .any(|arg| arg == dest || state.bools.contains_key(arg))
+        {
+            state.comparisons.insert(
+                dest.to_string(),
+                Comparison {
+                    op,
+                    lhs: args[0].clone(),
+                    rhs: args[1].clone(),
+                },
+            );
+        }
+    }
+
+    fn transfer_edge(&self, cfg: &ControlFlowGraph, from: usize, to: usize, fact: &mut Self::Fact) {
+        let Some(state) = fact else {
+            return;
+        };
+
+        let function = cfg.get_function();
+        let from_block = function.get_block_by_id(from).unwrap();
+        let Some(br) = from_block
+            .instrs
+            .last()
+            .filter(|i| i.get_op_code() == Some(OpCode::Branch))
+        else {
+            return;
+        };
+
+        let labels = br.get_labels_copy().unwrap();
+        let to_name = function.get_block_name(to).unwrap();
+        let taken = match (labels[0] == to_name, labels[1] == to_name) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => return,
+        };
+
+        let cond = &br.get_args().unwrap()[0];
+        let value = taken as i64;
+        if !state
+            .get_range_or(cond, Interval::any_bool())
+            .contains(value)
+        {
+            *fact = None;
+            return;
+        }
+        state.bools.insert(cond.clone(), Interval::constant(value));
+
+        if let Some(comparison) = state.comparisons.get(cond).cloned() {
+            if !state.refine(&comparison, taken) {
+                *fact = None;
+            }
+        }
+    }
+
+    fn widen(&self, block_id: usize, previous: &Self::Fact, fact: &mut Self::Fact) {
+        if !self.loop_headers.contains(&block_id) {
+            return;
+        }
+
+        let (Some(previous), Some(state)) = (previous, fact.as_mut()) else {
+            return;
+        };
+
+        for (var, range) in state.ints.iter_mut() {
+            if let Some(previous_range) = previous.ints.get(var) {
+                *range = previous_range.widen(*range);
+            }
+        }
+    }
+
+    fn narrowing_passes(&self) -> usize {
+        NARROWING_PASSES
+    }
+
+    fn narrow(&self, previous: &Self::Fact, fact: &mut Self::Fact) {
+        let (Some(previous), Some(state)) = (previous, fact.as_mut()) else {
+            return;
+        };
+
+        for (var, range) in state.ints.iter_mut() {
+            if let Some(previous_range) = previous.ints.get(var) {
+                *range = previous_range.narrow(*range);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, rc::Rc};
+
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::{BranchOutcome, Interval, IntervalAnalysis, INT_MAX, INT_MIN};
+
+    fn value(op: OpCode, dest: &str, instr_type: Type, args: &[&str]) -> Rc<Instruction> {
+        Instruction::new_value(
+            op,
+            dest.to_string(),
+            instr_type,
+            args.iter().map(|a| a.to_string()).collect(),
+            vec![],
+            vec![],
+        )
+    }
+
+    fn int(dest: &str, value: i32) -> Rc<Instruction> {
+        Instruction::new_const(
+            OpCode::Const,
+            dest.to_string(),
+            Type::Int,
+            Value::Int(value),
+        )
+    }
+
+    fn jmp(label: &str) -> Rc<Instruction> {
+        Instruction::new_effect(OpCode::Jump, vec![], vec![], vec![label.to_string()])
+    }
+
+    fn print(var: &str) -> Rc<Instruction> {
+        Instruction::new_effect(OpCode::Print, vec![var.to_string()], vec![], vec![])
+    }
+
+    fn ret() -> Rc<Instruction> {
+        Instruction::new_effect(OpCode::Ret, vec![], vec![], vec![])
+    }
+
+    // the range of var flowing into every block, with args a: int and c: bool.
+    // None for blocks that can't be reached, or where var isn't assigned yet
+    fn get_ranges_in(instrs: Vec<Rc<Instruction>>, var: &str) -> Vec<Option<Interval>> {
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![
+                FunctionArg::new("a".to_string(), Type::Int),
+                FunctionArg::new("c".to_string(), Type::Bool),
+            ],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let results = IntervalAnalysis::new(&cfg).analyze();
+        cfg.get_all_block_ids()
+            .iter()
+            .map(|block_id| {
+                results
+                    .get_in(*block_id)
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|state| state.get_range(var))
+            })
+            .collect()
+    }
+
+    fn br(cond: &str, taken: &str, not_taken: &str) -> Rc<Instruction> {
+        Instruction::new_effect(
+            OpCode::Branch,
+            vec![cond.to_string()],
+            vec![],
+            vec![taken.to_string(), not_taken.to_string()],
+        )
+    }
+
+    #[test]
+    fn test_interval_loop() {
+        // for (i = 0; i < 10; i++), with a check inside the loop that always passes and one after
+        // it that always fails
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_const(OpCode::Const, "i".to_string(), Type::Int, Value::Int(0)),
+            Instruction::new_const(OpCode::Const, "n".to_string(), Type::Int, Value::Int(10)),
+            Instruction::new_const(OpCode::Const, "one".to_string(), Type::Int, Value::Int(1)),
+            Instruction::new_label("loop"),
+            value(OpCode::LessThan, "cond", Type::Bool, &["i", "n"]),
+            br("cond", "body", "done"),
+            Instruction::new_label("body"),
+            value(OpCode::LessThanOrEqual, "check", Type::Bool, &["i", "n"]),
+            br("check", "ok", "done"),
+            Instruction::new_label("ok"),
+            value(OpCode::Add, "i", Type::Int, &["i", "one"]),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["loop".to_string()]),
+            Instruction::new_label("done"),
+            value(OpCode::GreaterThan, "over", Type::Bool, &["i", "n"]),
+            br("over", "bad", "end"),
+            Instruction::new_label("bad"),
+            Instruction::new_effect(OpCode::Print, vec!["i".to_string()], vec![], vec![]),
+            Instruction::new_label("end"),
+            value(OpCode::Mul, "sq", Type::Int, &["i", "x"]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("x".to_string(), Type::Int)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let analysis = IntervalAnalysis::new(&cfg);
+        let results = analysis.analyze();
+        let range_in = |block_id: usize, var: &str| {
+            results
+                .get_in(block_id)
+                .unwrap()
+                .as_ref()
+                .and_then(|s| s.get_range(var))
+        };
+
+        // widened to i32::MAX, then narrowed back down by the loop condition
+        assert_eq!(range_in(1, "i"), Some(Interval::new(0, 10)));
+        assert_eq!(range_in(2, "i"), Some(Interval::new(0, 9)));
+        assert_eq!(range_in(3, "i"), Some(Interval::new(0, 9)));
+        assert_eq!(range_in(4, "i"), Some(Interval::new(10, 10)));
+
+        // bad can't be reached
+        assert_eq!(results.get_in(5), Some(&None));
+
+        let end_facts = results.get_instr_facts(&analysis, &cfg, 6).unwrap();
+        assert_eq!(
+            end_facts[2].as_ref().unwrap().get_range("sq"),
+            Some(Interval::any_int())
+        );
+
+        assert_eq!(
+            analysis.find_constant_branches(&results),
+            BTreeMap::from([
+                (2, BranchOutcome::AlwaysTaken),
+                (4, BranchOutcome::NeverTaken)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_interval_unbounded_loop() {
+        // nothing bounds i, so without widening every visit to the loop would grow it by one.
+        // i + 1 can wrap around once i reaches the top, so i ends up being anything
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            int("i", 0),
+            int("one", 1),
+            Instruction::new_label("loop"),
+            value(OpCode::Add, "i", Type::Int, &["i", "one"]),
+            br("c", "loop", "done"),
+            Instruction::new_label("done"),
+            print("i"),
+        ];
+
+        let ranges = get_ranges_in(instrs, "i");
+        assert_eq!(ranges[1], Some(Interval::any_int()));
+        assert_eq!(ranges[2], Some(Interval::any_int()));
+    }
+
+    #[test]
+    fn test_interval_narrowing() {
+        // i steps by 3 up to 10. widening pushes i at the loop header to i32::MAX, and narrowing
+        // brings it back to the last value the loop can produce
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            int("i", 0),
+            int("n", 10),
+            int("three", 3),
+            Instruction::new_label("loop"),
+            value(OpCode::LessThan, "cond", Type::Bool, &["i", "n"]),
+            br("cond", "body", "done"),
+            Instruction::new_label("body"),
+            value(OpCode::Add, "i", Type::Int, &["i", "three"]),
+            jmp("loop"),
+            Instruction::new_label("done"),
+            print("i"),
+        ];
+
+        let ranges = get_ranges_in(instrs, "i");
+        assert_eq!(ranges[1], Some(Interval::new(0, 12)));
+        assert_eq!(ranges[2], Some(Interval::new(0, 9)));
+        assert_eq!(ranges[3], Some(Interval::new(10, 12)));
+    }
+
+    #[test]
+    fn test_interval_comparison_edges() {
+        // a is known to be at least 0 by the time it's compared.
+        // (op, compared against, range when taken, range when not taken)
+        let cases = [
+            (
+                OpCode::LessThan,
+                "five",
+                Interval::new(0, 4),
+                Interval::new(5, INT_MAX),
+            ),
+            (
+                OpCode::LessThanOrEqual,
+                "five",
+                Interval::new(0, 5),
+                Interval::new(6, INT_MAX),
+            ),
+            (
+                OpCode::GreaterThan,
+                "five",
+                Interval::new(6, INT_MAX),
+                Interval::new(0, 5),
+            ),
+            (
+                OpCode::GreaterThanOrEqual,
+                "five",
+                Interval::new(5, INT_MAX),
+                Interval::new(0, 4),
+            ),
+            // a != 0 can only take 0 off the end of a's range
+            (
+                OpCode::Equal,
+                "zero",
+                Interval::constant(0),
+                Interval::new(1, INT_MAX),
+            ),
+        ];
+
+        for (op, rhs, taken, not_taken) in cases {
+            let instrs = vec![
+                Instruction::new_label("entry"),
+                int("zero", 0),
+                int("five", 5),
+                value(
+                    OpCode::GreaterThanOrEqual,
+                    "guard",
+                    Type::Bool,
+                    &["a", "zero"],
+                ),
+                br("guard", "check", "out"),
+                Instruction::new_label("check"),
+                value(op, "cond", Type::Bool, &["a", rhs]),
+                br("cond", "yes", "no"),
+                Instruction::new_label("yes"),
+                print("a"),
+                ret(),
+                Instruction::new_label("no"),
+                print("a"),
+                ret(),
+                Instruction::new_label("out"),
+                print("a"),
+            ];
+
+            let ranges = get_ranges_in(instrs, "a");
+            assert_eq!(ranges[1], Some(Interval::new(0, INT_MAX)), "{:?}", op);
+            assert_eq!(ranges[2], Some(taken), "{:?}", op);
+            assert_eq!(ranges[3], Some(not_taken), "{:?}", op);
+            assert_eq!(ranges[4], Some(Interval::new(INT_MIN, -1)), "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn test_interval_infeasible_edge() {
+        // a < 5 and a > 5 can't both hold, so nothing flows into bad, not even the args
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            int("five", 5),
+            value(OpCode::LessThan, "small", Type::Bool, &["a", "five"]),
+            br("small", "check", "done"),
+            Instruction::new_label("check"),
+            value(OpCode::GreaterThan, "big", Type::Bool, &["a", "five"]),
+            br("big", "bad", "done"),
+            Instruction::new_label("bad"),
+            print("a"),
+            ret(),
+            Instruction::new_label("done"),
+            print("a"),
+        ];
+
+        let ranges = get_ranges_in(instrs, "a");
+        assert_eq!(ranges[1], Some(Interval::new(INT_MIN, 4)));
+        assert_eq!(ranges[2], None);
+        assert_eq!(ranges[3], Some(Interval::any_int()));
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{basicblock::BasicBlock, bril::types::Instruction, cfg::ControlFlowGraph};
+
+use super::{
+    bitset::{BitSet, GenKill, GenKillTarget, IndexedSet, Universe},
+    solve, DataFlowAnalysis, DataFlowResults, Direction,
+};
+
+pub type LiveVars = IndexedSet<String>;
+
+/*
+    A variable v is LIVE at a program point iff there is a path in the CFG from that point to a
+    use of v, along which v is not redefined.
+
+    Solved backwards: live-in = USE U (live-out - DEF), and a block's live-out is the union of its
+    successors' live-ins. Nothing is live after a ret, and ret operands are uses like any other.
+
+    A phi doesn't use its operands where it sits. Each operand is used at the end of the
+    predecessor named by its label, so it's only live along that one incoming edge. Function args
+    are defined before the entry runs, so args live into the entry are the ones read before being
+    redefined. Any other variable live into the entry can be read without ever being defined.
+*/
+pub struct LiveVariables<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    numbering: VariableNumbering,
+}
+
+struct VariableNumbering {
+    vars: Rc<Universe<String>>,
+    // var -> a set with just var, to kill it with
+    kills: HashMap<String, BitSet>,
+    // block id -> transfer over the whole block
+    blocks: HashMap<usize, GenKill>,
+}
+
+// every variable the function mentions: its args, and the dests and args of its instructions
+pub(crate) fn create_variable_universe(cfg: &ControlFlowGraph) -> Universe<String> {
+    let function = cfg.get_function();
+    let mut vars: Vec<String> = function.get_args().iter().map(|a| a.name.clone()).collect();
+    for block in function.get_blocks() {
+        for instr in &block.instrs {
+            vars.extend(instr.get_dest().map(|d| d.to_string()));
+            vars.extend(instr.get_args_copy());
+        }
+    }
+
+    Universe::new(vars)
+}
+
+impl<'g, 'a> LiveVariables<'g, 'a> {
+    pub fn new(cfg: &'g ControlFlowGraph<'a>) -> Self {
+        LiveVariables {
+            cfg,
+            numbering: VariableNumbering::create(cfg),
+        }
+    }
+
+    pub fn analyze(&self) -> DataFlowResults<LiveVars> {
+        solve(self, self.cfg)
+    }
+}
+
+impl VariableNumbering {
+    fn create(cfg: &ControlFlowGraph) -> Self {
+        let vars = Rc::new(create_variable_universe(cfg));
+        let kills = vars
+            .iter()
+            .map(|(idx, var)| {
+                let mut kill = BitSet::new_empty(vars.len());
+                kill.insert(idx);
+                (var.clone(), kill)
+            })
+            .collect();
+
+        let mut numbering = VariableNumbering {
+            vars,
+            kills,
+            blocks: HashMap::new(),
+        };
+
+        for block in cfg.get_function().get_blocks() {
+            let transfer = GenKill::create_for_block(
+                numbering.vars.len(),
+                block,
+                Direction::Backward,
+                |_, instr, target| numbering.transfer_instr(instr, target),
+            );
+            numbering.blocks.insert(block.get_id(), transfer);
+        }
+
+        numbering
+    }
+
+    // kills the dest, then makes the args live
+    fn transfer_instr(&self, instr: &Instruction, target: &mut impl GenKillTarget) {
+        if let Some(dest) = instr.get_dest() {
+            target.kill(&self.kills[dest]);
+        }
+
+        // phi operands are added on their incoming edges instead
+        if !instr.is_phi() {
+            for arg in instr.get_args_copy() {
+                target.gen(self.vars.get_index(&arg).unwrap());
+            }
+        }
+    }
+}
+
+impl DataFlowAnalysis for LiveVariables<'_, '_> {
+    type Fact = LiveVars;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_empty(&self.numbering.vars)
+    }
+
+    fn boundary(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_empty(&self.numbering.vars)
+    }
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.union_with(other);
+    }
+
+    fn transfer_instr(
+        &self,
+        _block_id: usize,
+        _instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        self.numbering.transfer_instr(instr, fact.get_mut_bits());
+    }
+
+    fn transfer_block(&self, block: &BasicBlock, fact: &mut Self::Fact) {
+        self.numbering.blocks[&block.get_id()].apply(fact.get_mut_bits());
+    }
+
+    // fact is live-in of to. the operands of to's phis coming from from are live out of from
+    fn transfer_edge(&self, cfg: &ControlFlowGraph, from: usize, to: usize, fact: &mut Self::Fact) {
+        let function = cfg.get_function();
+        let Some(from_name) = function.get_block_name(from) else {
+            return;
+        };
+
+        let to_block = function.get_block_by_id(to).unwrap();
+        for phi in to_block.instrs.iter().filter(|i| i.is_phi()) {
+            let args = phi.get_args_copy();
+            let labels = phi.get_labels_copy().unwrap_or_default();
+            for (arg, label) in args.into_iter().zip(labels.iter()) {
+                if *label == from_name {
+                    fact.insert(&arg);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::{LiveVariables, LiveVars};
+
+    fn vars(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn to_set(fact: Option<&LiveVars>) -> BTreeSet<String> {
+        fact.unwrap().iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_live_variables_phi() {
+        // a loop in ssa form: x is a on the way in and y on the way around
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_const(OpCode::Const, "a".to_string(), Type::Int, Value::Int(1)),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["loop".to_string()]),
+            Instruction::new_label("loop"),
+            Instruction::new_value(
+                OpCode::Phi,
+                "x".to_string(),
+                Type::Int,
+                vec!["a".to_string(), "y".to_string()],
+                vec![],
+                vec!["entry".to_string(), "body".to_string()],
+            ),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["body".to_string(), "done".to_string()],
+            ),
+            Instruction::new_label("body"),
+            Instruction::new_value(
+                OpCode::Add,
+                "y".to_string(),
+                Type::Int,
+                vec!["x".to_string(), "x".to_string()],
+                vec![],
+                vec![],
+            ),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["loop".to_string()]),
+            Instruction::new_label("done"),
+            Instruction::new_effect(OpCode::Ret, vec!["x".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Int,
+            vec![FunctionArg::new("cond".to_string(), Type::Bool)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let analysis = LiveVariables::new(&cfg);
+        let results = analysis.analyze();
+
+        // a is only live on the way into the loop, y only on the way around
+        assert_eq!(to_set(results.get_in(0)), vars(&["cond"]));
+        assert_eq!(to_set(results.get_out(0)), vars(&["a", "cond"]));
+        assert_eq!(to_set(results.get_in(1)), vars(&["cond"]));
+        assert_eq!(to_set(results.get_out(1)), vars(&["cond", "x"]));
+        assert_eq!(to_set(results.get_in(2)), vars(&["cond", "x"]));
+        assert_eq!(to_set(results.get_out(2)), vars(&["cond", "y"]));
+        assert_eq!(to_set(results.get_in(3)), vars(&["x"]));
+        assert_eq!(to_set(results.get_out(3)), vars(&[]));
+
+        let body_facts = results.get_instr_facts(&analysis, &cfg, 2).unwrap();
+        assert_eq!(
+            body_facts
+                .iter()
+                .map(|f| to_set(Some(f)))
+                .collect::<Vec<_>>(),
+            vec![
+                vars(&["cond", "x"]),
+                vars(&["cond", "x"]),
+                vars(&["cond", "y"]),
+                vars(&["cond", "y"]),
+            ]
+        );
+    }
+}
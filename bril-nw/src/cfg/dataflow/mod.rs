@@ -1,3 +1,105 @@
+pub mod anticipated_expressions;
+pub mod available_expressions;
+pub mod bitset;
+pub mod constant_propagation;
+pub mod def_use;
+pub mod defined_variables;
+pub mod interval_analysis;
+pub mod live_variables;
 pub mod reaching_definitions;
+pub mod report;
+mod solver;
+pub mod taint;
 
-pub trait DataFlowAnalysis {}
+pub use solver::{solve, DataFlowResults};
+
+use crate::{basicblock::BasicBlock, bril::types::Instruction, cfg::ControlFlowGraph};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    // facts flow from the entry along edges, e.g. reaching definitions
+    Forward,
+    // facts flow from the exits against edges, e.g. liveness
+    Backward,
+}
+
+/*
+    A dataflow problem over the cfg of one function, solved by solve().
+
+    Facts form a lattice. merge combines the facts flowing in from several edges, and is a join for
+    "may" problems (something holds along some path) or a meet for "must" problems (something holds
+    along every path). init is the identity of merge: bottom for a join, top for a meet. Every
+    block starts out with it, so a fact a block hasn't heard from yet doesn't change the result.
+
+    boundary is the fact flowing into the function: into the entry for forward problems, and out
+    of the blocks without successors for backward problems.
+
+    transfer_instr moves a fact across one instruction, in the direction of the analysis. By
+    default a block is transferred one instruction at a time, but analyses can override
+    transfer_block with something faster (e.g. precomputed gen and kill sets) as long as it agrees
+    with transfer_instr. Set-valued facts can be stored as bits (see bitset). The items are then
+    numbered for one cfg, so such analyses are created for a cfg and hold on to it.
+    transfer_edge can change a fact as it flows along a single edge, e.g. to use a br condition,
+    or to pick out the phi operands for one predecessor.
+
+    For solve to terminate, transfers must be monotone and the lattice must have finite height.
+    Lattices with long or infinite ascending chains (e.g. integer ranges) have to implement widen,
+    which is applied to the fact flowing into a block every time it's visited again. The
+    fixpoint found with widening can then be made more precise with a few extra passes over the
+    blocks, each using narrow to refine the facts flowing into a block.
+*/
+pub trait DataFlowAnalysis {
+    type Fact: Clone + PartialEq;
+
+    fn direction(&self) -> Direction;
+
+    fn init(&self, cfg: &ControlFlowGraph) -> Self::Fact;
+
+    fn boundary(&self, cfg: &ControlFlowGraph) -> Self::Fact;
+
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+    fn transfer_instr(
+        &self,
+        block_id: usize,
+        instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    );
+
+    fn transfer_block(&self, block: &BasicBlock, fact: &mut Self::Fact) {
+        let instrs = block.instrs.iter().enumerate();
+        match self.direction() {
+            Direction::Forward => {
+                for (idx, instr) in instrs {
+                    self.transfer_instr(block.get_id(), idx, instr, fact);
+                }
+            }
+            Direction::Backward => {
+                for (idx, instr) in instrs.rev() {
+                    self.transfer_instr(block.get_id(), idx, instr, fact);
+                }
+            }
+        }
+    }
+
+    // from -> to is always an edge of the cfg, even for backward problems
+    fn transfer_edge(
+        &self,
+        _cfg: &ControlFlowGraph,
+        _from: usize,
+        _to: usize,
+        _fact: &mut Self::Fact,
+    ) {
+    }
+
+    // previous is what flowed into the block the last time it was visited, fact what flows in now
+    fn widen(&self, _block_id: usize, _previous: &Self::Fact, _fact: &mut Self::Fact) {}
+
+    fn narrowing_passes(&self) -> usize {
+        0
+    }
+
+    // previous is what flowed into the block at the fixpoint, fact what the transfers give now
+    fn narrow(&self, _previous: &Self::Fact, _fact: &mut Self::Fact) {}
+}
@@ -1,16 +1,47 @@
-use std::{
-    collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
-    hash::{Hash, Hasher},
-};
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{basicblock::BasicBlock, bril::types::Instruction, cfg::ControlFlowGraph};
 
-use crate::{
-    basicblock::{BasicBlock, FunctionBlocks},
-    cfg::ControlFlowGraph,
+use super::{
+    bitset::{BitSet, GenKill, GenKillTarget, IndexedSet, Universe},
+    solve, DataFlowAnalysis, DataFlowResults, Direction,
 };
 
-type IdentifiedDeclaration = (usize, String);
+// the instr_idx-th instruction (counting labels) of a block
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct InstrLocation {
+    pub block_id: usize,
+    pub instr_idx: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DefinitionSite {
+    // function args are defined before the entry block runs
+    Arg,
+    Instr(InstrLocation),
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Definition {
+    pub var: String,
+    pub site: DefinitionSite,
+}
+
+pub type ReachingFacts = IndexedSet<Definition>;
 
-pub struct ReachingDefinitions();
+pub struct ReachingDefinitions<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    numbering: DefinitionNumbering,
+}
+
+// every definition in the function, numbered, and what defining each variable kills
+struct DefinitionNumbering {
+    definitions: Rc<Universe<Definition>>,
+    // var -> all definitions of var
+    kills: HashMap<String, BitSet>,
+    // block id -> transfer over the whole block
+    blocks: HashMap<usize, GenKill>,
+}
 
 /*
     A instruction d defining variable v REACHES another instruction u iff
@@ -23,139 +54,274 @@ pub struct ReachingDefinitions();
     "kill" - Any definition kills all of the currently available definitions
 
     For every definition and every use, determine whether the definition reaches the use
-*/
-impl ReachingDefinitions {
-    pub fn new() -> Self {
-        ReachingDefinitions()
-    }
 
-    pub fn analyze(&self, cfg: &ControlFlowGraph, function: &FunctionBlocks) {
-        if function.get_blocks().is_empty() {
-            return;
+    Definitions are identified by the instruction making them, so two definitions of v in the same
+    block are different definitions (and only the second one leaves the block). They are numbered
+    when the analysis is created for a cfg, and facts are sets of those numbers.
+*/
+impl<'g, 'a> ReachingDefinitions<'g, 'a> {
+    pub fn new(cfg: &'g ControlFlowGraph<'a>) -> Self {
+        ReachingDefinitions {
+            cfg,
+            numbering: DefinitionNumbering::create(cfg),
         }
+    }
 
-        let mut inputs: HashMap<usize, BTreeSet<IdentifiedDeclaration>> = HashMap::new();
-        let mut outputs: HashMap<usize, BTreeSet<IdentifiedDeclaration>> = HashMap::new();
+    pub fn analyze(&self) -> DataFlowResults<ReachingFacts> {
+        solve(self, self.cfg)
+    }
+}
 
-        let init_block_id = function.get_blocks()[0].get_id();
+impl DefinitionNumbering {
+    fn create(cfg: &ControlFlowGraph) -> Self {
+        let function = cfg.get_function();
 
-        // init state of this data flow analysis is the args of the function
-        let init_inputs: BTreeSet<IdentifiedDeclaration> = function
+        let mut definitions: Vec<Definition> = function
             .get_args()
             .iter()
-            .map(|a| (init_block_id, a.name.clone()))
+            .map(|a| Definition {
+                var: a.name.clone(),
+                site: DefinitionSite::Arg,
+            })
             .collect();
+        for block in function.get_blocks() {
+            for (instr_idx, instr) in block.instrs.iter().enumerate() {
+                if let Some(dest) = instr.get_dest() {
+                    definitions.push(Definition {
+                        var: dest.to_string(),
+                        site: DefinitionSite::Instr(InstrLocation {
+                            block_id: block.get_id(),
+                            instr_idx,
+                        }),
+                    });
+                }
+            }
+        }
+        let definitions: Rc<Universe<Definition>> = Rc::new(definitions.into_iter().collect());
+
+        let mut kills: HashMap<String, BitSet> = HashMap::new();
+        for (idx, definition) in definitions.iter() {
+            kills
+                .entry(definition.var.clone())
+                .or_insert_with(|| BitSet::new_empty(definitions.len()))
+                .insert(idx);
+        }
 
-        // TODO: i think idx 0 should be the first function block?
-        inputs.insert(init_block_id, init_inputs);
+        let mut numbering = DefinitionNumbering {
+            definitions,
+            kills,
+            blocks: HashMap::new(),
+        };
 
-        // add all blocks to the worklist
-        let mut work_list: BTreeSet<usize> = BTreeSet::new();
         for block in function.get_blocks() {
-            work_list.insert(block.get_id());
+            let transfer = GenKill::create_for_block(
+                numbering.definitions.len(),
+                block,
+                Direction::Forward,
+                |instr_idx, instr, target| {
+                    numbering.transfer_instr(block.get_id(), instr_idx, instr, target)
+                },
+            );
+            numbering.blocks.insert(block.get_id(), transfer);
         }
 
-        // forward worklist algorithm
-        while !work_list.is_empty() {
-            let block_id = *work_list.iter().next().unwrap();
-            let block = function.get_block_by_id(block_id).unwrap();
-
-            if block_id != init_block_id {
-                // merge
-                // in[b] = merge (out[p] for each predecessor p of b)
-                let maybe_predecessors = cfg.predecessors.get(&block_id);
-                if let Some(predecessors) = maybe_predecessors {
-                    let merged_input: BTreeSet<IdentifiedDeclaration> = predecessors
-                        .iter()
-                        .map(|pred_id| outputs.get(pred_id).map_or(BTreeSet::new(), |o| o.clone()))
-                        .fold(
-                            BTreeSet::<IdentifiedDeclaration>::new(),
-                            |mut accum, out| {
-                                accum.extend(out);
-                                accum
-                            },
-                        );
-                    inputs.insert(block_id, merged_input);
-                }
-            }
+        numbering
+    }
 
-            // transfer
-            // out[b] = transfer(b, in[b])
-            // for reaching definitions this is DEF[b] U (in[b] - KILL[b])
-            let defs = get_defs(block);
-
-            let input_copy = inputs.get(&block_id).unwrap().clone();
-
-            let updated_outputs = transfer_defs(block_id, defs, input_copy);
-            let maybe_current_outputs = outputs.get(&block_id);
-            if let Some(current_outputs) = maybe_current_outputs {
-                if check_different(&updated_outputs, current_outputs) {
-                    // successors need to be added to work list
-                    let successors = cfg.successors.get(&block_id);
-                    if let Some(successors) = successors {
-                        for successor in successors {
-                            work_list.insert(*successor);
-                        }
-                    }
-                }
-            }
-            outputs.insert(block_id, updated_outputs);
+    // kills every other definition of the var an instruction defines, and makes its own
+    fn transfer_instr(
+        &self,
+        block_id: usize,
+        instr_idx: usize,
+        instr: &Instruction,
+        target: &mut impl GenKillTarget,
+    ) {
+        let Some(dest) = instr.get_dest() else {
+            return;
+        };
+
+        let definition = Definition {
+            var: dest.to_string(),
+            site: DefinitionSite::Instr(InstrLocation {
+                block_id,
+                instr_idx,
+            }),
+        };
+        target.kill(&self.kills[dest]);
+        target.gen(self.definitions.get_index(&definition).unwrap());
+    }
+}
+
+impl DataFlowAnalysis for ReachingDefinitions<'_, '_> {
+    type Fact = ReachingFacts;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn init(&self, _cfg: &ControlFlowGraph) -> Self::Fact {
+        IndexedSet::new_empty(&self.numbering.definitions)
+    }
+
+    // function args are defined before the entry block runs
+    fn boundary(&self, cfg: &ControlFlowGraph) -> Self::Fact {
+        let mut result = IndexedSet::new_empty(&self.numbering.definitions);
+        for arg in cfg.get_function().get_args() {
+            result.insert(&Definition {
+                var: arg.name.clone(),
+                site: DefinitionSite::Arg,
+            });
         }
+
+        result
+    }
+
+    // in[b] = merge (out[p] for each predecessor p of b)
+    fn merge(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.union_with(other);
+    }
+
+    // out = DEF U (in - KILL). any definition kills all of the currently available definitions of
+    // the same variable
+    fn transfer_instr(
+        &self,
+        block_id: usize,
+        instr_idx: usize,
+        instr: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        self.numbering
+            .transfer_instr(block_id, instr_idx, instr, fact.get_mut_bits());
+    }
+
+    fn transfer_block(&self, block: &BasicBlock, fact: &mut Self::Fact) {
+        let numbering = &self.numbering;
+        numbering.blocks[&block.get_id()].apply(fact.get_mut_bits());
     }
 }
 
-// gets all vars that have been assigned to in this block
-fn get_defs(block: &BasicBlock) -> BTreeSet<String> {
-    block
-        .instrs
-        .iter()
-        .fold(BTreeSet::<String>::new(), |mut accum, instr| {
-            let maybe_dest = instr.get_dest();
-            if let Some(dest) = maybe_dest {
-                accum.insert(dest.to_string());
-            }
+impl fmt::Display for InstrLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.block_id, self.instr_idx)
+    }
+}
 
-            accum
-        })
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.site {
+            DefinitionSite::Arg => write!(f, "{}@arg", self.var),
+            DefinitionSite::Instr(location) => write!(f, "{}@{}", self.var, location),
+        }
+    }
 }
 
-fn transfer_defs(
-    block_id: usize,
-    defs: BTreeSet<String>,
-    input: BTreeSet<IdentifiedDeclaration>,
-) -> BTreeSet<IdentifiedDeclaration> {
-    let mut kills: BTreeSet<IdentifiedDeclaration> = BTreeSet::new();
-    for (other_block_id, def_name) in &input {
-        if defs.contains(def_name) {
-            kills.insert((*other_block_id, def_name.clone()));
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        basicblock::{FunctionBlocks, FunctionBlocksLoader},
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::{Definition, DefinitionSite, InstrLocation, ReachingDefinitions, ReachingFacts};
+
+    pub(crate) fn def(var: &str, block_id: usize, instr_idx: usize) -> Definition {
+        Definition {
+            var: var.to_string(),
+            site: DefinitionSite::Instr(InstrLocation {
+                block_id,
+                instr_idx,
+            }),
         }
     }
 
-    let diff: BTreeSet<IdentifiedDeclaration> = input.difference(&kills).cloned().collect();
-    let identified_defs: BTreeSet<IdentifiedDeclaration> =
-        defs.into_iter().map(|decl| (block_id, decl)).collect();
+    pub(crate) fn arg(var: &str) -> Definition {
+        Definition {
+            var: var.to_string(),
+            site: DefinitionSite::Arg,
+        }
+    }
 
-    diff.union(&identified_defs).cloned().collect()
-}
+    fn facts(defs: Vec<Definition>) -> BTreeSet<Definition> {
+        defs.into_iter().collect()
+    }
 
-fn check_different(
-    updated: &BTreeSet<IdentifiedDeclaration>,
-    current: &BTreeSet<IdentifiedDeclaration>,
-) -> bool {
-    if updated.len() != current.len() {
-        return true;
+    fn to_set(fact: Option<&ReachingFacts>) -> Option<BTreeSet<Definition>> {
+        fact.map(|f| f.iter().cloned().collect())
     }
 
-    if calc_hash(updated) != calc_hash(current) {
-        return true;
+    // x is defined in the entry, then twice in the loop body. only the second definition in the
+    // body makes it back around to the loop header
+    pub(crate) fn get_test_function() -> FunctionBlocks {
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_const(OpCode::Const, "x".to_string(), Type::Int, Value::Int(1)),
+            Instruction::new_label("loop"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["body".to_string(), "done".to_string()],
+            ),
+            Instruction::new_label("body"),
+            Instruction::new_value(
+                OpCode::Add,
+                "x".to_string(),
+                Type::Int,
+                vec!["x".to_string(), "x".to_string()],
+                vec![],
+                vec![],
+            ),
+            Instruction::new_value(
+                OpCode::Add,
+                "x".to_string(),
+                Type::Int,
+                vec!["x".to_string(), "x".to_string()],
+                vec![],
+                vec![],
+            ),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["loop".to_string()]),
+            Instruction::new_label("done"),
+            Instruction::new_effect(OpCode::Print, vec!["x".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("cond".to_string(), Type::Bool)],
+            instrs,
+        );
+
+        FunctionBlocksLoader::new(function).load().unwrap()
     }
 
-    return false;
-}
+    #[test]
+    fn test_reaching_definitions_loop() {
+        let mut blocks = get_test_function();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let analysis = ReachingDefinitions::new(&cfg);
+        let results = analysis.analyze();
 
-fn calc_hash(d: &BTreeSet<IdentifiedDeclaration>) -> u64 {
-    let mut h = DefaultHasher::new();
-    d.hash(&mut h);
+        assert_eq!(to_set(results.get_in(0)), Some(facts(vec![arg("cond")])));
+        assert_eq!(
+            to_set(results.get_in(1)),
+            Some(facts(vec![arg("cond"), def("x", 0, 1), def("x", 2, 2)]))
+        );
+        assert_eq!(
+            to_set(results.get_out(2)),
+            Some(facts(vec![arg("cond"), def("x", 2, 2)]))
+        );
+        assert_eq!(results.get_in(3), results.get_in(1));
 
-    h.finish()
+        let body_facts = results.get_instr_facts(&analysis, &cfg, 2).unwrap();
+        assert_eq!(body_facts.len(), 5);
+        assert_eq!(&body_facts[1], results.get_in(1).unwrap());
+        assert_eq!(
+            to_set(Some(&body_facts[2])),
+            Some(facts(vec![arg("cond"), def("x", 2, 1)]))
+        );
+        assert_eq!(&body_facts[3], results.get_out(2).unwrap());
+    }
 }
@@ -0,0 +1,261 @@
+use std::{fmt::Write, rc::Rc};
+
+use json::JsonValue;
+
+use crate::{bril::types::Instruction, cfg::ControlFlowGraph};
+
+use super::{
+    anticipated_expressions::AnticipatedExpressions,
+    available_expressions::AvailableExpressions,
+    constant_propagation::{ConstantPropagation, ConstantValue},
+    defined_variables::DefinedVariables,
+    interval_analysis::IntervalAnalysis,
+    live_variables::LiveVariables,
+    reaching_definitions::ReachingDefinitions,
+    DataFlowAnalysis,
+};
+
+// names of the analyses create_report knows, e.g. for picking one on the command line
+pub const ANALYSIS_NAMES: [&str; 7] = [
+    "reaching",
+    "live",
+    "defined",
+    "constprop",
+    "available",
+    "anticipated",
+    "intervals",
+];
+
+pub trait ReportableAnalysis: DataFlowAnalysis {
+    // a fact as a list of readable items, e.g. one per live variable
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String>;
+}
+
+pub struct BlockReport {
+    pub block_id: usize,
+    pub name: String,
+    pub instrs: Vec<Rc<Instruction>>,
+    // before the block's first instruction and after its last, in program order
+    pub facts_in: Vec<String>,
+    pub facts_out: Vec<String>,
+}
+
+/*
+    The facts an analysis found at the start and end of every block of a function, as text.
+    Printed either as the function with comments around each block, or as json.
+*/
+pub struct DataFlowReport {
+    pub function_name: String,
+    // the header the annotated function is printed with, same as when printing the blocks
+    pub signature: String,
+    pub blocks: Vec<BlockReport>,
+}
+
+pub fn create_report(analysis_name: &str, cfg: &ControlFlowGraph) -> Option<DataFlowReport> {
+    let report = match analysis_name {
+        "reaching" => DataFlowReport::create(&ReachingDefinitions::new(cfg), cfg),
+        "live" => DataFlowReport::create(&LiveVariables::new(cfg), cfg),
+        "defined" => DataFlowReport::create(&DefinedVariables::new(cfg), cfg),
+        "constprop" => DataFlowReport::create(&ConstantPropagation::new(), cfg),
+        "available" => DataFlowReport::create(&AvailableExpressions::new(cfg), cfg),
+        "anticipated" => DataFlowReport::create(&AnticipatedExpressions::new(cfg), cfg),
+        "intervals" => DataFlowReport::create(&IntervalAnalysis::new(cfg), cfg),
+        _ => return None,
+    };
+
+    Some(report)
+}
+
+impl DataFlowReport {
+    pub fn create<A: ReportableAnalysis>(analysis: &A, cfg: &ControlFlowGraph) -> Self {
+        let results = super::solve(analysis, cfg);
+        let function = cfg.get_function();
+
+        let blocks = function
+            .get_blocks()
+            .iter()
+            .map(|block| BlockReport {
+                block_id: block.get_id(),
+                name: block.get_name(),
+                instrs: block.instrs.clone(),
+                facts_in: results
+                    .get_in(block.get_id())
+                    .map_or(vec![], |f| analysis.describe_fact(f)),
+                facts_out: results
+                    .get_out(block.get_id())
+                    .map_or(vec![], |f| analysis.describe_fact(f)),
+            })
+            .collect();
+
+        DataFlowReport {
+            function_name: function.get_name().clone(),
+            signature: function.get_signature(),
+            blocks,
+        }
+    }
+
+    // the function's instructions, with the facts as comments at the start and end of each block
+    pub fn to_annotated_string(&self) -> String {
+        let mut result = String::new();
+        writeln!(result, "{} {{", self.signature).unwrap();
+
+        for block in &self.blocks {
+            let mut instrs = block.instrs.iter().peekable();
+            if let Some(label) = instrs.next_if(|i| i.is_label()) {
+                write!(result, "{}", label).unwrap();
+            }
+
+            writeln!(result, "    // in: {}", block.facts_in.join(", ")).unwrap();
+            for instr in instrs {
+                write!(result, "{}", instr).unwrap();
+            }
+            writeln!(result, "    // out: {}", block.facts_out.join(", ")).unwrap();
+        }
+
+        writeln!(result, "}}").unwrap();
+        result
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut blocks = JsonValue::new_array();
+        for block in &self.blocks {
+            let mut block_json = JsonValue::new_object();
+            block_json["id"] = block.block_id.into();
+            block_json["name"] = block.name.clone().into();
+            block_json["in"] = block.facts_in.clone().into();
+            block_json["out"] = block.facts_out.clone().into();
+            blocks.push(block_json).unwrap();
+        }
+
+        let mut result = JsonValue::new_object();
+        result["name"] = self.function_name.clone().into();
+        result["blocks"] = blocks;
+        result
+    }
+}
+
+// all the reports for one analysis, e.g. one per function of a program
+pub fn reports_to_json(analysis_name: &str, reports: &[DataFlowReport]) -> JsonValue {
+    let mut functions = JsonValue::new_array();
+    for report in reports {
+        functions.push(report.to_json()).unwrap();
+    }
+
+    let mut result = JsonValue::new_object();
+    result["analysis"] = analysis_name.into();
+    result["functions"] = functions;
+    result
+}
+
+fn describe_all<T: ToString>(items: impl IntoIterator<Item = T>) -> Vec<String> {
+    items.into_iter().map(|i| i.to_string()).collect()
+}
+
+impl ReportableAnalysis for ReachingDefinitions<'_, '_> {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        describe_all(fact)
+    }
+}
+
+impl ReportableAnalysis for LiveVariables<'_, '_> {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        describe_all(fact)
+    }
+}
+
+impl ReportableAnalysis for DefinedVariables<'_, '_> {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        describe_all(fact)
+    }
+}
+
+impl ReportableAnalysis for AvailableExpressions<'_, '_> {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        describe_all(fact)
+    }
+}
+
+impl ReportableAnalysis for AnticipatedExpressions<'_, '_> {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        describe_all(fact)
+    }
+}
+
+// x = 4 for a constant, x = ? for a var that could have different values
+impl ReportableAnalysis for ConstantPropagation {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        fact.iter()
+            .filter_map(|(var, value)| match value {
+                ConstantValue::Undefined => None,
+                ConstantValue::Constant(c) => Some(format!("{} = {}", var, c)),
+                ConstantValue::NotConstant => Some(format!("{} = ?", var)),
+            })
+            .collect()
+    }
+}
+
+impl ReportableAnalysis for IntervalAnalysis<'_, '_> {
+    fn describe_fact(&self, fact: &Self::Fact) -> Vec<String> {
+        let Some(state) = fact else {
+            return vec!["unreachable".to_string()];
+        };
+
+        let mut ranges: Vec<_> = state
+            .get_int_ranges()
+            .iter()
+            .chain(state.get_bool_ranges())
+            .collect();
+        ranges.sort_by_key(|(var, _)| *var);
+
+        ranges
+            .into_iter()
+            .map(|(var, range)| format!("{}: {}", var, range))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::create_report;
+
+    #[test]
+    fn test_live_report() {
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_value(
+                OpCode::Add,
+                "y".to_string(),
+                Type::Int,
+                vec!["x".to_string(), "x".to_string()],
+                vec![],
+                vec![],
+            ),
+            Instruction::new_effect(OpCode::Print, vec!["y".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("x".to_string(), Type::Int)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let report = create_report("live", &cfg).unwrap();
+        assert_eq!(
+            report.to_annotated_string(),
+            "@main(x: int) {\n.entry:\n    // in: x\n    y: int = add x x  \n    print y  \n    // out: \n}\n"
+        );
+        assert_eq!(
+            report.to_json().dump(),
+            r#"{"name":"main","blocks":[{"id":0,"name":"entry","in":["x"],"out":[]}]}"#
+        );
+        assert!(create_report("nope", &cfg).is_none());
+    }
+}
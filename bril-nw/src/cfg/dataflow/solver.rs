@@ -0,0 +1,202 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::cfg::ControlFlowGraph;
+
+use super::{DataFlowAnalysis, Direction};
+
+/*
+    Facts at the start and end of every block, in program order: "in" is the fact before a block's
+    first instruction and "out" the fact after its last, whatever the direction of the analysis.
+    Facts at single instructions are recomputed from these on demand.
+*/
+#[derive(Debug)]
+pub struct DataFlowResults<F> {
+    ins: HashMap<usize, F>,
+    outs: HashMap<usize, F>,
+}
+
+impl<F: Clone> DataFlowResults<F> {
+    pub fn get_in(&self, block_id: usize) -> Option<&F> {
+        self.ins.get(&block_id)
+    }
+
+    pub fn get_out(&self, block_id: usize) -> Option<&F> {
+        self.outs.get(&block_id)
+    }
+
+    // the fact at every point in the block, in program order. point i is right before instruction
+    // i, and the last point is after the last instruction, so there's one more point than there
+    // are instructions
+    pub fn get_instr_facts<A: DataFlowAnalysis<Fact = F>>(
+        &self,
+        analysis: &A,
+        cfg: &ControlFlowGraph,
+        block_id: usize,
+    ) -> Option<Vec<F>> {
+        let block = cfg.get_function().get_block_by_id(block_id)?;
+        let instrs = block.instrs.iter().enumerate();
+
+        match analysis.direction() {
+            Direction::Forward => {
+                let mut fact = self.get_in(block_id)?.clone();
+                let mut result = vec![fact.clone()];
+                for (idx, instr) in instrs {
+                    analysis.transfer_instr(block_id, idx, instr, &mut fact);
+                    result.push(fact.clone());
+                }
+
+                Some(result)
+            }
+            Direction::Backward => {
+                let mut fact = self.get_out(block_id)?.clone();
+                let mut result = vec![fact.clone()];
+                for (idx, instr) in instrs.rev() {
+                    analysis.transfer_instr(block_id, idx, instr, &mut fact);
+                    result.push(fact.clone());
+                }
+
+                result.reverse();
+                Some(result)
+            }
+        }
+    }
+}
+
+/*
+    Iterates the analysis to a fixpoint with a worklist.
+
+    Blocks are visited in reverse postorder for forward problems, and in reverse postorder of the
+    reversed cfg for backward problems, so a block is mostly visited after the blocks it gets its
+    facts from. The worklist always hands out the earliest block in that order, and a block is only
+    put back when a fact flowing into it changed. Narrowing passes (if any) go over all blocks in
+    the same order.
+
+    Blocks that the traversal can't reach (unreachable code for forward problems) are still
+    solved, after everything else, so every block has facts.
+*/
+pub fn solve<A: DataFlowAnalysis>(
+    analysis: &A,
+    cfg: &ControlFlowGraph,
+) -> DataFlowResults<A::Fact> {
+    let direction = analysis.direction();
+
+    let mut order: Vec<usize> = match direction {
+        Direction::Forward => cfg.reverse_postorder().collect(),
+        Direction::Backward => {
+            let mut order: Vec<usize> = cfg.reverse_cfg_postorder().collect();
+            order.reverse();
+            order
+        }
+    };
+    let ordered: HashSet<usize> = order.iter().copied().collect();
+    let mut unordered: Vec<usize> = cfg
+        .get_all_block_ids()
+        .iter()
+        .copied()
+        .filter(|id| !ordered.contains(id))
+        .collect();
+    unordered.sort_unstable();
+    order.extend(unordered);
+
+    let positions: HashMap<usize, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(position, id)| (*id, position))
+        .collect();
+
+    let init = analysis.init(cfg);
+    let boundary = analysis.boundary(cfg);
+
+    // facts flowing into blocks (in for forward, out for backward) and facts flowing out of them
+    let mut inputs: HashMap<usize, A::Fact> = HashMap::new();
+    let mut outputs: HashMap<usize, A::Fact> = order.iter().map(|id| (*id, init.clone())).collect();
+
+    let mut work_list: BTreeSet<usize> = (0..order.len()).collect();
+    while let Some(position) = work_list.pop_first() {
+        let block_id = order[position];
+
+        let mut input = merge_inputs(analysis, cfg, block_id, &init, &boundary, &outputs);
+        if let Some(previous) = inputs.get(&block_id) {
+            analysis.widen(block_id, previous, &mut input);
+        }
+
+        // transfer
+        let mut output = input.clone();
+        let block = cfg.get_function().get_block_by_id(block_id).unwrap();
+        analysis.transfer_block(block, &mut output);
+        inputs.insert(block_id, input);
+
+        if outputs[&block_id] == output {
+            continue;
+        }
+        outputs.insert(block_id, output);
+
+        let dependents = match direction {
+            Direction::Forward => cfg.get_successors(block_id),
+            Direction::Backward => cfg.get_predecessors(block_id),
+        };
+        work_list.extend(dependents.iter().map(|id| positions[id]));
+    }
+
+    for _ in 0..analysis.narrowing_passes() {
+        for block_id in &order {
+            let mut input = merge_inputs(analysis, cfg, *block_id, &init, &boundary, &outputs);
+            analysis.narrow(&inputs[block_id], &mut input);
+
+            let mut output = input.clone();
+            let block = cfg.get_function().get_block_by_id(*block_id).unwrap();
+            analysis.transfer_block(block, &mut output);
+
+            inputs.insert(*block_id, input);
+            outputs.insert(*block_id, output);
+        }
+    }
+
+    match direction {
+        Direction::Forward => DataFlowResults {
+            ins: inputs,
+            outs: outputs,
+        },
+        Direction::Backward => DataFlowResults {
+            ins: outputs,
+            outs: inputs,
+        },
+    }
+}
+
+// the fact flowing into a block: the merge of what flows out of its neighbours along each edge
+fn merge_inputs<A: DataFlowAnalysis>(
+    analysis: &A,
+    cfg: &ControlFlowGraph,
+    block_id: usize,
+    init: &A::Fact,
+    boundary: &A::Fact,
+    outputs: &HashMap<usize, A::Fact>,
+) -> A::Fact {
+    let mut input = init.clone();
+    let neighbours = match analysis.direction() {
+        Direction::Forward => {
+            if block_id == cfg.get_entry_block_id() {
+                analysis.merge(&mut input, boundary);
+            }
+            cfg.get_predecessors(block_id)
+        }
+        Direction::Backward => {
+            if cfg.get_successors(block_id).is_empty() {
+                analysis.merge(&mut input, boundary);
+            }
+            cfg.get_successors(block_id)
+        }
+    };
+
+    for neighbour in neighbours {
+        let mut fact = outputs[neighbour].clone();
+        match analysis.direction() {
+            Direction::Forward => analysis.transfer_edge(cfg, *neighbour, block_id, &mut fact),
+            Direction::Backward => analysis.transfer_edge(cfg, block_id, *neighbour, &mut fact),
+        }
+        analysis.merge(&mut input, &fact);
+    }
+
+    input
+}
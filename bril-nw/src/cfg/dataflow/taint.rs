@@ -0,0 +1,517 @@
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::{Instruction, OpCode},
+    cfg::ControlFlowGraph,
+    points_to::PointsToAnalysis,
+};
+
+use super::{
+    def_use::DefUseChains,
+    reaching_definitions::{Definition, DefinitionSite, InstrLocation},
+};
+
+// why taint moves from one instruction to the next
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Dependence {
+    // the instruction uses the var, as defined by the previous one (or the source arg)
+    Data(String),
+    // the previous instruction is a br deciding whether the instruction runs
+    Control,
+    // the instruction loads from memory the previous one may have stored to
+    Memory,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaintStep {
+    pub location: InstrLocation,
+    pub dependence: Dependence,
+}
+
+// a source reaching a sink. the steps end at the sink
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaintFlow {
+    pub source: String,
+    pub sink: InstrLocation,
+    pub steps: Vec<TaintStep>,
+}
+
+/*
+    Information flow from function args (the sources) to instructions with given opcodes (the
+    sinks), e.g. whether anything printed or returned depends on a secret arg.
+
+    An instruction is tainted if it
+    - uses a var whose reaching definition is a source or a tainted instruction (def-use chains)
+    - is in a block control dependent on a tainted br, since the branch taken decides whether it
+      runs at all
+    - loads from memory a tainted store may have written (points-to analysis)
+
+    Taint is followed breadth-first from each source separately, so every flow reported is a
+    shortest chain of dependences from that source to the sink. Control dependence only covers
+    whether an instruction runs: leaking a secret by never returning (e.g. looping forever on one
+    branch) isn't tracked.
+*/
+pub struct TaintAnalysis {
+    // names of the function's args
+    sources: BTreeSet<String>,
+    sinks: HashSet<OpCode>,
+}
+
+impl TaintAnalysis {
+    pub fn new(sources: &[&str], sinks: &[OpCode]) -> Self {
+        TaintAnalysis {
+            sources: sources.iter().map(|source| source.to_string()).collect(),
+            sinks: sinks.iter().copied().collect(),
+        }
+    }
+
+    // ordered by source, then by sink
+    pub fn analyze(&self, cfg: &ControlFlowGraph) -> Vec<TaintFlow> {
+        let graph = DependenceGraph::create(cfg);
+
+        let function = cfg.get_function();
+        let mut result = Vec::new();
+        for source in &self.sources {
+            if function.get_args().iter().any(|arg| &arg.name == source) {
+                result.extend(self.find_flows(&graph, source));
+            }
+        }
+
+        result
+    }
+
+    fn find_flows(&self, graph: &DependenceGraph, source: &str) -> Vec<TaintFlow> {
+        // location -> where the taint came from, and why
+        let mut parents: HashMap<InstrLocation, (Option<InstrLocation>, Dependence)> =
+            HashMap::new();
+        let mut open_set: VecDeque<InstrLocation> = VecDeque::new();
+
+        let definition = Definition {
+            var: source.to_string(),
+            site: DefinitionSite::Arg,
+        };
+        for location in graph.chains.get_uses(&definition) {
+            parents.insert(location, (None, Dependence::Data(source.to_string())));
+            open_set.push_back(location);
+        }
+
+        let mut sinks = BTreeSet::new();
+        while let Some(location) = open_set.pop_front() {
+            let instr = graph.get_instr(location);
+            if instr
+                .get_op_code()
+                .is_some_and(|op| self.sinks.contains(&op))
+            {
+                sinks.insert(location);
+            }
+
+            for (next, dependence) in graph.get_dependents(location) {
+                if let Entry::Vacant(entry) = parents.entry(next) {
+                    entry.insert((Some(location), dependence));
+                    open_set.push_back(next);
+                }
+            }
+        }
+
+        sinks
+            .into_iter()
+            .map(|sink| {
+                let mut steps = Vec::new();
+                let mut next = Some(sink);
+                while let Some(location) = next {
+                    let (parent, dependence) = parents[&location].clone();
+                    steps.push(TaintStep {
+                        location,
+                        dependence,
+                    });
+                    next = parent;
+                }
+                steps.reverse();
+
+                TaintFlow {
+                    source: source.to_string(),
+                    sink,
+                    steps,
+                }
+            })
+            .collect()
+    }
+}
+
+impl TaintFlow {
+    // the source, then one instruction per line with why it's tainted
+    pub fn to_annotated_string(&self, function: &FunctionBlocks) -> String {
+        let get_instr = |location: InstrLocation| {
+            let block = function.get_block_by_id(location.block_id).unwrap();
+            block.instrs[location.instr_idx]
+                .to_string()
+                .trim()
+                .to_string()
+        };
+
+        let mut result = String::new();
+        writeln!(result, "{} reaches `{}`", self.source, get_instr(self.sink)).unwrap();
+        for step in &self.steps {
+            let reason = match &step.dependence {
+                Dependence::Data(var) => format!("uses {}", var),
+                Dependence::Control => "runs depending on the branch".to_string(),
+                Dependence::Memory => "loads what was stored".to_string(),
+            };
+
+            writeln!(result, "    {}  // {}", get_instr(step.location), reason).unwrap();
+        }
+
+        result
+    }
+}
+
+// everything taint can flow along in one function
+struct DependenceGraph<'a> {
+    function: &'a FunctionBlocks,
+    chains: DefUseChains,
+    // branch block id -> instructions in the blocks it decides
+    controlled: HashMap<usize, Vec<InstrLocation>>,
+    points_to: PointsToAnalysis,
+    loads: Vec<InstrLocation>,
+}
+
+impl<'a> DependenceGraph<'a> {
+    fn create(cfg: &'a ControlFlowGraph) -> Self {
+        let function = cfg.get_function();
+        let post_dominators = cfg.find_post_dominators();
+        let control_dependence = cfg.create_control_dependence_graph(&post_dominators);
+
+        let mut controlled: HashMap<usize, Vec<InstrLocation>> = HashMap::new();
+        let mut loads = Vec::new();
+        for block_id in cfg.get_all_block_ids() {
+            let locations: Vec<InstrLocation> = function
+                .get_block_by_id(*block_id)
+                .unwrap()
+                .instrs
+                .iter()
+                .enumerate()
+                .filter(|(_, instr)| instr.is_instr())
+                .map(|(instr_idx, _)| InstrLocation {
+                    block_id: *block_id,
+                    instr_idx,
+                })
+                .collect();
+
+            for controller in control_dependence.get_controlling_blocks(*block_id) {
+                controlled
+                    .entry(controller)
+                    .or_default()
+                    .extend(locations.iter().copied());
+            }
+
+            loads.extend(locations.into_iter().filter(|location| {
+                let block = function.get_block_by_id(location.block_id).unwrap();
+                block.instrs[location.instr_idx].get_op_code() == Some(OpCode::Load)
+            }));
+        }
+
+        DependenceGraph {
+            function,
+            chains: DefUseChains::create(cfg),
+            controlled,
+            points_to: PointsToAnalysis::create(function),
+            loads,
+        }
+    }
+
+    fn get_instr(&self, location: InstrLocation) -> &Instruction {
+        let block = self.function.get_block_by_id(location.block_id).unwrap();
+        &block.instrs[location.instr_idx]
+    }
+
+    // the instructions tainted by the one at location
+    fn get_dependents(&self, location: InstrLocation) -> Vec<(InstrLocation, Dependence)> {
+        let instr = self.get_instr(location);
+        let mut result = Vec::new();
+
+        if let Some(dest) = instr.get_dest() {
+            let definition = Definition {
+                var: dest.to_string(),
+                site: DefinitionSite::Instr(location),
+            };
+            for next in self.chains.get_uses(&definition) {
+                result.push((next, Dependence::Data(dest.to_string())));
+            }
+        }
+
+        match instr.get_op_code() {
+            Some(OpCode::Branch) => {
+                for next in self
+                    .controlled
+                    .get(&location.block_id)
+                    .into_iter()
+                    .flatten()
+                {
+                    result.push((*next, Dependence::Control));
+                }
+            }
+            Some(OpCode::Store) => {
+                let ptr = &instr.get_args_copy()[0];
+                for next in &self.loads {
+                    let load_ptr = &self.get_instr(*next).get_args_copy()[0];
+                    if self.points_to.may_alias(ptr, load_ptr) {
+                        result.push((*next, Dependence::Memory));
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::{loader::load_bril, types::OpCode},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::{Dependence, InstrLocation, TaintAnalysis, TaintFlow, TaintStep};
+
+    // secret decides which constant is printed, and is stored and loaded back before being
+    // returned. public only reaches the print
+    const PROGRAM: &str = r#"{
+        "functions": [
+            {
+                "name": "check",
+                "args": [{ "name": "secret", "type": "int" }, { "name": "public", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "const", "dest": "zero", "type": "int", "value": 0 },
+                    { "op": "lt", "dest": "neg", "type": "bool", "args": ["secret", "zero"] },
+                    { "op": "br", "args": ["neg"], "labels": ["yes", "no"] },
+                    { "label": "yes" },
+                    { "op": "const", "dest": "r", "type": "int", "value": 1 },
+                    { "op": "jmp", "labels": ["end"] },
+                    { "label": "no" },
+                    { "op": "id", "dest": "r", "type": "int", "args": ["public"] },
+                    { "label": "end" },
+                    { "op": "print", "args": ["r"] },
+                    { "op": "const", "dest": "one", "type": "int", "value": 1 },
+                    { "op": "alloc", "dest": "p", "type": { "ptr": "int" }, "args": ["one"] },
+                    { "op": "store", "args": ["p", "secret"] },
+                    { "op": "load", "dest": "v", "type": "int", "args": ["p"] },
+                    { "op": "free", "args": ["p"] },
+                    { "op": "ret", "args": ["v"] }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_taint() {
+        let program = load_bril(PROGRAM).unwrap();
+        let mut function = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut function);
+
+        let analysis = TaintAnalysis::new(&["secret", "public"], &[OpCode::Print, OpCode::Ret]);
+        let flows = analysis.analyze(&cfg);
+
+        let summary: Vec<(&str, Option<OpCode>)> = flows
+            .iter()
+            .map(|flow| {
+                let last = flow.steps.last().unwrap();
+                let block = cfg.get_function().get_block_by_id(last.location.block_id);
+                let op = block.unwrap().instrs[last.location.instr_idx].get_op_code();
+                (flow.source.as_str(), op)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("public", Some(OpCode::Print)),
+                ("secret", Some(OpCode::Print)),
+                ("secret", Some(OpCode::Ret)),
+            ]
+        );
+
+        // lt, br, then the const 1 only runs on one side of the branch
+        let dependences: Vec<&Dependence> = flows[1].steps.iter().map(|s| &s.dependence).collect();
+        assert_eq!(
+            dependences,
+            vec![
+                &Dependence::Data("secret".to_string()),
+                &Dependence::Data("neg".to_string()),
+                &Dependence::Control,
+                &Dependence::Data("r".to_string()),
+            ]
+        );
+
+        let dependences: Vec<&Dependence> = flows[2].steps.iter().map(|s| &s.dependence).collect();
+        assert_eq!(
+            dependences,
+            vec![
+                &Dependence::Data("secret".to_string()),
+                &Dependence::Memory,
+                &Dependence::Data("v".to_string()),
+            ]
+        );
+
+        let text = flows[2].to_annotated_string(cfg.get_function());
+        assert!(text.starts_with("secret reaches `ret v"));
+    }
+
+    // flows from secret to print in the first function of program, with the function's text form
+    fn find_print_flows(program: &str) -> (Vec<TaintFlow>, String) {
+        let program = load_bril(program).unwrap();
+        let mut function = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut function);
+
+        let flows = TaintAnalysis::new(&["secret"], &[OpCode::Print]).analyze(&cfg);
+        let text = flows
+            .iter()
+            .map(|flow| flow.to_annotated_string(cfg.get_function()))
+            .collect();
+
+        (flows, text)
+    }
+
+    fn step(block_id: usize, instr_idx: usize, dependence: Dependence) -> TaintStep {
+        TaintStep {
+            location: InstrLocation {
+                block_id,
+                instr_idx,
+            },
+            dependence,
+        }
+    }
+
+    #[test]
+    fn test_taint_implicit_flow() {
+        // only constants are printed, but whether the print runs at all depends on secret
+        let (flows, text) = find_print_flows(
+            r#"{
+                "functions": [
+                    {
+                        "name": "main",
+                        "args": [{ "name": "secret", "type": "bool" }],
+                        "instrs": [
+                            { "label": "entry" },
+                            { "op": "br", "args": ["secret"], "labels": ["yes", "end"] },
+                            { "label": "yes" },
+                            { "op": "const", "dest": "c", "type": "int", "value": 1 },
+                            { "op": "print", "args": ["c"] },
+                            { "label": "end" },
+                            { "op": "ret" }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        assert_eq!(flows.len(), 1);
+        assert_eq!(
+            flows[0].steps,
+            vec![
+                step(0, 1, Dependence::Data("secret".to_string())),
+                step(1, 2, Dependence::Control),
+            ]
+        );
+        assert_eq!(
+            text,
+            "secret reaches `print c`\n    \
+                br secret .yes .end  // uses secret\n    \
+                print c  // runs depending on the branch\n"
+        );
+    }
+
+    #[test]
+    fn test_taint_not_control_dependent() {
+        // both sides of the branch on secret meet again before the print, so it always runs
+        let (flows, _) = find_print_flows(
+            r#"{
+                "functions": [
+                    {
+                        "name": "main",
+                        "args": [{ "name": "secret", "type": "bool" }],
+                        "instrs": [
+                            { "label": "entry" },
+                            { "op": "br", "args": ["secret"], "labels": ["yes", "no"] },
+                            { "label": "yes" },
+                            { "op": "jmp", "labels": ["end"] },
+                            { "label": "no" },
+                            { "op": "jmp", "labels": ["end"] },
+                            { "label": "end" },
+                            { "op": "const", "dest": "c", "type": "int", "value": 1 },
+                            { "op": "print", "args": ["c"] }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        assert_eq!(flows, vec![]);
+    }
+
+    #[test]
+    fn test_taint_reported_path() {
+        // the shortest chain goes through y, not through the longer detour over z
+        let (flows, text) = find_print_flows(
+            r#"{
+                "functions": [
+                    {
+                        "name": "main",
+                        "args": [{ "name": "secret", "type": "int" }],
+                        "instrs": [
+                            { "label": "entry" },
+                            { "op": "const", "dest": "one", "type": "int", "value": 1 },
+                            { "op": "add", "dest": "y", "type": "int", "args": ["secret", "one"] },
+                            { "op": "mul", "dest": "z", "type": "int", "args": ["secret", "y"] },
+                            { "op": "add", "dest": "w", "type": "int", "args": ["y", "z"] },
+                            { "op": "print", "args": ["y"] },
+                            { "op": "print", "args": ["w"] }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        assert_eq!(
+            flows.iter().map(|flow| flow.sink).collect::<Vec<_>>(),
+            vec![
+                InstrLocation {
+                    block_id: 0,
+                    instr_idx: 5
+                },
+                InstrLocation {
+                    block_id: 0,
+                    instr_idx: 6
+                },
+            ]
+        );
+        assert_eq!(
+            flows[1].steps,
+            vec![
+                step(0, 2, Dependence::Data("secret".to_string())),
+                step(0, 4, Dependence::Data("y".to_string())),
+                step(0, 6, Dependence::Data("w".to_string())),
+            ]
+        );
+        assert_eq!(
+            text,
+            "secret reaches `print y`\n    \
+                y: int = add secret one  // uses secret\n    \
+                print y  // uses y\n\
+            secret reaches `print w`\n    \
+                y: int = add secret one  // uses secret\n    \
+                w: int = add y z  // uses y\n    \
+                print w  // uses w\n"
+        );
+    }
+}
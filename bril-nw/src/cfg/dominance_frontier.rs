@@ -0,0 +1,182 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{ControlFlowGraph, DominatorTree};
+
+/*
+    The dominance frontier of a block B is the set of blocks where B's dominance stops: blocks Y
+    with a predecessor dominated by B where B doesn't strictly dominate Y itself. A block can be in
+    its own frontier, e.g. a loop header is in its own frontier through the back edge.
+
+    All frontiers are computed together, bottom-up over the dominator tree (Cytron et al.):
+
+        DF(X) = DF_local(X) U DF_up(Z) for each child Z of X
+        DF_local(X) = { Y in successors(X) | idom(Y) != X }
+        DF_up(Z) = { Y in DF(Z) | idom(Y) != X }
+
+    Walking the tree in postorder means every child is done before its parent.
+*/
+#[derive(Debug, Default)]
+pub struct DominanceFrontiers {
+    frontiers: HashMap<usize, BTreeSet<usize>>,
+}
+
+impl DominanceFrontiers {
+    pub fn get(&self, block_id: usize) -> Option<&BTreeSet<usize>> {
+        self.frontiers.get(&block_id)
+    }
+
+    // the iterated dominance frontier: the closure of DF over a set of blocks. for a variable
+    // defined in def_blocks, these are exactly the blocks that need a phi node for it
+    pub fn get_iterated<I>(&self, def_blocks: I) -> BTreeSet<usize>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut result = BTreeSet::new();
+        let mut open_set: Vec<usize> = def_blocks.into_iter().collect();
+
+        while let Some(block_id) = open_set.pop() {
+            for frontier_block_id in self.get(block_id).into_iter().flatten() {
+                if result.insert(*frontier_block_id) {
+                    open_set.push(*frontier_block_id);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    // the frontier of a single block, straight from the definition: successors of blocks in its
+    // subtree that it doesn't strictly dominate
+    pub fn get_dominance_frontier(
+        &self,
+        dominator_tree: &DominatorTree,
+        block_id: usize,
+    ) -> BTreeSet<usize> {
+        dominator_tree
+            .dominated_by(block_id)
+            .flat_map(|dominated| self.get_successors(dominated).iter().copied())
+            .filter(|successor| !dominator_tree.strictly_dominates(block_id, *successor))
+            .collect()
+    }
+
+    // the frontiers of every block at once. not cached, since the tree and the cfg can change
+    // independently of each other
+    pub fn find_dominance_frontiers(&self, dominator_tree: &DominatorTree) -> DominanceFrontiers {
+        let mut frontiers: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+
+        for block_id in dominator_tree.postorder() {
+            let mut frontier = BTreeSet::new();
+
+            for successor in self.get_successors(block_id) {
+                if dominator_tree.idom(*successor) != Some(block_id) {
+                    frontier.insert(*successor);
+                }
+            }
+
+            for child in dominator_tree.children(block_id) {
+                for frontier_block_id in &frontiers[child] {
+                    if dominator_tree.idom(*frontier_block_id) != Some(block_id) {
+                        frontier.insert(*frontier_block_id);
+                    }
+                }
+            }
+
+            frontiers.insert(block_id, frontier);
+        }
+
+        DominanceFrontiers { frontiers }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::cfg::{
+        dominator_update::{CfgEdit, DominatorUpdateConfig},
+        test_util::get_mock_function_blocks,
+        ControlFlowGraph,
+    };
+
+    // 0 -> 1, loop 1 -> 2 -> {3, 4} -> 5 -> 1, exiting from 1 to 6
+    fn get_test_cfg_edges() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([
+            (0, vec![1]),
+            (1, vec![2, 6]),
+            (2, vec![3, 4]),
+            (3, vec![5]),
+            (4, vec![5]),
+            (5, vec![1]),
+        ])
+    }
+
+    #[test]
+    fn test_dominance_frontiers_loop() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges(),
+            vec![0, 1, 2, 3, 4, 5, 6],
+        );
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let frontiers = cfg.find_dominance_frontiers(&dominator_tree);
+
+        assert_eq!(frontiers.get(0), Some(&BTreeSet::new()));
+        // the loop header is in its own frontier through the back edge 5 -> 1
+        assert_eq!(frontiers.get(1), Some(&BTreeSet::from([1])));
+        assert_eq!(frontiers.get(2), Some(&BTreeSet::from([1])));
+        assert_eq!(frontiers.get(3), Some(&BTreeSet::from([5])));
+        assert_eq!(frontiers.get(5), Some(&BTreeSet::from([1])));
+        assert_eq!(frontiers.get(6), Some(&BTreeSet::new()));
+    }
+
+    #[test]
+    fn test_iterated_dominance_frontier() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges(),
+            vec![0, 1, 2, 3, 4, 5, 6],
+        );
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let frontiers = cfg.find_dominance_frontiers(&dominator_tree);
+
+        // a definition in 3 needs a phi at the join 5, which in turn needs one at the header 1
+        assert_eq!(frontiers.get_iterated([3]), BTreeSet::from([1, 5]));
+        assert_eq!(frontiers.get_iterated([0, 6]), BTreeSet::new());
+    }
+
+    #[test]
+    fn test_dominance_frontiers_after_edit() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let mut cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges(),
+            vec![0, 1, 2, 3, 4, 5, 6],
+        );
+        let mut dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        assert_eq!(
+            cfg.find_dominance_frontiers(&dominator_tree).get(3),
+            Some(&BTreeSet::from([5]))
+        );
+
+        // 3 can now leave the loop on its own, so 6 joins its frontier
+        cfg.apply_edit_and_update_dominators(
+            &mut dominator_tree,
+            CfgEdit::InsertEdge(3, 6),
+            &DominatorUpdateConfig::default(),
+        );
+
+        let frontiers = cfg.find_dominance_frontiers(&dominator_tree);
+        assert_eq!(frontiers.get(3), Some(&BTreeSet::from([5, 6])));
+        assert_eq!(frontiers.get(6), Some(&BTreeSet::new()));
+        for block_id in 0..7 {
+            assert_eq!(
+                frontiers.get(block_id),
+                Some(&cfg.get_dominance_frontier(&dominator_tree, block_id))
+            );
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use super::graph::ImmediateDominators;
+
+/*
+    Each block's parent in the tree is its immediate dominator, so A dominates B exactly when A is
+    an ancestor of B (or B itself).
+
+    The tree gets numbered by a depth-first walk when it's built. A is an ancestor of B iff A is
+    entered before B and left after B, which makes dominance queries two comparisons instead of a
+    walk up the tree.
+
+    Children are always kept sorted by block id so walks over the tree are deterministic.
+*/
+#[derive(Debug)]
+pub struct DominatorTree {
+    root: usize,
+    immediate_dominators: ImmediateDominators,
+    children: HashMap<usize, Vec<usize>>,
+    preorder: Vec<usize>,
+    postorder: Vec<usize>,
+    // (preorder number, postorder number)
+    numbers: HashMap<usize, (usize, usize)>,
+    depths: HashMap<usize, usize>,
+}
+
+impl DominatorTree {
+    // immediate_dominators maps every block but the root to its immediate dominator
+    pub fn new(root: usize, immediate_dominators: ImmediateDominators) -> Self {
+        let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (block_id, immediate_dominator) in &immediate_dominators {
+            children
+                .entry(*immediate_dominator)
+                .or_default()
+                .push(*block_id);
+        }
+
+        for block_children in children.values_mut() {
+            block_children.sort_unstable();
+        }
+
+        let mut tree = DominatorTree {
+            root,
+            immediate_dominators,
+            children,
+            preorder: Vec::new(),
+            postorder: Vec::new(),
+            numbers: HashMap::new(),
+            depths: HashMap::new(),
+        };
+        tree.number_nodes();
+
+        tree
+    }
+
+    fn number_nodes(&mut self) {
+        self.renumber_subtree(self.root);
+    }
+
+    /*
+        Moves every block in changed under its new immediate dominator and renumbers subtree_root's
+        subtree. Every block in changed has to be in subtree_root's subtree both before and after,
+        so nothing outside of the subtree moves. Blocks not in the tree yet get added as leaves.
+    */
+    pub(super) fn update_immediate_dominators(
+        &mut self,
+        subtree_root: usize,
+        changed: HashMap<usize, usize>,
+    ) {
+        for (block_id, new_immediate_dominator) in changed {
+            let old_immediate_dominator = self
+                .immediate_dominators
+                .insert(block_id, new_immediate_dominator);
+            if old_immediate_dominator == Some(new_immediate_dominator) {
+                continue;
+            }
+
+            if let Some(old_immediate_dominator) = old_immediate_dominator {
+                let siblings = self.children.get_mut(&old_immediate_dominator).unwrap();
+                siblings.retain(|sibling| *sibling != block_id);
+            }
+
+            let siblings = self.children.entry(new_immediate_dominator).or_default();
+            let idx = siblings.binary_search(&block_id).unwrap_or_else(|idx| idx);
+            siblings.insert(idx, block_id);
+        }
+
+        self.renumber_subtree(subtree_root);
+    }
+
+    // a subtree is a contiguous run of both the preorder and the postorder, so renumbering one
+    // only replaces its runs. if the subtree changed size, everything after it shifts as well
+    fn renumber_subtree(&mut self, subtree_root: usize) {
+        let old_size = self.dominated_by(subtree_root).count();
+        let (pre_start, post_start) = self
+            .numbers
+            .get(&subtree_root)
+            .map_or((0, 0), |(pre, post)| (*pre, post + 1 - old_size));
+        let root_depth = self.depth(subtree_root).unwrap_or(0);
+
+        let mut preorder = vec![subtree_root];
+        let mut postorder = Vec::new();
+        self.depths.insert(subtree_root, root_depth);
+
+        // (block id, index of the next child to visit)
+        let mut stack: Vec<(usize, usize)> = vec![(subtree_root, 0)];
+        while let Some((block_id, next_child)) = stack.last().copied() {
+            match self.children(block_id).get(next_child).copied() {
+                Some(child) => {
+                    stack.last_mut().unwrap().1 += 1;
+
+                    preorder.push(child);
+                    self.depths.insert(child, root_depth + stack.len());
+                    stack.push((child, 0));
+                }
+                None => {
+                    stack.pop();
+                    postorder.push(block_id);
+                }
+            }
+        }
+
+        let new_size = preorder.len();
+        self.preorder
+            .splice(pre_start..pre_start + old_size, preorder);
+        self.postorder
+            .splice(post_start..post_start + old_size, postorder);
+
+        let (pre_end, post_end) = if new_size == old_size {
+            (pre_start + new_size, post_start + new_size)
+        } else {
+            (self.preorder.len(), self.postorder.len())
+        };
+
+        for pre in pre_start..pre_end {
+            self.numbers.entry(self.preorder[pre]).or_default().0 = pre;
+        }
+        for post in post_start..post_end {
+            self.numbers.entry(self.postorder[post]).or_default().1 = post;
+        }
+    }
+
+    pub fn get_root(&self) -> usize {
+        self.root
+    }
+
+    // whether the block is in the tree at all
+    pub fn contains(&self, block_id: usize) -> bool {
+        self.numbers.contains_key(&block_id)
+    }
+
+    // the root has no immediate dominator
+    pub fn idom(&self, block_id: usize) -> Option<usize> {
+        self.immediate_dominators.get(&block_id).copied()
+    }
+
+    pub fn get_immediate_dominators(&self) -> &ImmediateDominators {
+        &self.immediate_dominators
+    }
+
+    pub fn children(&self, block_id: usize) -> &[usize] {
+        self.children
+            .get(&block_id)
+            .map_or(&[], |children| children.as_slice())
+    }
+
+    // distance from the root, which has depth 0
+    pub fn depth(&self, block_id: usize) -> Option<usize> {
+        self.depths.get(&block_id).copied()
+    }
+
+    // domination is reflexive, so every block dominates itself
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        match (self.numbers.get(&a), self.numbers.get(&b)) {
+            (Some((a_pre, a_post)), Some((b_pre, b_post))) => a_pre <= b_pre && b_post <= a_post,
+            _ => false,
+        }
+    }
+
+    pub fn strictly_dominates(&self, a: usize, b: usize) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    // the deepest block dominating both a and b
+    pub fn nearest_common_dominator(&self, a: usize, b: usize) -> Option<usize> {
+        let mut a_depth = self.depth(a)?;
+        let mut b_depth = self.depth(b)?;
+        let (mut a, mut b) = (a, b);
+
+        while a_depth > b_depth {
+            a = self.immediate_dominators[&a];
+            a_depth -= 1;
+        }
+
+        while b_depth > a_depth {
+            b = self.immediate_dominators[&b];
+            b_depth -= 1;
+        }
+
+        while a != b {
+            a = self.immediate_dominators[&a];
+            b = self.immediate_dominators[&b];
+        }
+
+        Some(a)
+    }
+
+    // a block comes before every block it dominates
+    pub fn preorder(&self) -> impl Iterator<Item = usize> + '_ {
+        self.preorder.iter().copied()
+    }
+
+    // a block comes after every block it dominates
+    pub fn postorder(&self) -> impl Iterator<Item = usize> + '_ {
+        self.postorder.iter().copied()
+    }
+
+    // every block dominated by block_id, including block_id itself, in preorder
+    pub fn dominated_by(&self, block_id: usize) -> impl Iterator<Item = usize> + '_ {
+        // a subtree is a contiguous run of the preorder
+        let range = self.numbers.get(&block_id).map_or(0..0, |(pre, _)| {
+            let end = self.preorder[*pre..]
+                .iter()
+                .position(|b| !self.dominates(block_id, *b))
+                .map_or(self.preorder.len(), |offset| pre + offset);
+
+            *pre..end
+        });
+
+        self.preorder[range].iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::DominatorTree;
+
+    //       0
+    //      / \
+    //     1   5
+    //    / \
+    //   2   4
+    //   |
+    //   3
+    fn get_test_tree() -> DominatorTree {
+        DominatorTree::new(0, HashMap::from([(1, 0), (5, 0), (4, 1), (2, 1), (3, 2)]))
+    }
+
+    #[test]
+    fn test_dominator_tree_queries() {
+        let tree = get_test_tree();
+
+        assert_eq!(tree.get_root(), 0);
+        assert_eq!(tree.idom(0), None);
+        assert_eq!(tree.idom(3), Some(2));
+        assert_eq!(tree.children(1), &[2, 4]);
+        assert_eq!(tree.children(3), &[] as &[usize]);
+        assert_eq!(tree.depth(0), Some(0));
+        assert_eq!(tree.depth(3), Some(3));
+        assert_eq!(tree.depth(6), None);
+
+        assert!(tree.dominates(0, 3));
+        assert!(tree.dominates(1, 4));
+        assert!(tree.dominates(4, 4));
+        assert!(!tree.strictly_dominates(4, 4));
+        assert!(!tree.dominates(2, 4));
+        assert!(!tree.dominates(5, 3));
+        assert!(!tree.dominates(3, 1));
+
+        assert_eq!(tree.nearest_common_dominator(3, 4), Some(1));
+        assert_eq!(tree.nearest_common_dominator(3, 5), Some(0));
+        assert_eq!(tree.nearest_common_dominator(2, 3), Some(2));
+        assert_eq!(tree.nearest_common_dominator(2, 6), None);
+    }
+
+    #[test]
+    fn test_dominator_tree_walks() {
+        let tree = get_test_tree();
+
+        assert_eq!(
+            tree.preorder().collect::<Vec<usize>>(),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            tree.postorder().collect::<Vec<usize>>(),
+            vec![3, 2, 4, 1, 5, 0]
+        );
+        assert_eq!(
+            tree.dominated_by(1).collect::<Vec<usize>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(tree.dominated_by(5).collect::<Vec<usize>>(), vec![5]);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{ControlFlowGraph, DominatorTree};
+
+/*
+    Keeps a dominator tree up to date as the cfg changes, without recomputing it from scratch.
+
+    For an edge u -> v where both blocks are reachable, let d be the nearest common dominator of u
+    and v. Inserting or deleting the edge can only change the immediate dominators of blocks that d
+    strictly dominates:
+    - blocks outside of d's subtree are reached the same ways they were before, since any path
+      using u -> v already had to go through d
+    - blocks in d's subtree can only be entered through d, so d and everything above it still
+      dominate them
+
+    So only d's subtree gets recomputed, treating d as the entry and only looking at edges between
+    blocks in the subtree (Cooper, Harvey & Kennedy's iterative algorithm). The new immediate
+    dominators are patched into the tree in place and only d's subtree gets renumbered.
+
+    This only holds as long as the set of reachable blocks stays the same. Edits that make blocks
+    reachable or unreachable, or that touch unreachable blocks, fall back to a full recomputation.
+*/
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CfgEdit {
+    InsertEdge(usize, usize),
+    DeleteEdge(usize, usize),
+    // a new block with no edges yet
+    InsertBlock(usize),
+    // also deletes every edge into and out of the block
+    DeleteBlock(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct DominatorUpdateConfig {
+    // check every incremental update against a full recomputation, panicking if they disagree.
+    // slow, meant for debugging transformations
+    pub verify: bool,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn apply_edit(&mut self, edit: CfgEdit) {
+        match edit {
+            CfgEdit::InsertEdge(from, to) => self.add_edge(from, to),
+            CfgEdit::DeleteEdge(from, to) => {
+                self.remove_edge(from, to);
+            }
+            CfgEdit::InsertBlock(block_id) => self.add_block_id(block_id),
+            CfgEdit::DeleteBlock(block_id) => self.remove_block_id(block_id),
+        }
+    }
+
+    // applies the edit to the cfg and updates dominator_tree to match
+    pub fn apply_edit_and_update_dominators(
+        &mut self,
+        dominator_tree: &mut DominatorTree,
+        edit: CfgEdit,
+        config: &DominatorUpdateConfig,
+    ) {
+        // an edge between reachable blocks can't make anything reachable, and deleting one only
+        // makes blocks unreachable if its target is cut off
+        let updated = match edit {
+            CfgEdit::InsertEdge(from, to) if self.is_reachable(from) && self.is_reachable(to) => {
+                self.apply_edit(edit);
+                self.update_dominator_subtree(dominator_tree, from, to);
+                true
+            }
+            CfgEdit::DeleteEdge(from, to) if self.is_reachable(from) => {
+                self.apply_edit(edit);
+                let still_reachable = self.is_reachable(to);
+                if still_reachable {
+                    self.update_dominator_subtree(dominator_tree, from, to);
+                }
+                still_reachable
+            }
+            CfgEdit::InsertBlock(block_id) if !dominator_tree.contains(block_id) => {
+                self.apply_edit(edit);
+                // a block without predecessors hangs off the root, same as any unreachable block
+                let root = dominator_tree.get_root();
+                dominator_tree.update_immediate_dominators(root, HashMap::from([(block_id, root)]));
+                true
+            }
+            _ => {
+                self.apply_edit(edit);
+                false
+            }
+        };
+
+        if !updated {
+            *dominator_tree = self.create_dominator_tree(&self.find_dominators());
+        }
+
+        if config.verify {
+            let expected = self.create_dominator_tree(&self.find_dominators());
+            assert_eq!(
+                dominator_tree.get_immediate_dominators(),
+                expected.get_immediate_dominators(),
+                "incremental dominator tree update for {:?} disagrees with a full recomputation",
+                edit
+            );
+        }
+    }
+
+    // walks predecessors back from block_id until it finds the entry. this only looks at blocks
+    // that can reach block_id, rather than the whole function
+    fn is_reachable(&self, block_id: usize) -> bool {
+        let mut visited = HashSet::from([block_id]);
+        let mut open_set = vec![block_id];
+
+        while let Some(next) = open_set.pop() {
+            if next == self.get_entry_block_id() {
+                return true;
+            }
+
+            for pred in self.get_predecessors(next) {
+                if visited.insert(*pred) {
+                    open_set.push(*pred);
+                }
+            }
+        }
+
+        false
+    }
+
+    // recomputes the immediate dominators within the subtree of the nearest common dominator of
+    // from and to, and patches them into dominator_tree
+    fn update_dominator_subtree(&self, dominator_tree: &mut DominatorTree, from: usize, to: usize) {
+        let subtree_root = dominator_tree.nearest_common_dominator(from, to).unwrap();
+        // unreachable blocks hanging off the root are never visited, since no edge from a reachable
+        // block leads into them
+        let subtree: HashSet<usize> = dominator_tree.dominated_by(subtree_root).collect();
+
+        let postorder = self.postorder_within(subtree_root, &subtree);
+        let postorder_numbers: HashMap<usize, usize> = postorder
+            .iter()
+            .enumerate()
+            .map(|(number, block_id)| (*block_id, number))
+            .collect();
+
+        let intersect = |idoms: &HashMap<usize, usize>, mut a: usize, mut b: usize| -> usize {
+            while a != b {
+                while postorder_numbers[&a] < postorder_numbers[&b] {
+                    a = idoms[&a];
+                }
+                while postorder_numbers[&b] < postorder_numbers[&a] {
+                    b = idoms[&b];
+                }
+            }
+
+            a
+        };
+
+        let mut subtree_idoms: HashMap<usize, usize> =
+            HashMap::from([(subtree_root, subtree_root)]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for block_id in postorder.iter().rev().skip(1) {
+                let new_idom = self
+                    .get_predecessors(*block_id)
+                    .iter()
+                    .filter(|p| subtree_idoms.contains_key(p))
+                    .copied()
+                    .reduce(|a, b| intersect(&subtree_idoms, a, b))
+                    .unwrap();
+
+                if subtree_idoms.insert(*block_id, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+
+        subtree_idoms.remove(&subtree_root);
+        dominator_tree.update_immediate_dominators(subtree_root, subtree_idoms);
+    }
+
+    // postorder from root, only following edges between blocks in within
+    fn postorder_within(&self, root: usize, within: &HashSet<usize>) -> Vec<usize> {
+        let mut result = Vec::new();
+        let mut visited = HashSet::from([root]);
+
+        // (block id, index of the next successor to explore)
+        let mut stack: Vec<(usize, usize)> = vec![(root, 0)];
+        while let Some((block_id, next_successor)) = stack.last().copied() {
+            match self.get_successors(block_id).get(next_successor) {
+                Some(successor) => {
+                    stack.last_mut().unwrap().1 += 1;
+                    if within.contains(successor) && visited.insert(*successor) {
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => {
+                    stack.pop();
+                    result.push(block_id);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cfg::{
+        test_util::{get_loop_around_diamond_edges, get_mock_function_blocks},
+        ControlFlowGraph, DominatorTree,
+    };
+
+    use super::{CfgEdit, DominatorUpdateConfig};
+
+    #[test]
+    fn test_update_dominators_edges() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let mut cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_loop_around_diamond_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+        let mut dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let config = DominatorUpdateConfig { verify: true };
+
+        // 0 -> 4 means neither 1 nor the loop dominates 4 anymore
+        cfg.apply_edit_and_update_dominators(
+            &mut dominator_tree,
+            CfgEdit::InsertEdge(0, 4),
+            &config,
+        );
+        assert_eq!(dominator_tree.idom(4), Some(0));
+        assert_eq!(dominator_tree.idom(5), Some(4));
+
+        cfg.apply_edit_and_update_dominators(
+            &mut dominator_tree,
+            CfgEdit::DeleteEdge(0, 4),
+            &config,
+        );
+        assert_eq!(dominator_tree.idom(4), Some(1));
+
+        // 4 is now only reachable through 2
+        cfg.apply_edit_and_update_dominators(
+            &mut dominator_tree,
+            CfgEdit::DeleteEdge(3, 4),
+            &config,
+        );
+        assert_eq!(dominator_tree.idom(4), Some(2));
+        assert!(dominator_tree.dominates(2, 5));
+    }
+
+    #[test]
+    fn test_update_dominators_reachability_changes() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let mut cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_loop_around_diamond_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+        let mut dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let config = DominatorUpdateConfig { verify: true };
+
+        cfg.apply_edit_and_update_dominators(&mut dominator_tree, CfgEdit::InsertBlock(6), &config);
+        assert_eq!(dominator_tree.idom(6), Some(0));
+
+        cfg.apply_edit_and_update_dominators(
+            &mut dominator_tree,
+            CfgEdit::InsertEdge(5, 6),
+            &config,
+        );
+        assert_eq!(dominator_tree.idom(6), Some(5));
+
+        cfg.apply_edit_and_update_dominators(&mut dominator_tree, CfgEdit::DeleteBlock(3), &config);
+        assert!(!dominator_tree.contains(3));
+        assert_eq!(dominator_tree.idom(4), Some(2));
+    }
+
+    // the patched tree has to match a fresh one exactly, numbering included
+    fn assert_same_tree(actual: &DominatorTree, expected: &DominatorTree) {
+        assert_eq!(
+            actual.get_immediate_dominators(),
+            expected.get_immediate_dominators()
+        );
+        assert_eq!(
+            actual.preorder().collect::<Vec<usize>>(),
+            expected.preorder().collect::<Vec<usize>>()
+        );
+        assert_eq!(
+            actual.postorder().collect::<Vec<usize>>(),
+            expected.postorder().collect::<Vec<usize>>()
+        );
+
+        for a in expected.preorder() {
+            assert_eq!(actual.depth(a), expected.depth(a));
+            assert_eq!(
+                actual.dominated_by(a).collect::<Vec<usize>>(),
+                expected.dominated_by(a).collect::<Vec<usize>>()
+            );
+            for b in expected.preorder() {
+                assert_eq!(actual.dominates(a, b), expected.dominates(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_dominators_matches_recompute() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let mut cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_loop_around_diamond_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+        let mut dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+
+        let edits = [
+            CfgEdit::InsertEdge(2, 3),
+            CfgEdit::InsertEdge(0, 3),
+            CfgEdit::InsertBlock(6),
+            CfgEdit::InsertEdge(3, 6),
+            CfgEdit::InsertEdge(6, 5),
+            CfgEdit::DeleteEdge(1, 3),
+            CfgEdit::InsertEdge(5, 2),
+            CfgEdit::DeleteEdge(0, 3),
+            CfgEdit::DeleteEdge(4, 5),
+            CfgEdit::InsertEdge(1, 5),
+            CfgEdit::DeleteEdge(2, 3),
+            CfgEdit::InsertBlock(7),
+            CfgEdit::InsertEdge(7, 4),
+            CfgEdit::InsertEdge(1, 7),
+            CfgEdit::DeleteEdge(2, 4),
+            CfgEdit::DeleteBlock(3),
+            CfgEdit::InsertEdge(4, 2),
+            CfgEdit::DeleteEdge(1, 2),
+        ];
+
+        for edit in edits {
+            cfg.apply_edit_and_update_dominators(
+                &mut dominator_tree,
+                edit,
+                &DominatorUpdateConfig::default(),
+            );
+
+            let expected = cfg.create_dominator_tree(&cfg.find_dominators());
+            assert_same_tree(&dominator_tree, &expected);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Write,
+};
+
+use itertools::Itertools;
+
+use crate::{basicblock::FunctionBlocks, bril::types::OpCode};
+
+use super::{
+    post_dominators::PostDominatorTree, ControlFlowGraph, DominatorTree, LoopForest,
+    VIRTUAL_EXIT_BLOCK_ID,
+};
+
+// a group of blocks drawn inside a box. clusters nest, but must not otherwise overlap
+#[derive(Debug, Default)]
+pub struct DotCluster {
+    pub label: String,
+    pub blocks: BTreeSet<usize>,
+    pub children: Vec<DotCluster>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn to_dot(&self, show_instrs: bool) -> String {
+        self.to_dot_with_clusters(show_instrs, &[])
+    }
+
+    pub fn to_dot_with_clusters(&self, show_instrs: bool, clusters: &[DotCluster]) -> String {
+        let function = self.get_function();
+
+        let mut result = String::new();
+        writeln!(result, "digraph \"{}\" {{", escape(function.get_name())).unwrap();
+        writeln!(result, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for block_id in self.get_all_block_ids() {
+            let block = function.get_block_by_id(*block_id).unwrap();
+
+            let mut label = format!("{}\\l", escape(&block.get_name()));
+            if show_instrs {
+                for instr in block.instrs.iter().filter(|i| !i.is_label()) {
+                    // instruction display pads missing operands with spaces
+                    let instr_str = instr.to_string().split_whitespace().join(" ");
+                    label.push_str(&escape(&instr_str));
+                    label.push_str("\\l");
+                }
+            }
+
+            writeln!(result, "    {} [label=\"{}\"];", node_id(*block_id), label).unwrap();
+        }
+
+        let mut cluster_counter = 0;
+        for cluster in clusters {
+            write_cluster(&mut result, cluster, 1, &mut cluster_counter);
+        }
+
+        for block_id in self.get_all_block_ids() {
+            let is_branch = function
+                .get_block_by_id(*block_id)
+                .unwrap()
+                .instrs
+                .last()
+                .and_then(|i| i.get_op_code())
+                == Some(OpCode::Branch);
+
+            let successors = self.get_successors(*block_id);
+            for (i, successor) in successors.iter().enumerate() {
+                // br jumps to its first label when the condition holds
+                let edge_label = match (is_branch && successors.len() == 2, i) {
+                    (true, 0) => " [label=\"T\"]",
+                    (true, _) => " [label=\"F\"]",
+                    _ => "",
+                };
+
+                writeln!(
+                    result,
+                    "    {} -> {}{};",
+                    node_id(*block_id),
+                    node_id(*successor),
+                    edge_label
+                )
+                .unwrap();
+            }
+        }
+
+        result.push_str("}\n");
+        result
+    }
+}
+
+impl DominatorTree {
+    pub fn to_dot(&self, function: &FunctionBlocks) -> String {
+        let edges = self
+            .preorder()
+            .flat_map(|parent| {
+                self.children(parent)
+                    .iter()
+                    .map(move |child| (parent, *child))
+            })
+            .sorted()
+            .collect();
+
+        tree_to_dot(
+            &edges,
+            function,
+            &format!("{} dominator tree", function.get_name()),
+        )
+    }
+}
+
+impl PostDominatorTree {
+    pub fn to_dot(&self, function: &FunctionBlocks) -> String {
+        let edges = self
+            .preorder()
+            .flat_map(|parent| {
+                self.children(parent)
+                    .iter()
+                    .map(move |child| (parent, *child))
+            })
+            .sorted()
+            .collect();
+
+        tree_to_dot(
+            &edges,
+            function,
+            &format!("{} post-dominator tree", function.get_name()),
+        )
+    }
+}
+
+// one cluster per loop, nested the same way the loops are
+pub fn create_loop_clusters(
+    function: &FunctionBlocks,
+    loop_forest: &LoopForest,
+) -> Vec<DotCluster> {
+    fn create_loop_cluster(
+        function: &FunctionBlocks,
+        loop_forest: &LoopForest,
+        loop_id: usize,
+    ) -> DotCluster {
+        let natural_loop = loop_forest.get_loop(loop_id).unwrap();
+        let header_name = function
+            .get_block_name(natural_loop.get_header())
+            .unwrap_or_default();
+
+        DotCluster {
+            label: format!("loop {}", header_name),
+            blocks: natural_loop.get_body().clone(),
+            children: natural_loop
+                .get_children()
+                .iter()
+                .map(|child| create_loop_cluster(function, loop_forest, *child))
+                .collect(),
+        }
+    }
+
+    loop_forest
+        .get_top_level_loops()
+        .iter()
+        .map(|loop_id| create_loop_cluster(function, loop_forest, *loop_id))
+        .collect()
+}
+
+// a cluster with everything block_id dominates, and another with its dominance frontier
+pub fn create_dominance_frontier_clusters(
+    cfg: &ControlFlowGraph,
+    dominator_tree: &DominatorTree,
+    block_id: usize,
+) -> Vec<DotCluster> {
+    let block_name = cfg
+        .get_function()
+        .get_block_name(block_id)
+        .unwrap_or_default();
+
+    let dominated: BTreeSet<usize> = dominator_tree.dominated_by(block_id).collect();
+
+    let frontier: BTreeSet<usize> = cfg
+        .get_dominance_frontier(dominator_tree, block_id)
+        .difference(&dominated)
+        .copied()
+        .collect();
+
+    vec![
+        DotCluster {
+            label: format!("dominated by {}", block_name),
+            blocks: dominated,
+            children: vec![],
+        },
+        DotCluster {
+            label: format!("dominance frontier of {}", block_name),
+            blocks: frontier,
+            children: vec![],
+        },
+    ]
+}
+
+// edges are (parent, child) pairs, already sorted
+fn tree_to_dot(edges: &Vec<(usize, usize)>, function: &FunctionBlocks, graph_name: &str) -> String {
+    let block_label = |block_id: usize| -> String {
+        if block_id == VIRTUAL_EXIT_BLOCK_ID {
+            "exit".to_string()
+        } else {
+            function.get_block_name(block_id).unwrap_or_default()
+        }
+    };
+
+    let nodes: BTreeSet<usize> = edges
+        .iter()
+        .flat_map(|(parent, child)| [*parent, *child])
+        .collect();
+
+    let mut result = String::new();
+    writeln!(result, "digraph \"{}\" {{", escape(graph_name)).unwrap();
+    writeln!(result, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+    for node in &nodes {
+        writeln!(
+            result,
+            "    {} [label=\"{}\"];",
+            node_id(*node),
+            escape(&block_label(*node))
+        )
+        .unwrap();
+    }
+
+    for (parent, child) in edges {
+        writeln!(result, "    {} -> {};", node_id(*parent), node_id(*child)).unwrap();
+    }
+
+    result.push_str("}\n");
+    result
+}
+
+fn write_cluster(result: &mut String, cluster: &DotCluster, depth: usize, counter: &mut usize) {
+    let indent = "    ".repeat(depth);
+
+    writeln!(result, "{}subgraph cluster_{} {{", indent, counter).unwrap();
+    writeln!(
+        result,
+        "{}    label=\"{}\";",
+        indent,
+        escape(&cluster.label)
+    )
+    .unwrap();
+    *counter += 1;
+
+    // blocks belonging to a nested cluster are declared there instead
+    let nested_blocks: HashSet<usize> = cluster
+        .children
+        .iter()
+        .flat_map(|c| c.blocks.iter().copied())
+        .collect();
+    for block_id in cluster.blocks.iter().filter(|b| !nested_blocks.contains(b)) {
+        writeln!(result, "{}    {};", indent, node_id(*block_id)).unwrap();
+    }
+
+    for child in &cluster.children {
+        write_cluster(result, child, depth + 1, counter);
+    }
+
+    writeln!(result, "{}}}", indent).unwrap();
+}
+
+fn node_id(block_id: usize) -> String {
+    if block_id == VIRTUAL_EXIT_BLOCK_ID {
+        "exit".to_string()
+    } else {
+        format!("b{}", block_id)
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type},
+        cfg::{dot::create_loop_clusters, test_util::get_mock_function_blocks, ControlFlowGraph},
+    };
+
+    #[test]
+    fn test_cfg_dot() {
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["left".to_string(), "right".to_string()],
+            ),
+            Instruction::new_label("left"),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["exit".to_string()]),
+            Instruction::new_label("right"),
+            Instruction::new_label("exit"),
+            Instruction::new_effect(OpCode::Print, vec!["cond".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("cond".to_string(), Type::Bool)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+
+        let dot = cfg.to_dot(true);
+        assert!(dot.starts_with("digraph \"main\" {"));
+        assert!(dot.contains("    b0 [label=\"entry\\lbr cond .left .right\\l\"];"));
+        assert!(dot.contains("    b2 [label=\"right\\l\"];"));
+        assert!(dot.contains("    b0 -> b1 [label=\"T\"];"));
+        assert!(dot.contains("    b0 -> b2 [label=\"F\"];"));
+        assert!(dot.contains("    b2 -> b3;"));
+
+        let dot = cfg.to_dot(false);
+        assert!(dot.contains("    b3 [label=\"exit\\l\"];"));
+    }
+
+    #[test]
+    fn test_dominator_tree_dot() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let edges = HashMap::from([(0, vec![1, 2]), (1, vec![3]), (2, vec![3])]);
+        let cfg = ControlFlowGraph::create_from_edges(&mut mock_blocks, edges, vec![0, 1, 2, 3]);
+
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let dot = dominator_tree.to_dot(cfg.get_function());
+
+        let edges: Vec<&str> = dot.lines().filter(|l| l.contains("->")).collect();
+        assert_eq!(
+            edges,
+            vec!["    b0 -> b1;", "    b0 -> b2;", "    b0 -> b3;"]
+        );
+    }
+
+    #[test]
+    fn test_loop_clusters() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let edges = HashMap::from([
+            (0, vec![1]),
+            (1, vec![2]),
+            (2, vec![3]),
+            (3, vec![2, 4]),
+            (4, vec![1, 5]),
+        ]);
+        let cfg =
+            ControlFlowGraph::create_from_edges(&mut mock_blocks, edges, vec![0, 1, 2, 3, 4, 5]);
+
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let loop_forest = cfg.find_natural_loops(&dominator_tree);
+        let clusters = create_loop_clusters(cfg.get_function(), &loop_forest);
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].blocks, BTreeSet::from([1, 2, 3, 4]));
+        assert_eq!(clusters[0].children.len(), 1);
+        assert_eq!(clusters[0].children[0].blocks, BTreeSet::from([2, 3]));
+    }
+}
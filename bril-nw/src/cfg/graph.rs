@@ -1,14 +1,18 @@
-use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 
 use itertools::Itertools;
 
 use crate::basicblock::FunctionBlocks;
 
+use super::DominatorTree;
+
 #[derive(Debug)]
 pub struct ControlFlowGraph<'a> {
     pub predecessors: HashMap<usize, Vec<usize>>,
     pub successors: HashMap<usize, Vec<usize>>,
+    // the block the function starts in. see normalize::insert_entry_block for giving it no predecessors
+    entry: usize,
     all_block_ids: Vec<usize>,
     blocks: &'a mut FunctionBlocks,
 }
@@ -17,7 +21,6 @@ pub type Dominators = HashMap<usize, HashSet<usize>>;
 pub type StrictDominators = Dominators;
 
 pub type ImmediateDominators = HashMap<usize, usize>;
-pub struct DominatorTree(pub HashMap<usize, HashSet<usize>>);
 
 impl fmt::Display for ControlFlowGraph<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -87,32 +90,111 @@ impl<'a> ControlFlowGraph<'a> {
                 continue;
             } else if i < blocks.len() - 1 {
                 // not a jump or ret but last instr so just point to next basic block
-                successors.insert(blocks[i].get_id(), vec![i + 1]);
-
-                let next_idx = i + 1;
-                if predecessors.contains_key(&next_idx) {
-                    predecessors
-                        .get_mut(&next_idx)
-                        .unwrap()
-                        .push(blocks[i].get_id());
-                } else {
-                    predecessors.insert(next_idx, vec![blocks[i].get_id()]);
-                }
+                let next_id = blocks[i + 1].get_id();
+                successors.insert(blocks[i].get_id(), vec![next_id]);
+
+                predecessors
+                    .entry(next_id)
+                    .or_default()
+                    .push(blocks[i].get_id());
             }
         }
 
+        // execution starts at the first block of the function
+        let entry = all_block_ids.first().copied().unwrap_or(0);
+
         ControlFlowGraph {
             predecessors,
             successors,
+            entry,
             all_block_ids,
             blocks: function_blocks,
         }
     }
 
+    pub fn get_function(&self) -> &FunctionBlocks {
+        self.blocks
+    }
+
     pub fn get_mut_function(&mut self) -> &mut FunctionBlocks {
         self.blocks
     }
 
+    pub fn get_entry_block_id(&self) -> usize {
+        self.entry
+    }
+
+    pub fn get_all_block_ids(&self) -> &Vec<usize> {
+        &self.all_block_ids
+    }
+
+    pub fn get_successors(&self, block_id: usize) -> &[usize] {
+        self.successors.get(&block_id).map_or(&[], |s| s.as_slice())
+    }
+
+    pub fn get_predecessors(&self, block_id: usize) -> &[usize] {
+        self.predecessors
+            .get(&block_id)
+            .map_or(&[], |p| p.as_slice())
+    }
+
+    /*
+        The edits below only change the graph. The instructions in the underlying function are left
+        alone, so they are meant for transformations that rewrite the instructions themselves and
+        need the graph to keep up. Parallel edges (e.g. a br with the same label twice) are kept,
+        so an edge may need to be removed more than once before the blocks are disconnected.
+    */
+    pub fn add_edge(&mut self, from: usize, to: usize) {
+        self.successors.entry(from).or_default().push(to);
+        self.predecessors.entry(to).or_default().push(from);
+    }
+
+    // returns whether there was an edge to remove
+    pub fn remove_edge(&mut self, from: usize, to: usize) -> bool {
+        let successor_idx = self
+            .successors
+            .get(&from)
+            .and_then(|successors| successors.iter().position(|s| *s == to));
+        let Some(successor_idx) = successor_idx else {
+            return false;
+        };
+
+        self.successors
+            .get_mut(&from)
+            .unwrap()
+            .remove(successor_idx);
+
+        let predecessors = self.predecessors.get_mut(&to).unwrap();
+        let predecessor_idx = predecessors.iter().position(|p| *p == from).unwrap();
+        predecessors.remove(predecessor_idx);
+
+        true
+    }
+
+    // adds a block with no edges. does nothing if the block is already in the graph
+    pub fn add_block_id(&mut self, block_id: usize) {
+        if !self.all_block_ids.contains(&block_id) {
+            self.all_block_ids.push(block_id);
+        }
+    }
+
+    // removes a block along with every edge into or out of it
+    pub fn remove_block_id(&mut self, block_id: usize) {
+        for successor in self.successors.remove(&block_id).unwrap_or_default() {
+            if let Some(predecessors) = self.predecessors.get_mut(&successor) {
+                predecessors.retain(|p| *p != block_id);
+            }
+        }
+
+        for predecessor in self.predecessors.remove(&block_id).unwrap_or_default() {
+            if let Some(successors) = self.successors.get_mut(&predecessor) {
+                successors.retain(|s| *s != block_id);
+            }
+        }
+
+        self.all_block_ids.retain(|b| *b != block_id);
+    }
+
     pub fn find_dominators(&self) -> Dominators {
         let mut dominators: HashMap<usize, HashSet<usize>> = HashMap::new();
         let mut should_continue = true;
@@ -123,28 +205,42 @@ impl<'a> ControlFlowGraph<'a> {
             .copied()
             .collect::<HashSet<usize>>();
         for block_id in &self.all_block_ids {
-            if *block_id == 0 {
-                dominators.insert(0, HashSet::from([0]));
+            if *block_id == self.entry {
+                dominators.insert(self.entry, HashSet::from([self.entry]));
             } else {
                 dominators.insert(*block_id, all_block_ids_set.clone());
             }
         }
 
+        // traversing in reverse post-order is most optimal for well-behaved reducible cfgs.
+        // unreachable blocks go last
+        let mut visit_order: Vec<usize> = self.reverse_postorder().collect();
+        let reachable_block_ids: HashSet<usize> = visit_order.iter().copied().collect();
+        visit_order.extend(
+            self.all_block_ids
+                .iter()
+                .filter(|block_id| !reachable_block_ids.contains(block_id)),
+        );
+
         while should_continue {
             should_continue = false;
 
-            // traversing in reverse post-order is most optimal for well-behaved reducible cfgs
-            // but this isn't too bad
             // natural loop - single entry (in-edge) into the cycle
             // c-like languages (minus goto) mostly only have natural loops
             // back edge - an edge A (tail) -> B (head) where B dominates A
             // more formally - for a back edge A -> B: smallest set of vertices L including A and B s.t. for all v in L, PREDS(v) in L OR v = B
             // reducible control flow: every back edge has a natural loop
             // e.g. if you remove all edges traversed after a BFS, the remainder are back edges
-            for block_id in &self.all_block_ids {
+            for block_id in &visit_order {
+                // the entry is only dominated by itself, even if there are edges back into it
+                if *block_id == self.entry {
+                    continue;
+                }
+
                 // a block A is "dominated" by another block B if B dominates all of A's predecessors
-                let block_predecessors = self.predecessors.get(block_id);
-                if let None = block_predecessors {
+                // edges can be removed from the graph, leaving an empty list of predecessors
+                let block_predecessors = self.predecessors.get(block_id).filter(|p| !p.is_empty());
+                if block_predecessors.is_none() {
                     continue;
                 }
 
@@ -171,7 +267,7 @@ impl<'a> ControlFlowGraph<'a> {
                 block_pred_dominator_intersection.insert(*block_id);
 
                 let current_dominator_set = dominators.get(block_id);
-                if let None = current_dominator_set {
+                if current_dominator_set.is_none() {
                     should_continue = true;
                 }
 
@@ -190,7 +286,7 @@ impl<'a> ControlFlowGraph<'a> {
     pub fn find_immediate_dominators(&self, dominators: &StrictDominators) -> ImmediateDominators {
         let mut result: HashMap<usize, usize> = HashMap::new();
         for block_id in &self.all_block_ids {
-            if *block_id == 0 {
+            if *block_id == self.entry {
                 continue; // entry node has no immediate dominator
             }
 
@@ -231,56 +327,40 @@ impl<'a> ControlFlowGraph<'a> {
             }
         }
 
-        // every node has an immediate dominator. don't think we should be getting here.
-        0
+        // every reachable node has an immediate dominator. unreachable ones get hung off the entry
+        self.entry
     }
 
     pub fn create_dominator_tree(&self, dominators: &Dominators) -> DominatorTree {
         let strict_dominators = retain_only_strict_dominators(dominators);
         let immediate_dominators = self.find_immediate_dominators(&strict_dominators);
 
-        let mut result: HashMap<usize, HashSet<usize>> = HashMap::new();
+        DominatorTree::new(self.entry, immediate_dominators)
+    }
+}
 
-        for block_id in immediate_dominators.keys() {
-            let immediate_dominator = immediate_dominators.get(block_id).unwrap();
-            if result.contains_key(immediate_dominator) {
-                result
-                    .get_mut(immediate_dominator)
-                    .unwrap()
-                    .insert(*block_id);
-            } else {
-                result.insert(*immediate_dominator, HashSet::from([*block_id]));
+#[cfg(test)]
+impl<'a> ControlFlowGraph<'a> {
+    // builds a cfg straight from an edge list so analyses can be tested on arbitrary graph shapes
+    pub(crate) fn create_from_edges(
+        function_blocks: &'a mut FunctionBlocks,
+        successors: HashMap<usize, Vec<usize>>,
+        all_block_ids: Vec<usize>,
+    ) -> Self {
+        let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for block_id in &all_block_ids {
+            for successor in successors.get(block_id).unwrap_or(&Vec::new()) {
+                predecessors.entry(*successor).or_default().push(*block_id);
             }
         }
 
-        DominatorTree(result)
-    }
-
-    pub fn get_dominance_frontier(
-        &self,
-        dominator_tree: &DominatorTree,
-        block_id: usize,
-    ) -> BTreeSet<usize> {
-        let mut dominated_nodes_set = find_all_dominated_nodes(dominator_tree, block_id);
-        dominated_nodes_set.insert(block_id);
-
-        let dominated_nodes: Vec<usize> = dominated_nodes_set.iter().copied().sorted().collect();
-
-        // look through all the successors of dominated nodes, eliminating those that are also in dominated_nodes
-        let mut all_successors_of_dominated: HashSet<usize> = HashSet::new();
-        for dominated_node in dominated_nodes.iter() {
-            all_successors_of_dominated.extend(
-                self.successors
-                    .get(dominated_node)
-                    .unwrap_or(&Vec::new())
-                    .iter(),
-            );
+        ControlFlowGraph {
+            predecessors,
+            successors,
+            entry: all_block_ids.first().copied().unwrap_or(0),
+            all_block_ids,
+            blocks: function_blocks,
         }
-
-        all_successors_of_dominated
-            .difference(&dominated_nodes_set)
-            .copied()
-            .collect()
     }
 }
 
@@ -292,7 +372,7 @@ pub fn retain_only_strict_dominators(dominators: &Dominators) -> StrictDominator
     for block_id in block_ids {
         let block_dominators = dominators.get(&block_id);
 
-        if let None = block_dominators {
+        if block_dominators.is_none() {
             continue;
         }
 
@@ -305,111 +385,49 @@ pub fn retain_only_strict_dominators(dominators: &Dominators) -> StrictDominator
     result
 }
 
-fn find_all_dominated_nodes(dom_tree: &DominatorTree, block_id: usize) -> HashSet<usize> {
-    let mut dominated_node_ids = HashSet::new();
-
-    let mut open_set: VecDeque<usize> = VecDeque::new();
-    open_set.push_back(block_id);
-
-    let none_set = HashSet::new();
-    while !open_set.is_empty() {
-        let next_block_id = open_set.pop_front().unwrap();
-
-        let immediately_dominated_ids = dom_tree.0.get(&next_block_id).unwrap_or(&none_set);
-        dominated_node_ids.extend(immediately_dominated_ids.iter());
-
-        immediately_dominated_ids
-            .iter()
-            .for_each(|i| open_set.push_back(*i));
-    }
-
-    dominated_node_ids
-}
-
 #[cfg(test)]
 mod tests {
     use std::collections::{BTreeSet, HashMap, HashSet};
 
-    use crate::{basicblock::FunctionBlocks, cfg::graph::retain_only_strict_dominators};
+    use crate::cfg::{graph::retain_only_strict_dominators, test_util::get_mock_function_blocks};
 
     use super::{ControlFlowGraph, ImmediateDominators};
 
-    struct GraphEdges {
-        successors: HashMap<usize, Vec<usize>>,
-        predecessors: HashMap<usize, Vec<usize>>,
-        all_block_ids: Vec<usize>,
-    }
-
-    fn get_test_cfg_edges_1() -> GraphEdges {
-        GraphEdges {
-            successors: HashMap::from([
-                (0, vec![1]),
-                (1, vec![2, 3]),
-                (2, vec![4, 5]),
-                (4, vec![5]),
-                (5, vec![1]),
-            ]),
-            predecessors: HashMap::from([
-                (1, vec![0, 5]),
-                (2, vec![1]),
-                (3, vec![1]),
-                (4, vec![2]),
-                (5, vec![2, 4]),
-            ]),
-            all_block_ids: vec![0, 1, 2, 3, 4, 5],
-        }
-    }
-
-    fn get_test_cfg_edges_2() -> GraphEdges {
-        GraphEdges {
-            successors: HashMap::from([
-                (0, vec![1]),
-                (1, vec![2, 3, 5]),
-                (2, vec![4]),
-                (3, vec![4]),
-                (4, vec![1]),
-            ]),
-            predecessors: HashMap::from([
-                (1, vec![0, 4]),
-                (2, vec![1]),
-                (3, vec![1]),
-                (4, vec![2, 3]),
-                (5, vec![1]),
-            ]),
-            all_block_ids: vec![0, 1, 2, 3, 4, 5],
-        }
-    }
-
-    fn get_test_cfg_edges_3() -> GraphEdges {
-        GraphEdges {
-            predecessors: HashMap::from([(1, vec![0]), (2, vec![0]), (3, vec![1, 2])]),
-            successors: HashMap::from([(0, vec![1, 2]), (1, vec![3]), (2, vec![3])]),
-            all_block_ids: vec![0, 1, 2, 3],
-        }
+    // 0 -> 1 -> {2, 3}, 2 -> {4, 5}, 4 -> 5, 5 -> 1
+    fn get_test_cfg_edges_1() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([
+            (0, vec![1]),
+            (1, vec![2, 3]),
+            (2, vec![4, 5]),
+            (4, vec![5]),
+            (5, vec![1]),
+        ])
     }
 
-    fn get_mock_function_blocks() -> FunctionBlocks {
-        FunctionBlocks::new("", vec![], vec![], HashMap::new(), HashMap::new())
+    // 0 -> 1 -> {2, 3, 5}, 2 -> 4, 3 -> 4, 4 -> 1
+    fn get_test_cfg_edges_2() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([
+            (0, vec![1]),
+            (1, vec![2, 3, 5]),
+            (2, vec![4]),
+            (3, vec![4]),
+            (4, vec![1]),
+        ])
     }
 
-    fn get_mock_cfg<'a>(
-        function_blocks: &'a mut FunctionBlocks,
-        edges: GraphEdges,
-    ) -> ControlFlowGraph<'a> {
-        ControlFlowGraph {
-            predecessors: edges.predecessors,
-            successors: edges.successors,
-            all_block_ids: edges.all_block_ids,
-            blocks: function_blocks,
-        }
+    // a diamond: 0 -> {1, 2}, 1 -> 3, 2 -> 3
+    fn get_test_cfg_edges_3() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([(0, vec![1, 2]), (1, vec![3]), (2, vec![3])])
     }
 
     #[test]
     fn test_find_dominators_1() {
-        let edges = get_test_cfg_edges_1();
         let mut mock_blocks = get_mock_function_blocks();
-
-        let cfg = get_mock_cfg(&mut mock_blocks, edges);
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_1(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
 
         let dominators = cfg.find_dominators();
         let expected: HashMap<usize, HashSet<usize>> = HashMap::from([
@@ -426,10 +444,12 @@ mod tests {
 
     #[test]
     fn test_find_strict_dominators_1() {
-        let edges = get_test_cfg_edges_1();
         let mut mock_blocks = get_mock_function_blocks();
-
-        let cfg = get_mock_cfg(&mut mock_blocks, edges);
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_1(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
 
         let dominators = cfg.find_dominators();
         let strict_dominators = retain_only_strict_dominators(&dominators);
@@ -447,10 +467,12 @@ mod tests {
 
     #[test]
     fn test_find_immediate_dominators_1() {
-        let edges = get_test_cfg_edges_1();
         let mut mock_blocks = get_mock_function_blocks();
-
-        let cfg = get_mock_cfg(&mut mock_blocks, edges);
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_1(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
 
         let dominators = cfg.find_dominators();
         let immediate_dominators =
@@ -463,44 +485,49 @@ mod tests {
 
     #[test]
     fn test_dominator_tree_1() {
-        let edges = get_test_cfg_edges_1();
         let mut mock_blocks = get_mock_function_blocks();
-
-        let cfg = get_mock_cfg(&mut mock_blocks, edges);
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_1(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
 
         let dominators = cfg.find_dominators();
         let dominator_tree = cfg.create_dominator_tree(&dominators);
 
-        let expected = HashMap::from([
-            (0, HashSet::from([1])),
-            (1, HashSet::from([2, 3])),
-            (2, HashSet::from([4, 5])),
-        ]);
-
-        assert_eq!(dominator_tree.0, expected);
+        assert_eq!(dominator_tree.get_root(), 0);
+        assert_eq!(dominator_tree.children(0), &[1]);
+        assert_eq!(dominator_tree.children(1), &[2, 3]);
+        assert_eq!(dominator_tree.children(2), &[4, 5]);
+        assert_eq!(dominator_tree.children(3), &[] as &[usize]);
     }
 
     #[test]
     fn test_dominator_tree_2() {
-        let edges = get_test_cfg_edges_2();
         let mut mock_blocks = get_mock_function_blocks();
-
-        let cfg = get_mock_cfg(&mut mock_blocks, edges);
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_2(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
 
         let dominators = cfg.find_dominators();
         let dominator_tree = cfg.create_dominator_tree(&dominators);
 
-        let expected = HashMap::from([(0, HashSet::from([1])), (1, HashSet::from([2, 3, 4, 5]))]);
-
-        assert_eq!(dominator_tree.0, expected);
+        assert_eq!(dominator_tree.children(0), &[1]);
+        assert_eq!(dominator_tree.children(1), &[2, 3, 4, 5]);
+        assert!(dominator_tree.dominates(1, 4));
+        assert!(!dominator_tree.dominates(2, 4));
     }
 
     #[test]
     fn test_dominance_frontier_1() {
-        let edges = get_test_cfg_edges_3();
         let mut mock_blocks = get_mock_function_blocks();
-
-        let cfg = get_mock_cfg(&mut mock_blocks, edges);
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_3(),
+            vec![0, 1, 2, 3],
+        );
 
         let dominators = cfg.find_dominators();
         let dominator_tree = cfg.create_dominator_tree(&dominators);
@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use super::{ControlFlowGraph, DominatorTree};
+
+pub type LoopId = usize;
+
+/*
+    A back edge is an edge A (tail) -> B (head) where B dominates A.
+    The natural loop of a back edge A -> B is the smallest set of blocks L containing A and B
+    such that for every v in L, PREDS(v) is in L OR v = B.
+
+    B is the loop's header: the single way into the loop. The tails of its back edges are its latches.
+    Loops sharing a header are merged into one, since they can't be told apart structurally
+    (e.g. a while loop with a `continue`).
+
+    Natural loops are either disjoint or nested, so they form a forest where each loop's parent is
+    the smallest loop that encloses it.
+*/
+#[derive(Debug)]
+pub struct NaturalLoop {
+    header: usize,
+    latches: BTreeSet<usize>,
+    body: BTreeSet<usize>,
+    exit_edges: BTreeSet<(usize, usize)>,
+    parent: Option<LoopId>,
+    children: Vec<LoopId>,
+    depth: usize,
+}
+
+#[derive(Debug)]
+pub struct LoopForest {
+    loops: Vec<NaturalLoop>,
+    top_level_loops: Vec<LoopId>,
+    innermost_loops: HashMap<usize, LoopId>,
+}
+
+impl NaturalLoop {
+    pub fn get_header(&self) -> usize {
+        self.header
+    }
+
+    pub fn get_latches(&self) -> &BTreeSet<usize> {
+        &self.latches
+    }
+
+    // all blocks in the loop, including the header and any nested loops
+    pub fn get_body(&self) -> &BTreeSet<usize> {
+        &self.body
+    }
+
+    pub fn contains(&self, block_id: usize) -> bool {
+        self.body.contains(&block_id)
+    }
+
+    // edges leaving the loop, as (block in the loop, block outside the loop)
+    pub fn get_exit_edges(&self) -> &BTreeSet<(usize, usize)> {
+        &self.exit_edges
+    }
+
+    // blocks in the loop with a successor outside of it
+    pub fn get_exiting_blocks(&self) -> BTreeSet<usize> {
+        self.exit_edges.iter().map(|(from, _)| *from).collect()
+    }
+
+    // blocks outside the loop that are jumped to from inside of it
+    pub fn get_exit_blocks(&self) -> BTreeSet<usize> {
+        self.exit_edges.iter().map(|(_, to)| *to).collect()
+    }
+
+    pub fn get_parent(&self) -> Option<LoopId> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &Vec<LoopId> {
+        &self.children
+    }
+
+    // outermost loops have a depth of 1
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl LoopForest {
+    pub fn get_loops(&self) -> &Vec<NaturalLoop> {
+        &self.loops
+    }
+
+    pub fn get_loop(&self, loop_id: LoopId) -> Option<&NaturalLoop> {
+        self.loops.get(loop_id)
+    }
+
+    pub fn get_top_level_loops(&self) -> &Vec<LoopId> {
+        &self.top_level_loops
+    }
+
+    pub fn get_loop_with_header(&self, header: usize) -> Option<LoopId> {
+        self.loops.iter().position(|l| l.header == header)
+    }
+
+    // the most deeply nested loop containing block_id
+    pub fn get_innermost_loop(&self, block_id: usize) -> Option<LoopId> {
+        self.innermost_loops.get(&block_id).copied()
+    }
+
+    // number of loops containing block_id. 0 if it isn't in a loop
+    pub fn get_loop_depth(&self, block_id: usize) -> usize {
+        self.get_innermost_loop(block_id)
+            .map_or(0, |loop_id| self.loops[loop_id].depth)
+    }
+
+    pub fn is_loop_header(&self, block_id: usize) -> bool {
+        self.get_loop_with_header(block_id).is_some()
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn find_back_edges(&self, dominator_tree: &DominatorTree) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        for block_id in self.get_all_block_ids() {
+            for successor in self.get_successors(*block_id) {
+                if dominator_tree.dominates(*successor, *block_id) {
+                    result.push((*block_id, *successor));
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn find_natural_loops(&self, dominator_tree: &DominatorTree) -> LoopForest {
+        // header -> latches. loops sharing a header get merged here
+        let mut latches_by_header: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for (latch, header) in self.find_back_edges(dominator_tree) {
+            latches_by_header.entry(header).or_default().insert(latch);
+        }
+
+        let mut loops: Vec<NaturalLoop> = latches_by_header
+            .into_iter()
+            .map(|(header, latches)| {
+                let body = self.find_natural_loop_body(header, &latches);
+                let exit_edges = body
+                    .iter()
+                    .flat_map(|block_id| {
+                        self.get_successors(*block_id)
+                            .iter()
+                            .filter(|successor| !body.contains(successor))
+                            .map(|successor| (*block_id, *successor))
+                    })
+                    .collect();
+
+                NaturalLoop {
+                    header,
+                    latches,
+                    body,
+                    exit_edges,
+                    parent: None,
+                    children: Vec::new(),
+                    depth: 0,
+                }
+            })
+            .collect();
+
+        // a loop's parent is the smallest other loop whose body contains it
+        let mut loop_ids_by_size: Vec<LoopId> = (0..loops.len()).collect();
+        loop_ids_by_size.sort_by_key(|loop_id| loops[*loop_id].body.len());
+
+        for (i, loop_id) in loop_ids_by_size.iter().enumerate() {
+            let parent = loop_ids_by_size[i + 1..].iter().copied().find(|candidate| {
+                loops[*candidate].body.contains(&loops[*loop_id].header)
+                    && loops[*candidate].body.is_superset(&loops[*loop_id].body)
+            });
+
+            loops[*loop_id].parent = parent;
+        }
+
+        let mut top_level_loops = Vec::new();
+        for loop_id in 0..loops.len() {
+            match loops[loop_id].parent {
+                Some(parent) => loops[parent].children.push(loop_id),
+                None => top_level_loops.push(loop_id),
+            }
+        }
+
+        // parents are strictly larger than their children so outermost loops come last
+        for loop_id in loop_ids_by_size.iter().rev() {
+            loops[*loop_id].depth = loops[*loop_id]
+                .parent
+                .map_or(1, |parent| loops[parent].depth + 1);
+        }
+
+        // smaller loops are visited first, so the first loop claiming a block is its innermost one
+        let mut innermost_loops: HashMap<usize, LoopId> = HashMap::new();
+        for loop_id in &loop_ids_by_size {
+            for block_id in &loops[*loop_id].body {
+                innermost_loops.entry(*block_id).or_insert(*loop_id);
+            }
+        }
+
+        LoopForest {
+            loops,
+            top_level_loops,
+            innermost_loops,
+        }
+    }
+
+    // walk backwards from the latches until the header is hit
+    fn find_natural_loop_body(&self, header: usize, latches: &BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut body = BTreeSet::from([header]);
+        let mut open_set: VecDeque<usize> = VecDeque::new();
+
+        for latch in latches {
+            if body.insert(*latch) {
+                open_set.push_back(*latch);
+            }
+        }
+
+        while let Some(next) = open_set.pop_front() {
+            for pred in self.get_predecessors(next) {
+                if body.insert(*pred) {
+                    open_set.push_back(*pred);
+                }
+            }
+        }
+
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::cfg::{test_util::get_mock_function_blocks, ControlFlowGraph};
+
+    // single loop 1 -> 2 -> {4, 5} -> 5 -> 1, exiting from 1 to 3
+    fn get_test_cfg_edges_1() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([
+            (0, vec![1]),
+            (1, vec![2, 3]),
+            (2, vec![4, 5]),
+            (4, vec![5]),
+            (5, vec![1]),
+        ])
+    }
+
+    // inner loop {2, 3} inside outer loop {1, 2, 3, 4}
+    fn get_test_cfg_edges_2() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([
+            (0, vec![1]),
+            (1, vec![2]),
+            (2, vec![3]),
+            (3, vec![2, 4]),
+            (4, vec![1, 5]),
+        ])
+    }
+
+    // two back edges 2 -> 1 and 3 -> 1 sharing a header
+    fn get_test_cfg_edges_3() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([(0, vec![1]), (1, vec![2, 3]), (2, vec![1]), (3, vec![1, 4])])
+    }
+
+    #[test]
+    fn test_single_loop() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_1(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        assert_eq!(cfg.find_back_edges(&dominator_tree), vec![(5, 1)]);
+
+        let forest = cfg.find_natural_loops(&dominator_tree);
+        assert_eq!(forest.get_loops().len(), 1);
+
+        let l = forest.get_loop(0).unwrap();
+        assert_eq!(l.get_header(), 1);
+        assert_eq!(l.get_latches(), &BTreeSet::from([5]));
+        assert_eq!(l.get_body(), &BTreeSet::from([1, 2, 4, 5]));
+        assert_eq!(l.get_exit_edges(), &BTreeSet::from([(1, 3)]));
+        assert_eq!(l.get_depth(), 1);
+
+        assert_eq!(forest.get_loop_depth(4), 1);
+        assert_eq!(forest.get_loop_depth(3), 0);
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_2(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let forest = cfg.find_natural_loops(&dominator_tree);
+        assert_eq!(forest.get_loops().len(), 2);
+
+        let outer_id = forest.get_loop_with_header(1).unwrap();
+        let inner_id = forest.get_loop_with_header(2).unwrap();
+        let outer = forest.get_loop(outer_id).unwrap();
+        let inner = forest.get_loop(inner_id).unwrap();
+
+        assert_eq!(outer.get_body(), &BTreeSet::from([1, 2, 3, 4]));
+        assert_eq!(inner.get_body(), &BTreeSet::from([2, 3]));
+        assert_eq!(inner.get_parent(), Some(outer_id));
+        assert_eq!(outer.get_children(), &vec![inner_id]);
+        assert_eq!(forest.get_top_level_loops(), &vec![outer_id]);
+
+        assert_eq!(inner.get_exit_blocks(), BTreeSet::from([4]));
+        assert_eq!(outer.get_exiting_blocks(), BTreeSet::from([4]));
+
+        assert_eq!(forest.get_innermost_loop(3), Some(inner_id));
+        assert_eq!(forest.get_innermost_loop(4), Some(outer_id));
+        assert_eq!(forest.get_loop_depth(3), 2);
+        assert_eq!(forest.get_loop_depth(4), 1);
+        assert_eq!(forest.get_loop_depth(5), 0);
+    }
+
+    #[test]
+    fn test_loops_sharing_header_are_merged() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_3(),
+            vec![0, 1, 2, 3, 4],
+        );
+
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let forest = cfg.find_natural_loops(&dominator_tree);
+        assert_eq!(forest.get_loops().len(), 1);
+
+        let l = forest.get_loop(0).unwrap();
+        assert_eq!(l.get_header(), 1);
+        assert_eq!(l.get_latches(), &BTreeSet::from([2, 3]));
+        assert_eq!(l.get_body(), &BTreeSet::from([1, 2, 3]));
+        assert_eq!(l.get_exit_edges(), &BTreeSet::from([(3, 4)]));
+    }
+}
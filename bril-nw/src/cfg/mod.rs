@@ -1,4 +1,22 @@
 pub mod dataflow;
+pub mod dominance_frontier;
+pub mod dominator_tree;
+pub mod dominator_update;
+pub mod dot;
 pub mod graph;
+pub mod loops;
+pub mod normalize;
+pub mod post_dominators;
+pub mod reducibility;
+pub mod scc;
+pub mod traversal;
 
+#[cfg(test)]
+pub(crate) mod test_util;
+
+pub use dominance_frontier::DominanceFrontiers;
+pub use dominator_tree::DominatorTree;
+pub use dominator_update::{CfgEdit, DominatorUpdateConfig};
 pub use graph::ControlFlowGraph;
+pub use loops::{LoopForest, NaturalLoop};
+pub use post_dominators::{ControlDependenceGraph, VIRTUAL_EXIT_BLOCK_ID};
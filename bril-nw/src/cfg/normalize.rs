@@ -0,0 +1,212 @@
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::{Instruction, OpCode, Type},
+};
+
+use super::ControlFlowGraph;
+
+/*
+    Optional normalization of a function's cfg, for analyses that want a single entry and exit:
+
+    - the entry has no predecessors. when the first block is also a jump target (e.g. the head of
+      a loop), a fresh block that just jumps to it is put in front of it. otherwise phi nodes in the
+      entry would need a value for "coming from outside the function"
+    - there is a single exit. when the function has more than one ret, each is replaced by a jump
+      to a new exit block at the end of the function. returned values are copied into a temporary
+      first, which the exit block returns
+
+    Blocks stuck in an infinite loop still never reach the exit.
+*/
+#[derive(Debug, Default, PartialEq)]
+pub struct NormalizedBlocks {
+    pub inserted_entry: Option<usize>,
+    pub inserted_exit: Option<usize>,
+}
+
+pub fn normalize(function: &mut FunctionBlocks) -> NormalizedBlocks {
+    NormalizedBlocks {
+        inserted_entry: insert_entry_block(function),
+        inserted_exit: insert_exit_block(function),
+    }
+}
+
+// returns the id of the new entry block, if one was needed
+pub fn insert_entry_block(function: &mut FunctionBlocks) -> Option<usize> {
+    let old_entry = {
+        let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+        let entry = cfg.get_entry_block_id();
+        if cfg.get_all_block_ids().is_empty() || cfg.get_predecessors(entry).is_empty() {
+            return None;
+        }
+
+        entry
+    };
+
+    let old_entry_label = function.get_or_create_block_label(old_entry).unwrap();
+    let entry_label = function.create_unique_block_name("entry");
+
+    Some(function.insert_block(
+        0,
+        vec![
+            Instruction::new_label(&entry_label),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec![old_entry_label]),
+        ],
+    ))
+}
+
+// returns the id of the new exit block, if one was needed
+pub fn insert_exit_block(function: &mut FunctionBlocks) -> Option<usize> {
+    // a last block without a terminator returns too
+    let falls_off_end = function
+        .get_blocks()
+        .last()
+        .is_some_and(|b| !b.instrs.last().is_some_and(|i| i.is_jump() || i.is_ret()));
+    let num_exits = function
+        .get_blocks()
+        .iter()
+        .filter(|b| b.instrs.last().is_some_and(|i| i.is_ret()))
+        .count()
+        + falls_off_end as usize;
+    if num_exits <= 1 {
+        return None;
+    }
+
+    function.make_fallthroughs_explicit();
+
+    let exit_label = function.create_unique_block_name("exit");
+    let return_type = function.get_return_type();
+    let return_var = match return_type {
+        Type::Unit => None,
+        _ => Some(function.create_unique_var_name("ret.val")),
+    };
+
+    for block in function.get_mut_blocks() {
+        let Some(ret) = block.instrs.last().filter(|i| i.is_ret()).cloned() else {
+            continue;
+        };
+        block.instrs.pop();
+
+        let ret_arg = ret.get_args().and_then(|args| args.first()).cloned();
+        if let (Some(return_var), Some(ret_arg)) = (&return_var, ret_arg) {
+            block.instrs.push(Instruction::new_value(
+                OpCode::Id,
+                return_var.clone(),
+                return_type.clone(),
+                vec![ret_arg],
+                vec![],
+                vec![],
+            ));
+        }
+
+        block.instrs.push(Instruction::new_effect(
+            OpCode::Jump,
+            vec![],
+            vec![],
+            vec![exit_label.clone()],
+        ));
+    }
+
+    Some(function.add_block(vec![
+        Instruction::new_label(&exit_label),
+        Instruction::new_effect(
+            OpCode::Ret,
+            return_var.into_iter().collect(),
+            vec![],
+            vec![],
+        ),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type},
+        cfg::ControlFlowGraph,
+    };
+
+    use super::{normalize, NormalizedBlocks};
+
+    #[test]
+    fn test_normalize_entry_and_exit() {
+        // the first block is a loop header, and there are two rets
+        let instrs = vec![
+            Instruction::new_label("loop"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["body".to_string(), "done".to_string()],
+            ),
+            Instruction::new_label("body"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["loop".to_string(), "early".to_string()],
+            ),
+            Instruction::new_label("early"),
+            Instruction::new_effect(OpCode::Ret, vec!["x".to_string()], vec![], vec![]),
+            Instruction::new_label("done"),
+            Instruction::new_effect(OpCode::Ret, vec!["y".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Int,
+            vec![
+                FunctionArg::new("cond".to_string(), Type::Bool),
+                FunctionArg::new("x".to_string(), Type::Int),
+                FunctionArg::new("y".to_string(), Type::Int),
+            ],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+
+        let normalized = normalize(&mut blocks);
+        assert_eq!(
+            normalized,
+            NormalizedBlocks {
+                inserted_entry: Some(4),
+                inserted_exit: Some(5),
+            }
+        );
+
+        let exit_block = blocks.get_block_by_id(5).unwrap();
+        assert_eq!(exit_block.get_name(), "exit");
+        assert_eq!(
+            exit_block.instrs.last().unwrap().get_args(),
+            Some(&vec!["ret.val".to_string()])
+        );
+
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+        assert_eq!(cfg.get_entry_block_id(), 4);
+        assert!(cfg.get_predecessors(4).is_empty());
+        assert_eq!(cfg.get_successors(4), &[0]);
+        assert_eq!(cfg.find_exiting_blocks(), vec![5]);
+
+        let dominator_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        assert_eq!(dominator_tree.get_root(), 4);
+        assert_eq!(dominator_tree.idom(0), Some(4));
+        assert_eq!(dominator_tree.idom(5), Some(0));
+    }
+
+    #[test]
+    fn test_normalize_already_normal() {
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_effect(OpCode::Print, vec!["x".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("x".to_string(), Type::Int)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        let before = blocks.to_string();
+
+        assert_eq!(normalize(&mut blocks), NormalizedBlocks::default());
+        // the fallthrough off the end is the only exit, so no ret gets added for it either
+        assert_eq!(blocks.to_string(), before);
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use super::{ControlFlowGraph, DominatorTree};
+
+// id of the synthetic node every exiting block flows into. no real block can have this id
+pub const VIRTUAL_EXIT_BLOCK_ID: usize = usize::MAX;
+
+pub type PostDominators = HashMap<usize, HashSet<usize>>;
+pub type StrictPostDominators = PostDominators;
+
+pub type ImmediatePostDominators = HashMap<usize, usize>;
+
+/*
+    The dominator tree of the reversed graph, rooted at the virtual exit. A post-dominates B exactly
+    when A is an ancestor of B (or B itself).
+*/
+#[derive(Debug)]
+pub struct PostDominatorTree(DominatorTree);
+
+impl PostDominatorTree {
+    pub fn new(immediate_post_dominators: ImmediatePostDominators) -> Self {
+        PostDominatorTree(DominatorTree::new(
+            VIRTUAL_EXIT_BLOCK_ID,
+            immediate_post_dominators,
+        ))
+    }
+
+    // always the virtual exit
+    pub fn get_root(&self) -> usize {
+        self.0.get_root()
+    }
+
+    // the virtual exit has no immediate post-dominator
+    pub fn ipdom(&self, block_id: usize) -> Option<usize> {
+        self.0.idom(block_id)
+    }
+
+    pub fn children(&self, block_id: usize) -> &[usize] {
+        self.0.children(block_id)
+    }
+
+    // post-domination is reflexive, so every block post-dominates itself
+    pub fn post_dominates(&self, a: usize, b: usize) -> bool {
+        self.0.dominates(a, b)
+    }
+
+    pub fn strictly_post_dominates(&self, a: usize, b: usize) -> bool {
+        self.0.strictly_dominates(a, b)
+    }
+
+    // a block comes before every block it post-dominates
+    pub fn preorder(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.preorder()
+    }
+
+    // every block post-dominated by block_id, including block_id itself, in preorder
+    pub fn post_dominated_by(&self, block_id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.0.dominated_by(block_id)
+    }
+}
+
+/*
+    A block B is control dependent on a block A iff
+        - there is an edge A -> S where B post-dominates S
+        - B does not strictly post-dominate A
+
+    In other words, A is a branch that decides whether B runs. Taking one of A's edges
+    guarantees B will execute, while another edge may skip it.
+    The blocks B depends on are exactly the post-dominance frontier of B.
+*/
+#[derive(Debug, Default)]
+pub struct ControlDependenceGraph {
+    // block id -> branch blocks deciding whether it runs
+    controllers: HashMap<usize, BTreeSet<usize>>,
+    // branch block id -> blocks whose execution it decides
+    dependents: HashMap<usize, BTreeSet<usize>>,
+}
+
+impl ControlDependenceGraph {
+    fn add_dependence(&mut self, controller: usize, dependent: usize) {
+        self.controllers
+            .entry(dependent)
+            .or_default()
+            .insert(controller);
+        self.dependents
+            .entry(controller)
+            .or_default()
+            .insert(dependent);
+    }
+
+    pub fn get_controlling_blocks(&self, block_id: usize) -> BTreeSet<usize> {
+        self.controllers.get(&block_id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_dependent_blocks(&self, block_id: usize) -> BTreeSet<usize> {
+        self.dependents.get(&block_id).cloned().unwrap_or_default()
+    }
+
+    pub fn is_control_dependent(&self, block_id: usize, controller: usize) -> bool {
+        self.controllers
+            .get(&block_id)
+            .is_some_and(|c| c.contains(&controller))
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    // blocks that get an edge into the virtual exit.
+    // these are blocks ending in a ret (or falling off the end of the function), plus one block per
+    // region that can never reach such a block (e.g. an infinite loop). without the latter, blocks in
+    // those regions would have no post-dominators at all
+    pub fn find_exiting_blocks(&self) -> Vec<usize> {
+        let mut exiting_blocks: Vec<usize> = self
+            .get_all_block_ids()
+            .iter()
+            .copied()
+            .filter(|block_id| self.get_successors(*block_id).is_empty())
+            .collect();
+
+        // every successor of a block that cannot reach the exit also cannot reach it, so picking the
+        // last such block in program order usually picks the jump back to the loop header.
+        // everything that reaches the new exiting block is then known to reach the exit as well
+        let mut reaches_exit = HashSet::new();
+        self.extend_blocks_reaching(&exiting_blocks, &mut reaches_exit);
+        for block_id in self.get_all_block_ids().iter().rev() {
+            if !reaches_exit.contains(block_id) {
+                exiting_blocks.push(*block_id);
+                self.extend_blocks_reaching(&[*block_id], &mut reaches_exit);
+            }
+        }
+
+        exiting_blocks
+    }
+
+    // adds every block that reaches one of targets to visited.
+    // blocks already in visited are not walked again
+    fn extend_blocks_reaching(&self, targets: &[usize], visited: &mut HashSet<usize>) {
+        visited.extend(targets.iter().copied());
+        let mut open_set: VecDeque<usize> = targets.iter().copied().collect();
+
+        while let Some(next) = open_set.pop_front() {
+            for pred in self.get_predecessors(next) {
+                if visited.insert(*pred) {
+                    open_set.push_back(*pred);
+                }
+            }
+        }
+    }
+
+    // successors in the graph augmented with the virtual exit
+    fn get_augmented_successors(
+        &self,
+        block_id: usize,
+        exiting_blocks: &HashSet<usize>,
+    ) -> Vec<usize> {
+        let mut successors = self.get_successors(block_id).to_vec();
+        if exiting_blocks.contains(&block_id) {
+            successors.push(VIRTUAL_EXIT_BLOCK_ID);
+        }
+
+        successors
+    }
+
+    pub fn find_post_dominators(&self) -> PostDominators {
+        let exiting_blocks: HashSet<usize> = self.find_exiting_blocks().into_iter().collect();
+
+        let mut all_block_ids_set = self
+            .get_all_block_ids()
+            .iter()
+            .copied()
+            .collect::<HashSet<usize>>();
+        all_block_ids_set.insert(VIRTUAL_EXIT_BLOCK_ID);
+
+        let mut post_dominators: PostDominators = HashMap::new();
+        post_dominators.insert(
+            VIRTUAL_EXIT_BLOCK_ID,
+            HashSet::from([VIRTUAL_EXIT_BLOCK_ID]),
+        );
+        for block_id in self.get_all_block_ids() {
+            post_dominators.insert(*block_id, all_block_ids_set.clone());
+        }
+
+        // same as find_dominators, but flowing backwards from the exit.
+        // a block A is post-dominated by B if B post-dominates all of A's successors
+        let mut should_continue = true;
+        while should_continue {
+            should_continue = false;
+
+            for block_id in self.get_all_block_ids().iter().rev() {
+                let mut successor_intersection = self
+                    .get_augmented_successors(*block_id, &exiting_blocks)
+                    .iter()
+                    .map(|successor| post_dominators.get(successor).unwrap().clone())
+                    .reduce(|s1, s2| s1.intersection(&s2).copied().collect())
+                    .unwrap_or_default();
+
+                // post-domination is reflexive
+                successor_intersection.insert(*block_id);
+
+                if post_dominators.get(block_id).unwrap() != &successor_intersection {
+                    should_continue = true;
+                    post_dominators.insert(*block_id, successor_intersection);
+                }
+            }
+        }
+
+        post_dominators
+    }
+
+    pub fn find_immediate_post_dominators(
+        &self,
+        post_dominators: &PostDominators,
+    ) -> ImmediatePostDominators {
+        let strict_post_dominators = super::graph::retain_only_strict_dominators(post_dominators);
+
+        let mut result: ImmediatePostDominators = HashMap::new();
+        for (block_id, block_strict_post_dominators) in &strict_post_dominators {
+            if *block_id == VIRTUAL_EXIT_BLOCK_ID {
+                continue; // the exit is the root of the post-dominator tree
+            }
+
+            // strict post-dominators of a block form a chain up to the exit.
+            // the immediate one is post-dominated by every other member of the chain
+            let immediate_post_dominator = block_strict_post_dominators
+                .iter()
+                .find(|candidate| {
+                    strict_post_dominators.get(candidate).unwrap().len() + 1
+                        == block_strict_post_dominators.len()
+                })
+                .copied()
+                .unwrap_or(VIRTUAL_EXIT_BLOCK_ID);
+
+            result.insert(*block_id, immediate_post_dominator);
+        }
+
+        result
+    }
+
+    pub fn create_post_dominator_tree(
+        &self,
+        post_dominators: &PostDominators,
+    ) -> PostDominatorTree {
+        PostDominatorTree::new(self.find_immediate_post_dominators(post_dominators))
+    }
+
+    pub fn get_post_dominance_frontier(
+        &self,
+        post_dominator_tree: &PostDominatorTree,
+        block_id: usize,
+    ) -> BTreeSet<usize> {
+        // predecessors of anything block_id post-dominates, that block_id doesn't strictly post-dominate.
+        // block_id stays a candidate itself, e.g. when it's a loop's exit test
+        let mut result: BTreeSet<usize> = BTreeSet::new();
+        for post_dominated_node in post_dominator_tree.post_dominated_by(block_id) {
+            result.extend(self.get_predecessors(post_dominated_node).iter());
+        }
+
+        result
+            .into_iter()
+            .filter(|pred| !post_dominator_tree.strictly_post_dominates(block_id, *pred))
+            .collect()
+    }
+
+    pub fn create_control_dependence_graph(
+        &self,
+        post_dominators: &PostDominators,
+    ) -> ControlDependenceGraph {
+        let exiting_blocks: HashSet<usize> = self.find_exiting_blocks().into_iter().collect();
+        let immediate_post_dominators = self.find_immediate_post_dominators(post_dominators);
+
+        let mut result = ControlDependenceGraph::default();
+
+        // for every edge A -> S, everything on the post-dominator tree path from S up to (but not including)
+        // ipdom(A) is control dependent on A
+        for block_id in self.get_all_block_ids() {
+            let stop = immediate_post_dominators.get(block_id).copied();
+            for successor in self.get_augmented_successors(*block_id, &exiting_blocks) {
+                let mut runner = successor;
+                while Some(runner) != stop && runner != VIRTUAL_EXIT_BLOCK_ID {
+                    result.add_dependence(*block_id, runner);
+                    runner = *immediate_post_dominators.get(&runner).unwrap();
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap, HashSet};
+
+    use crate::cfg::{
+        test_util::{get_loop_around_diamond_edges, get_mock_function_blocks},
+        ControlFlowGraph,
+    };
+
+    use super::VIRTUAL_EXIT_BLOCK_ID;
+
+    const EXIT: usize = VIRTUAL_EXIT_BLOCK_ID;
+
+    // 0 -> {1, 2}, 1 returns, 2 -> {3, 4}, 3 returns, 4 -> 4 spins forever
+    fn get_test_cfg_edges_2() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([(0, vec![1, 2]), (2, vec![3, 4]), (4, vec![4])])
+    }
+
+    #[test]
+    fn test_post_dominators_1() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_loop_around_diamond_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+
+        let post_dominators = cfg.find_post_dominators();
+        let expected: HashMap<usize, HashSet<usize>> = HashMap::from([
+            (0, HashSet::from([0, 1, 4, 5, EXIT])),
+            (1, HashSet::from([1, 4, 5, EXIT])),
+            (2, HashSet::from([2, 4, 5, EXIT])),
+            (3, HashSet::from([3, 4, 5, EXIT])),
+            (4, HashSet::from([4, 5, EXIT])),
+            (5, HashSet::from([5, EXIT])),
+            (EXIT, HashSet::from([EXIT])),
+        ]);
+
+        assert_eq!(post_dominators, expected);
+    }
+
+    #[test]
+    fn test_post_dominator_tree_multiple_exits() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_2(),
+            vec![0, 1, 2, 3, 4],
+        );
+
+        // 1 and 3 return, 4 never reaches a ret so it gets hooked up to the exit too
+        assert_eq!(cfg.find_exiting_blocks(), vec![1, 3, 4]);
+
+        let post_dominators = cfg.find_post_dominators();
+        let post_dominator_tree = cfg.create_post_dominator_tree(&post_dominators);
+
+        assert_eq!(post_dominator_tree.get_root(), EXIT);
+        assert_eq!(post_dominator_tree.children(EXIT), &[0, 1, 2, 3, 4]);
+        for block_id in 0..5 {
+            assert_eq!(post_dominator_tree.ipdom(block_id), Some(EXIT));
+            assert!(post_dominator_tree.post_dominates(EXIT, block_id));
+        }
+    }
+
+    #[test]
+    fn test_post_dominance_frontier_1() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_loop_around_diamond_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+
+        let post_dominators = cfg.find_post_dominators();
+        let post_dominator_tree = cfg.create_post_dominator_tree(&post_dominators);
+
+        assert_eq!(
+            cfg.get_post_dominance_frontier(&post_dominator_tree, 2),
+            BTreeSet::from([1])
+        );
+        // the loop test decides whether the loop runs again, including itself
+        assert_eq!(
+            cfg.get_post_dominance_frontier(&post_dominator_tree, 1),
+            BTreeSet::from([4])
+        );
+        assert_eq!(
+            cfg.get_post_dominance_frontier(&post_dominator_tree, 4),
+            BTreeSet::from([4])
+        );
+        assert_eq!(
+            cfg.get_post_dominance_frontier(&post_dominator_tree, 5),
+            BTreeSet::new()
+        );
+    }
+
+    #[test]
+    fn test_control_dependence_1() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_loop_around_diamond_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+
+        let post_dominators = cfg.find_post_dominators();
+        let cdg = cfg.create_control_dependence_graph(&post_dominators);
+
+        assert_eq!(cdg.get_controlling_blocks(0), BTreeSet::new());
+        assert_eq!(cdg.get_controlling_blocks(1), BTreeSet::from([4]));
+        assert_eq!(cdg.get_controlling_blocks(2), BTreeSet::from([1]));
+        assert_eq!(cdg.get_controlling_blocks(3), BTreeSet::from([1]));
+        assert_eq!(cdg.get_controlling_blocks(4), BTreeSet::from([4]));
+        assert_eq!(cdg.get_controlling_blocks(5), BTreeSet::new());
+
+        assert_eq!(cdg.get_dependent_blocks(1), BTreeSet::from([2, 3]));
+        assert_eq!(cdg.get_dependent_blocks(4), BTreeSet::from([1, 4]));
+        assert!(cdg.is_control_dependent(2, 1));
+        assert!(!cdg.is_control_dependent(5, 4));
+    }
+
+    #[test]
+    fn test_control_dependence_multiple_exits() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges_2(),
+            vec![0, 1, 2, 3, 4],
+        );
+
+        let post_dominators = cfg.find_post_dominators();
+        let cdg = cfg.create_control_dependence_graph(&post_dominators);
+
+        assert_eq!(cdg.get_controlling_blocks(1), BTreeSet::from([0]));
+        assert_eq!(cdg.get_controlling_blocks(2), BTreeSet::from([0]));
+        assert_eq!(cdg.get_controlling_blocks(3), BTreeSet::from([2]));
+        assert_eq!(cdg.get_controlling_blocks(4), BTreeSet::from([2, 4]));
+    }
+}
@@ -0,0 +1,554 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    rc::Rc,
+};
+
+use crate::{basicblock::FunctionBlocks, bril::types::Instruction};
+
+use super::{scc::find_strongly_connected_components, ControlFlowGraph};
+
+/*
+    A cfg is reducible iff it can be collapsed into a single node by repeatedly applying
+        - T1: remove an edge from a node to itself
+        - T2: if a node N (other than the entry) has exactly one predecessor P, merge N into P
+
+    Each collapsed node is a single-entry region of the original cfg, entered through its header.
+    If the reduction gets stuck, every cycle left over has more than one way into it (e.g. two
+    gotos into the middle of a loop). Those cycles are the irreducible regions.
+
+    Node splitting makes such a cfg reducible by giving each extra entry its own copy of a region,
+    so every copy only has one way into it. This can blow up code size, hence the growth limit.
+*/
+#[derive(Debug, PartialEq)]
+pub struct IrreducibleRegion {
+    // every block in the offending strongly connected region
+    pub blocks: BTreeSet<usize>,
+    // blocks through which control can enter the region from outside of it
+    pub entries: BTreeSet<usize>,
+}
+
+#[derive(Debug)]
+pub struct NodeSplittingConfig {
+    // largest allowed size of the function after splitting, as a multiple of its size before.
+    // size is counted in instructions
+    pub max_code_growth: f64,
+}
+
+#[derive(Debug)]
+pub enum NodeSplittingError {
+    CodeGrowthLimitExceeded(Vec<IrreducibleRegion>),
+}
+
+impl Default for NodeSplittingConfig {
+    fn default() -> Self {
+        NodeSplittingConfig {
+            max_code_growth: 2.0,
+        }
+    }
+}
+
+struct ReductionGraph {
+    entry: usize,
+    successors: BTreeMap<usize, BTreeSet<usize>>,
+    predecessors: BTreeMap<usize, BTreeSet<usize>>,
+    // node -> original blocks collapsed into it. a node's id is the id of its header block
+    members: BTreeMap<usize, BTreeSet<usize>>,
+    // the edges between the original blocks, before any reduction
+    block_successors: BTreeMap<usize, BTreeSet<usize>>,
+}
+
+impl ReductionGraph {
+    // only blocks reachable from the entry take part. unreachable code can't make a cfg irreducible
+    fn from_cfg(cfg: &ControlFlowGraph) -> Self {
+        let entry = cfg.get_entry_block_id();
+
+        let mut successors: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        let mut open_set = VecDeque::from([entry]);
+        successors.insert(entry, BTreeSet::new());
+
+        while let Some(next) = open_set.pop_front() {
+            for successor in cfg.get_successors(next) {
+                successors.get_mut(&next).unwrap().insert(*successor);
+                if !successors.contains_key(successor) {
+                    successors.insert(*successor, BTreeSet::new());
+                    open_set.push_back(*successor);
+                }
+            }
+        }
+
+        let mut predecessors: BTreeMap<usize, BTreeSet<usize>> = successors
+            .keys()
+            .map(|node| (*node, BTreeSet::new()))
+            .collect();
+        for (node, node_successors) in &successors {
+            for successor in node_successors {
+                predecessors.get_mut(successor).unwrap().insert(*node);
+            }
+        }
+
+        let members = successors
+            .keys()
+            .map(|node| (*node, BTreeSet::from([*node])))
+            .collect();
+
+        ReductionGraph {
+            entry,
+            block_successors: successors.clone(),
+            successors,
+            predecessors,
+            members,
+        }
+    }
+
+    fn reduce(&mut self) {
+        loop {
+            // T1
+            for (node, node_successors) in self.successors.iter_mut() {
+                if node_successors.remove(node) {
+                    self.predecessors.get_mut(node).unwrap().remove(node);
+                }
+            }
+
+            // T2
+            let candidate = self
+                .predecessors
+                .iter()
+                .find(|(node, preds)| **node != self.entry && preds.len() == 1)
+                .map(|(node, preds)| (*node, *preds.iter().next().unwrap()));
+
+            match candidate {
+                Some((node, pred)) => self.merge(node, pred),
+                None => break,
+            }
+        }
+    }
+
+    fn merge(&mut self, node: usize, into: usize) {
+        let node_successors = self.successors.remove(&node).unwrap();
+        self.predecessors.remove(&node);
+
+        let into_successors = self.successors.get_mut(&into).unwrap();
+        into_successors.remove(&node);
+        into_successors.extend(node_successors.iter());
+
+        for successor in node_successors {
+            let successor_preds = self.predecessors.get_mut(&successor).unwrap();
+            successor_preds.remove(&node);
+            successor_preds.insert(into);
+        }
+
+        let node_members = self.members.remove(&node).unwrap();
+        self.members.get_mut(&into).unwrap().extend(node_members);
+    }
+
+    fn is_reduced(&self) -> bool {
+        self.members.len() == 1
+    }
+
+    // regions of nodes the reduction got stuck on, as (reduced nodes, region)
+    fn find_stuck_regions(&self) -> Vec<(Vec<usize>, IrreducibleRegion)> {
+        let nodes: Vec<usize> = self.successors.keys().copied().collect();
+        let components = find_strongly_connected_components(&nodes, |node| {
+            self.successors.get(&node).unwrap().clone()
+        });
+
+        let mut result: Vec<(Vec<usize>, IrreducibleRegion)> = components
+            .into_iter()
+            .filter(|component| component.len() > 1)
+            .map(|component| {
+                let blocks = self.find_blocks_on_cycles(&component);
+
+                let entries = component
+                    .iter()
+                    .copied()
+                    .filter(|node| {
+                        *node == self.entry
+                            || self
+                                .predecessors
+                                .get(node)
+                                .unwrap()
+                                .iter()
+                                .any(|pred| !component.contains(pred))
+                    })
+                    .collect();
+
+                (component, IrreducibleRegion { blocks, entries })
+            })
+            .collect();
+
+        result.sort_by_key(|(component, _)| component[0]);
+        result
+    }
+
+    // the original blocks on the cycles through the component's nodes. nodes can have other
+    // blocks merged into them that aren't on those cycles, e.g. a loop hanging off of one of them
+    fn find_blocks_on_cycles(&self, component: &[usize]) -> BTreeSet<usize> {
+        let members: BTreeSet<usize> = component
+            .iter()
+            .flat_map(|node| self.members.get(node).unwrap().iter().copied())
+            .collect();
+        let member_ids: Vec<usize> = members.iter().copied().collect();
+
+        let block_components = find_strongly_connected_components(&member_ids, |block| {
+            self.block_successors
+                .get(&block)
+                .unwrap()
+                .intersection(&members)
+                .copied()
+                .collect::<Vec<usize>>()
+        });
+
+        // every cycle through the component enters each node at its header
+        block_components
+            .into_iter()
+            .filter(|block_component| block_component.iter().any(|b| component.contains(b)))
+            .flatten()
+            .collect()
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn is_reducible(&self) -> bool {
+        let mut reduction_graph = ReductionGraph::from_cfg(self);
+        reduction_graph.reduce();
+
+        reduction_graph.is_reduced()
+    }
+
+    // empty if the cfg is reducible
+    pub fn find_irreducible_regions(&self) -> Vec<IrreducibleRegion> {
+        let mut reduction_graph = ReductionGraph::from_cfg(self);
+        reduction_graph.reduce();
+
+        reduction_graph
+            .find_stuck_regions()
+            .into_iter()
+            .map(|(_, region)| region)
+            .collect()
+    }
+}
+
+// a region of blocks to duplicate, and the blocks that should jump into each new copy of it
+struct SplitPlan {
+    header: usize,
+    members: BTreeSet<usize>,
+    redirected_preds: Vec<Vec<usize>>,
+}
+
+// makes the function's cfg reducible by duplicating blocks. returns the number of blocks added.
+// expects the function to not be in ssa form, since phi nodes aren't rewritten for the new copies.
+// if the size limit would be exceeded, the function is left untouched
+pub fn make_reducible(
+    function: &mut FunctionBlocks,
+    config: &NodeSplittingConfig,
+) -> Result<usize, NodeSplittingError> {
+    let original_function = function.clone();
+    let original_size = count_instrs(function);
+    let max_size = (original_size as f64 * config.max_code_growth).floor() as usize;
+
+    let mut num_blocks_added = 0;
+    let mut made_fallthroughs_explicit = false;
+
+    loop {
+        let plan = {
+            let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+            let mut reduction_graph = ReductionGraph::from_cfg(&cfg);
+            reduction_graph.reduce();
+
+            if reduction_graph.is_reduced() {
+                return Ok(num_blocks_added);
+            }
+
+            let stuck_regions = reduction_graph.find_stuck_regions();
+
+            // split the smallest stuck node. every non-entry node left has multiple predecessors
+            let region_size = |node: &usize| -> usize {
+                reduction_graph
+                    .members
+                    .get(node)
+                    .unwrap()
+                    .iter()
+                    .map(|block_id| {
+                        cfg.get_function()
+                            .get_block_by_id(*block_id)
+                            .unwrap()
+                            .instrs
+                            .len()
+                    })
+                    .sum()
+            };
+
+            let header = stuck_regions
+                .iter()
+                .flat_map(|(component, _)| component.iter().copied())
+                .filter(|node| *node != reduction_graph.entry)
+                .min_by_key(|node| (region_size(node), *node))
+                .unwrap();
+
+            let pred_nodes: Vec<usize> = reduction_graph
+                .predecessors
+                .get(&header)
+                .unwrap()
+                .iter()
+                .copied()
+                .collect();
+
+            let size_after_split =
+                count_instrs(cfg.get_function()) + (pred_nodes.len() - 1) * region_size(&header);
+            if size_after_split > max_size {
+                drop(cfg);
+                *function = original_function;
+                return Err(NodeSplittingError::CodeGrowthLimitExceeded(
+                    stuck_regions
+                        .into_iter()
+                        .map(|(_, region)| region)
+                        .collect(),
+                ));
+            }
+
+            // the first predecessor keeps the original region, the rest each get a copy
+            let redirected_preds = pred_nodes[1..]
+                .iter()
+                .map(|pred_node| {
+                    reduction_graph
+                        .members
+                        .get(pred_node)
+                        .unwrap()
+                        .iter()
+                        .copied()
+                        .filter(|block_id| cfg.get_successors(*block_id).contains(&header))
+                        .collect()
+                })
+                .collect();
+
+            SplitPlan {
+                header,
+                members: reduction_graph.members.get(&header).unwrap().clone(),
+                redirected_preds,
+            }
+        };
+
+        if !made_fallthroughs_explicit {
+            // copies get appended to the end of the function, so nothing can rely on falling through
+            function.make_fallthroughs_explicit();
+            made_fallthroughs_explicit = true;
+        }
+
+        for preds in &plan.redirected_preds {
+            num_blocks_added += split_region(function, plan.header, &plan.members, preds);
+        }
+    }
+}
+
+// copies every block in members, retargets jumps between members to the copies,
+// then points preds at the copy of header. returns the number of blocks added
+fn split_region(
+    function: &mut FunctionBlocks,
+    header: usize,
+    members: &BTreeSet<usize>,
+    preds: &[usize],
+) -> usize {
+    let mut copy_names: HashMap<String, String> = HashMap::new();
+    let mut copy_ids: Vec<usize> = Vec::new();
+
+    for member in members {
+        let block = function.get_block_by_id(*member).unwrap();
+        let old_name = block.get_name();
+        let new_name = function.create_unique_block_name(&old_name);
+
+        let mut instrs = block.instrs.clone();
+        if instrs.first().is_some_and(|i| i.is_label()) {
+            instrs.remove(0);
+        }
+        instrs.insert(0, Instruction::new_label(&new_name));
+
+        copy_ids.push(function.add_block(instrs));
+        copy_names.insert(old_name, new_name);
+    }
+
+    for copy_id in &copy_ids {
+        retarget_jumps(function, *copy_id, &copy_names);
+    }
+
+    let header_name = function.get_block_name(header).unwrap();
+    let header_copy_names = HashMap::from([(
+        header_name.clone(),
+        copy_names.get(&header_name).unwrap().clone(),
+    )]);
+    for pred in preds {
+        retarget_jumps(function, *pred, &header_copy_names);
+    }
+
+    copy_ids.len()
+}
+
+fn retarget_jumps(
+    function: &mut FunctionBlocks,
+    block_id: usize,
+    new_targets: &HashMap<String, String>,
+) {
+    let block = function.get_mut_block_by_id(block_id).unwrap();
+    let last_instr = block.instrs.last_mut().unwrap();
+    if !last_instr.is_jump() {
+        return;
+    }
+
+    let mut new_instr = last_instr.as_ref().clone();
+    for label in new_instr.get_labels_mut().unwrap() {
+        if let Some(new_target) = new_targets.get(label) {
+            *label = new_target.clone();
+        }
+    }
+
+    *last_instr = Rc::new(new_instr);
+}
+
+fn count_instrs(function: &FunctionBlocks) -> usize {
+    function.get_blocks().iter().map(|b| b.instrs.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::{
+        basicblock::{FunctionBlocks, FunctionBlocksLoader},
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        cfg::{test_util::get_mock_function_blocks, ControlFlowGraph},
+    };
+
+    use super::{make_reducible, IrreducibleRegion, NodeSplittingConfig, NodeSplittingError};
+
+    /*
+        entry: br cond .a .b
+        .a: x = 1; br cond .b .end
+        .b: y = 2; jmp .a
+        .end: ret
+
+        the loop between a and b can be entered through either of them
+    */
+    fn get_irreducible_function() -> FunctionBlocks {
+        let label = |l: &str| vec![l.to_string()];
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["a".to_string(), "b".to_string()],
+            ),
+            Instruction::new_label("a"),
+            Instruction::new_const(OpCode::Const, "x".to_string(), Type::Int, Value::Int(1)),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["b".to_string(), "end".to_string()],
+            ),
+            Instruction::new_label("b"),
+            Instruction::new_const(OpCode::Const, "y".to_string(), Type::Int, Value::Int(2)),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], label("a")),
+            Instruction::new_label("end"),
+            Instruction::new_effect(OpCode::Ret, vec![], vec![], vec![]),
+        ];
+
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("cond".to_string(), Type::Bool)],
+            instrs,
+        );
+
+        FunctionBlocksLoader::new(function).load().unwrap()
+    }
+
+    #[test]
+    fn test_reducible() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let edges = HashMap::from([
+            (0, vec![1]),
+            (1, vec![2, 3]),
+            (2, vec![4, 5]),
+            (4, vec![5]),
+            (5, vec![1]),
+        ]);
+        let cfg =
+            ControlFlowGraph::create_from_edges(&mut mock_blocks, edges, vec![0, 1, 2, 3, 4, 5]);
+
+        assert!(cfg.is_reducible());
+        assert!(cfg.find_irreducible_regions().is_empty());
+    }
+
+    #[test]
+    fn test_irreducible_regions() {
+        let mut mock_blocks = get_mock_function_blocks();
+        // the cycle 1 <-> 2 is entered from 0 at both 1 and 2. 3 -> 4 -> 3 is a regular loop
+        // after it, which the reduction merges into 1 but isn't part of the irreducible region
+        let edges = HashMap::from([
+            (0, vec![1, 2]),
+            (1, vec![2, 3]),
+            (2, vec![1]),
+            (3, vec![4]),
+            (4, vec![3, 5]),
+        ]);
+        let cfg =
+            ControlFlowGraph::create_from_edges(&mut mock_blocks, edges, vec![0, 1, 2, 3, 4, 5]);
+
+        assert!(!cfg.is_reducible());
+        assert_eq!(
+            cfg.find_irreducible_regions(),
+            vec![IrreducibleRegion {
+                blocks: BTreeSet::from([1, 2]),
+                entries: BTreeSet::from([1, 2]),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_make_reducible() {
+        let mut function = get_irreducible_function();
+        {
+            let cfg = ControlFlowGraph::create_from_basic_blocks(&mut function);
+            assert!(!cfg.is_reducible());
+        }
+
+        let num_blocks_added =
+            make_reducible(&mut function, &NodeSplittingConfig::default()).unwrap();
+        assert_eq!(num_blocks_added, 1);
+        assert_eq!(function.get_blocks().len(), 5);
+
+        // a now jumps to its own copy of b, which loops back to a
+        let a = function.get_block_by_name("a").unwrap();
+        assert_eq!(
+            a.instrs.last().unwrap().get_labels_copy().unwrap(),
+            vec!["b.1".to_string(), "end".to_string()]
+        );
+
+        let b_copy = function.get_block_by_name("b.1").unwrap();
+        assert_eq!(b_copy.instrs.len(), 3);
+        assert_eq!(
+            b_copy.instrs.last().unwrap().get_labels_copy().unwrap(),
+            vec!["a".to_string()]
+        );
+
+        let cfg = ControlFlowGraph::create_from_basic_blocks(&mut function);
+        assert!(cfg.is_reducible());
+    }
+
+    #[test]
+    fn test_make_reducible_growth_limit() {
+        let mut function = get_irreducible_function();
+
+        let config = NodeSplittingConfig {
+            max_code_growth: 1.0,
+        };
+        let result = make_reducible(&mut function, &config);
+        assert!(matches!(
+            result,
+            Err(NodeSplittingError::CodeGrowthLimitExceeded(regions)) if regions.len() == 1
+        ));
+
+        // nothing changed
+        assert_eq!(function.get_blocks().len(), 4);
+    }
+}
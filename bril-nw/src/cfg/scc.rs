@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+
+// tarjan's algorithm, run with an explicit stack so large graphs can't overflow the call stack.
+// components come out in reverse topological order: a component only has edges into itself or into
+// components that come before it. edges to nodes outside of node_ids are ignored
+pub fn find_strongly_connected_components<F, I>(
+    node_ids: &[usize],
+    successors: F,
+) -> Vec<Vec<usize>>
+where
+    F: Fn(usize) -> I,
+    I: IntoIterator<Item = usize>,
+{
+    let node_set: HashSet<usize> = node_ids.iter().copied().collect();
+
+    let mut indices: HashMap<usize, usize> = HashMap::new();
+    let mut lowlinks: HashMap<usize, usize> = HashMap::new();
+    let mut on_stack: HashSet<usize> = HashSet::new();
+    let mut stack: Vec<usize> = Vec::new();
+    let mut components: Vec<Vec<usize>> = Vec::new();
+
+    // numbers a newly discovered node and returns its call stack frame
+    let visit = |node: usize,
+                 indices: &mut HashMap<usize, usize>,
+                 lowlinks: &mut HashMap<usize, usize>,
+                 on_stack: &mut HashSet<usize>,
+                 stack: &mut Vec<usize>|
+     -> (usize, Vec<usize>, usize) {
+        let index = indices.len();
+        indices.insert(node, index);
+        lowlinks.insert(node, index);
+        on_stack.insert(node);
+        stack.push(node);
+
+        let node_successors = successors(node)
+            .into_iter()
+            .filter(|s| node_set.contains(s))
+            .collect();
+        (node, node_successors, 0)
+    };
+
+    for root in node_ids {
+        if indices.contains_key(root) {
+            continue;
+        }
+
+        // (node, its successors, index of the next successor to visit)
+        let mut call_stack: Vec<(usize, Vec<usize>, usize)> = Vec::new();
+
+        call_stack.push(visit(
+            *root,
+            &mut indices,
+            &mut lowlinks,
+            &mut on_stack,
+            &mut stack,
+        ));
+
+        while let Some((node, node_successors, next)) = call_stack.last_mut() {
+            let node = *node;
+            if *next < node_successors.len() {
+                let successor = node_successors[*next];
+                *next += 1;
+
+                if !indices.contains_key(&successor) {
+                    call_stack.push(visit(
+                        successor,
+                        &mut indices,
+                        &mut lowlinks,
+                        &mut on_stack,
+                        &mut stack,
+                    ));
+                } else if on_stack.contains(&successor) {
+                    let lowlink = lowlinks[&node].min(indices[&successor]);
+                    lowlinks.insert(node, lowlink);
+                }
+
+                continue;
+            }
+
+            call_stack.pop();
+            if let Some((parent, _, _)) = call_stack.last() {
+                let lowlink = lowlinks[parent].min(lowlinks[&node]);
+                lowlinks.insert(*parent, lowlink);
+            }
+
+            if lowlinks[&node] == indices[&node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack.remove(&member);
+                    component.push(member);
+
+                    if member == node {
+                        break;
+                    }
+                }
+
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::find_strongly_connected_components;
+
+    #[test]
+    fn test_scc_1() {
+        let edges: HashMap<usize, Vec<usize>> = HashMap::from([
+            (0, vec![1]),
+            (1, vec![2, 3]),
+            (2, vec![1]),
+            (3, vec![4]),
+            (4, vec![3, 5]),
+        ]);
+
+        let components = find_strongly_connected_components(&[0, 1, 2, 3, 4, 5], |n| {
+            edges.get(&n).cloned().unwrap_or_default()
+        });
+
+        assert_eq!(components, vec![vec![5], vec![3, 4], vec![1, 2], vec![0]]);
+    }
+}
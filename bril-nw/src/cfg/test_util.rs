@@ -0,0 +1,20 @@
+use std::collections::HashMap;
+
+use crate::basicblock::FunctionBlocks;
+
+// a function without any blocks, for cfgs built straight from edges
+pub(crate) fn get_mock_function_blocks() -> FunctionBlocks {
+    FunctionBlocks::new("", vec![], vec![], HashMap::new(), HashMap::new())
+}
+
+// 0 -> 1 -> {2, 3}, 2 -> 4, 3 -> 4, 4 -> {1, 5}
+// a loop around a diamond with a single exit
+pub(crate) fn get_loop_around_diamond_edges() -> HashMap<usize, Vec<usize>> {
+    HashMap::from([
+        (0, vec![1]),
+        (1, vec![2, 3]),
+        (2, vec![4]),
+        (3, vec![4]),
+        (4, vec![1, 5]),
+    ])
+}
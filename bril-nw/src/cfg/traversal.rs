@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+
+use super::ControlFlowGraph;
+
+/*
+    Depth-first orders over the cfg, starting from the entry block. Blocks unreachable from the
+    entry are never visited.
+
+    - preorder: a block comes before everything first discovered through it
+    - postorder: a block comes after everything first discovered through it
+    - reverse postorder: a block comes before all of its successors, ignoring back edges.
+      this is the best order for forward dataflow problems
+    - reverse cfg postorder: postorder over the cfg with its edges flipped, starting from the
+      exiting blocks. reversed, this is the best order for backward dataflow problems
+
+    Successors are explored in the order they appear in the cfg, so e.g. the "true" side of a br
+    is explored before the "false" side.
+*/
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    // edge along which a block was first discovered
+    Tree,
+    // edge to a block that is still being explored, i.e. an ancestor in the dfs tree
+    Back,
+    // edge to an already explored descendant in the dfs tree
+    Forward,
+    // any other edge, between blocks where neither is an ancestor of the other
+    Cross,
+}
+
+pub struct Preorder<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    stack: Vec<usize>,
+    visited: Vec<bool>,
+}
+
+pub struct Postorder<'g, 'a> {
+    cfg: &'g ControlFlowGraph<'a>,
+    reverse_edges: bool,
+    // roots that have yet to be explored
+    roots: Vec<usize>,
+    // (block id, index of the next edge to explore)
+    stack: Vec<(usize, usize)>,
+    visited: Vec<bool>,
+}
+
+impl<'g, 'a> Iterator for Preorder<'g, 'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(block_id) = self.stack.pop() {
+            if visited(&self.visited, block_id) {
+                continue;
+            }
+
+            mark_visited(&mut self.visited, block_id);
+
+            // pushed backwards so the first successor is explored first
+            for successor in self.cfg.get_successors(block_id).iter().rev() {
+                if !visited(&self.visited, *successor) {
+                    self.stack.push(*successor);
+                }
+            }
+
+            return Some(block_id);
+        }
+
+        None
+    }
+}
+
+impl<'g, 'a> Postorder<'g, 'a> {
+    fn get_edges(&self, block_id: usize) -> &'g [usize] {
+        if self.reverse_edges {
+            self.cfg.get_predecessors(block_id)
+        } else {
+            self.cfg.get_successors(block_id)
+        }
+    }
+}
+
+impl<'g, 'a> Iterator for Postorder<'g, 'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.stack.is_empty() {
+                let root = self.roots.pop()?;
+                if !visited(&self.visited, root) {
+                    mark_visited(&mut self.visited, root);
+                    self.stack.push((root, 0));
+                }
+
+                continue;
+            }
+
+            let (block_id, next_edge) = *self.stack.last().unwrap();
+            let edges = self.get_edges(block_id);
+
+            if next_edge < edges.len() {
+                let next_block_id = edges[next_edge];
+                self.stack.last_mut().unwrap().1 += 1;
+
+                if !visited(&self.visited, next_block_id) {
+                    mark_visited(&mut self.visited, next_block_id);
+                    self.stack.push((next_block_id, 0));
+                }
+            } else {
+                self.stack.pop();
+                return Some(block_id);
+            }
+        }
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn preorder(&self) -> Preorder<'_, 'a> {
+        Preorder {
+            cfg: self,
+            stack: self.get_entry_roots(),
+            visited: self.create_visited_set(),
+        }
+    }
+
+    pub fn postorder(&self) -> Postorder<'_, 'a> {
+        Postorder {
+            cfg: self,
+            reverse_edges: false,
+            roots: self.get_entry_roots(),
+            stack: Vec::new(),
+            visited: self.create_visited_set(),
+        }
+    }
+
+    pub fn reverse_postorder(&self) -> impl Iterator<Item = usize> {
+        let mut order: Vec<usize> = self.postorder().collect();
+        order.reverse();
+
+        order.into_iter()
+    }
+
+    // postorder of the reversed cfg, exploring from every block that exits the function.
+    // see find_exiting_blocks for how infinite loops are handled
+    pub fn reverse_cfg_postorder(&self) -> Postorder<'_, 'a> {
+        let mut roots = self.find_exiting_blocks();
+        roots.reverse(); // roots are popped off the end
+
+        Postorder {
+            cfg: self,
+            reverse_edges: true,
+            roots,
+            stack: Vec::new(),
+            visited: self.create_visited_set(),
+        }
+    }
+
+    // classifies every edge reachable from the entry by how a depth-first search from the entry sees it
+    pub fn classify_edges(&self) -> BTreeMap<(usize, usize), EdgeKind> {
+        let mut result = BTreeMap::new();
+        if self.get_all_block_ids().is_empty() {
+            return result;
+        }
+
+        let mut counter = 0;
+        let mut preorder_numbers: Vec<Option<usize>> = vec![None; self.get_visited_set_size()];
+        let mut finished = self.create_visited_set();
+
+        let entry = self.get_entry_block_id();
+        preorder_numbers[entry] = Some(counter);
+        let mut stack: Vec<(usize, usize)> = vec![(entry, 0)];
+
+        while let Some((block_id, next_edge)) = stack.last_mut() {
+            let block_id = *block_id;
+            let successors = self.get_successors(block_id);
+
+            if *next_edge == successors.len() {
+                mark_visited(&mut finished, block_id);
+                stack.pop();
+                continue;
+            }
+
+            let successor = successors[*next_edge];
+            *next_edge += 1;
+
+            let kind = match preorder_numbers[successor] {
+                None => {
+                    counter += 1;
+                    preorder_numbers[successor] = Some(counter);
+                    stack.push((successor, 0));
+
+                    EdgeKind::Tree
+                }
+                Some(_) if !visited(&finished, successor) => EdgeKind::Back,
+                Some(successor_number)
+                    if preorder_numbers[block_id].unwrap() < successor_number =>
+                {
+                    EdgeKind::Forward
+                }
+                Some(_) => EdgeKind::Cross,
+            };
+
+            result.insert((block_id, successor), kind);
+        }
+
+        result
+    }
+
+    fn get_entry_roots(&self) -> Vec<usize> {
+        if self.get_all_block_ids().is_empty() {
+            vec![]
+        } else {
+            vec![self.get_entry_block_id()]
+        }
+    }
+
+    fn get_visited_set_size(&self) -> usize {
+        self.get_all_block_ids()
+            .iter()
+            .max()
+            .map_or(0, |max_id| max_id + 1)
+    }
+
+    // block ids are small and dense, so a flat vec is cheaper than hashing
+    fn create_visited_set(&self) -> Vec<bool> {
+        vec![false; self.get_visited_set_size()]
+    }
+}
+
+fn visited(visited_set: &[bool], block_id: usize) -> bool {
+    visited_set.get(block_id).copied().unwrap_or(false)
+}
+
+fn mark_visited(visited_set: &mut Vec<bool>, block_id: usize) {
+    if block_id >= visited_set.len() {
+        visited_set.resize(block_id + 1, false);
+    }
+
+    visited_set[block_id] = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::cfg::{test_util::get_mock_function_blocks, ControlFlowGraph};
+
+    use super::EdgeKind;
+
+    // 0 -> {1, 4}, 1 -> 2, 2 -> {1, 3}, 3 returns, 4 -> 3, 5 is unreachable
+    fn get_test_cfg_edges() -> HashMap<usize, Vec<usize>> {
+        HashMap::from([
+            (0, vec![1, 4]),
+            (1, vec![2]),
+            (2, vec![1, 3]),
+            (4, vec![3]),
+            (5, vec![3]),
+        ])
+    }
+
+    #[test]
+    fn test_orders() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            get_test_cfg_edges(),
+            vec![0, 1, 2, 3, 4, 5],
+        );
+
+        assert_eq!(cfg.preorder().collect::<Vec<usize>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(cfg.postorder().collect::<Vec<usize>>(), vec![3, 2, 1, 4, 0]);
+        assert_eq!(
+            cfg.reverse_postorder().collect::<Vec<usize>>(),
+            vec![0, 4, 1, 2, 3]
+        );
+
+        // from the only exiting block 3 backwards: 3 <- 2 <- 1 <- 0, then 1 <- 2 (seen), then 4, then 5
+        assert_eq!(
+            cfg.reverse_cfg_postorder().collect::<Vec<usize>>(),
+            vec![0, 1, 2, 4, 5, 3]
+        );
+    }
+
+    #[test]
+    fn test_classify_edges() {
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            HashMap::from([
+                (0, vec![1, 3]),
+                (1, vec![2, 3]),
+                (2, vec![1]),
+                (3, vec![]),
+                (4, vec![2]),
+            ]),
+            vec![0, 1, 2, 3, 4],
+        );
+
+        let expected = BTreeMap::from([
+            ((0, 1), EdgeKind::Tree),
+            ((0, 3), EdgeKind::Forward),
+            ((1, 2), EdgeKind::Tree),
+            ((1, 3), EdgeKind::Tree),
+            ((2, 1), EdgeKind::Back),
+        ]);
+        assert_eq!(cfg.classify_edges(), expected);
+
+        let mut mock_blocks = get_mock_function_blocks();
+        let cfg = ControlFlowGraph::create_from_edges(
+            &mut mock_blocks,
+            HashMap::from([(0, vec![1, 2]), (1, vec![3]), (2, vec![3])]),
+            vec![0, 1, 2, 3],
+        );
+        assert_eq!(cfg.classify_edges().get(&(2, 3)), Some(&EdgeKind::Cross));
+    }
+}
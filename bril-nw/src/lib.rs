@@ -6,6 +6,9 @@ extern crate lazy_static;
 
 pub mod basicblock;
 pub mod bril;
+pub mod callgraph;
 pub mod cfg;
 pub mod opt;
+pub mod points_to;
+pub mod purity;
 pub mod ssa;
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use crate::{
+    basicblock::FunctionBlocks, bril::types::OpCode, cfg::ControlFlowGraph,
+    opt::GlobalOptimizationPass,
+};
+
+/*
+    Picks an order for the blocks of a function so that blocks end up right after the block
+    jumping to them, then drops the jmps that became fallthroughs.
+
+    Blocks are laid out by greedily chaining edges together, heaviest first (Pettis & Hansen). An
+    edge a -> b joins a's chain and b's chain if a is the last block of its chain and b is the first
+    block of its own. The entry's chain always goes first, the rest follow in the order their first
+    blocks originally appeared in.
+
+    Edge weights can be supplied, e.g. from a profile. Otherwise they are guessed:
+    - a jmp is the only edge out of its block, and the only kind of edge a fallthrough can replace
+    - edges going back to an earlier block are most likely loop back edges, and putting a loop
+      header after its latch tends to break up the rest of the loop
+
+    Before reordering, every fallthrough is made explicit, so the order can't change what the
+    function does. Afterwards jmps to the block right after are removed again, except where that
+    would leave the block empty.
+*/
+pub struct BlockLayout {
+    // (from block id, to block id) -> weight. edges that are left out have weight 0
+    edge_weights: Option<HashMap<(usize, usize), f64>>,
+}
+
+const JUMP_EDGE_WEIGHT: f64 = 2.0;
+const BRANCH_EDGE_WEIGHT: f64 = 1.0;
+const BACKWARD_EDGE_FACTOR: f64 = 0.5;
+
+impl BlockLayout {
+    pub fn new() -> Self {
+        BlockLayout { edge_weights: None }
+    }
+
+    pub fn with_edge_weights(edge_weights: HashMap<(usize, usize), f64>) -> Self {
+        BlockLayout {
+            edge_weights: Some(edge_weights),
+        }
+    }
+
+    // all edges in the function, with their weights, heaviest first
+    fn get_weighted_edges(&self, function: &mut FunctionBlocks) -> Vec<(usize, usize, f64)> {
+        let original_positions: HashMap<usize, usize> = function
+            .get_blocks()
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (block.get_id(), idx))
+            .collect();
+
+        let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+
+        let mut result = Vec::new();
+        for from in cfg.get_all_block_ids() {
+            let successors = cfg.get_successors(*from);
+            for to in successors {
+                let weight = match &self.edge_weights {
+                    Some(edge_weights) => edge_weights.get(&(*from, *to)).copied().unwrap_or(0.0),
+                    None => {
+                        let is_jump = cfg
+                            .get_function()
+                            .get_block_by_id(*from)
+                            .unwrap()
+                            .instrs
+                            .last()
+                            .and_then(|i| i.get_op_code())
+                            == Some(OpCode::Jump);
+                        let weight = if is_jump {
+                            JUMP_EDGE_WEIGHT
+                        } else {
+                            BRANCH_EDGE_WEIGHT
+                        };
+
+                        if original_positions[to] <= original_positions[from] {
+                            weight * BACKWARD_EDGE_FACTOR
+                        } else {
+                            weight
+                        }
+                    }
+                };
+
+                result.push((*from, *to, weight));
+            }
+        }
+
+        // stable, so equal weights stay in program order
+        result.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        result
+    }
+
+    fn find_layout(&self, function: &mut FunctionBlocks) -> Vec<usize> {
+        let block_ids: Vec<usize> = function.get_blocks().iter().map(|b| b.get_id()).collect();
+        let Some(entry) = block_ids.first().copied() else {
+            return vec![];
+        };
+
+        // every block starts out in a chain of its own, numbered by the block's original position
+        let mut chains: Vec<Vec<usize>> = block_ids.iter().map(|id| vec![*id]).collect();
+        let mut chain_of: HashMap<usize, usize> = block_ids
+            .iter()
+            .enumerate()
+            .map(|(chain, id)| (*id, chain))
+            .collect();
+
+        for (from, to, _) in self.get_weighted_edges(function) {
+            let from_chain = chain_of[&from];
+            let to_chain = chain_of[&to];
+
+            let can_join = from_chain != to_chain
+                && to != entry
+                && chains[from_chain].last() == Some(&from)
+                && chains[to_chain].first() == Some(&to);
+            if !can_join {
+                continue;
+            }
+
+            let moved = std::mem::take(&mut chains[to_chain]);
+            for id in &moved {
+                chain_of.insert(*id, from_chain);
+            }
+            chains[from_chain].extend(moved);
+        }
+
+        // the entry is always the first block of the first chain, since nothing can be joined in
+        // front of it. the rest stay in program order
+        chains.into_iter().flatten().collect()
+    }
+}
+
+impl Default for BlockLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlobalOptimizationPass for BlockLayout {
+    fn run(&mut self, function: &mut FunctionBlocks) {
+        function.make_fallthroughs_explicit();
+
+        let layout = self.find_layout(function);
+        function.reorder_blocks(&layout);
+
+        remove_jumps_to_next_block(function);
+    }
+}
+
+fn remove_jumps_to_next_block(function: &mut FunctionBlocks) {
+    let blocks = function.get_mut_blocks();
+    for i in 0..blocks.len().saturating_sub(1) {
+        let next_label = blocks[i + 1]
+            .instrs
+            .first()
+            .and_then(|instr| instr.get_label())
+            .map(|label| label.to_string());
+
+        let block = &mut blocks[i];
+        let jumps_to_next = block.instrs.last().is_some_and(|instr| {
+            instr.get_op_code() == Some(OpCode::Jump)
+                && instr.get_labels_copy().unwrap().first() == next_label.as_ref()
+        });
+
+        // a block with nothing but a jmp can't be left empty
+        if jumps_to_next && block.instrs.len() > 1 {
+            block.instrs.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        basicblock::{FunctionBlocks, FunctionBlocksLoader},
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type},
+        opt::GlobalOptimizationPass,
+    };
+
+    use super::BlockLayout;
+
+    // entry branches to left or right, which both jump to exit. right jumps to the block right
+    // after it, left jumps back up, so there are two jmps to start with
+    fn get_test_function() -> FunctionBlocks {
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["left".to_string(), "right".to_string()],
+            ),
+            Instruction::new_label("right"),
+            Instruction::new_effect(OpCode::Print, vec!["cond".to_string()], vec![], vec![]),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["exit".to_string()]),
+            Instruction::new_label("exit"),
+            Instruction::new_effect(OpCode::Ret, vec![], vec![], vec![]),
+            Instruction::new_label("left"),
+            Instruction::new_effect(OpCode::Print, vec!["cond".to_string()], vec![], vec![]),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["exit".to_string()]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("cond".to_string(), Type::Bool)],
+            instrs,
+        );
+
+        FunctionBlocksLoader::new(function).load().unwrap()
+    }
+
+    fn get_block_names(function: &FunctionBlocks) -> Vec<String> {
+        function.get_blocks().iter().map(|b| b.get_name()).collect()
+    }
+
+    fn count_jumps(function: &FunctionBlocks) -> usize {
+        function
+            .get_blocks()
+            .iter()
+            .flat_map(|b| b.instrs.iter())
+            .filter(|i| i.get_op_code() == Some(OpCode::Jump))
+            .count()
+    }
+
+    #[test]
+    fn test_layout_heuristic() {
+        let mut function = get_test_function();
+        BlockLayout::new().run(&mut function);
+
+        // right -> exit is the heaviest edge, so right falls through into exit. left -> exit goes
+        // backwards, so entry -> left is taken first and left keeps its jmp
+        assert_eq!(
+            get_block_names(&function),
+            vec!["entry", "left", "right", "exit"]
+        );
+        assert_eq!(count_jumps(&function), 1);
+        assert_eq!(function.get_blocks()[2].instrs.len(), 2);
+    }
+
+    #[test]
+    fn test_layout_edge_weights() {
+        let mut function = get_test_function();
+
+        // block ids are entry: 0, right: 1, exit: 2, left: 3
+        let edge_weights = HashMap::from([((0, 3), 10.0), ((3, 2), 10.0), ((1, 2), 1.0)]);
+        BlockLayout::with_edge_weights(edge_weights).run(&mut function);
+
+        assert_eq!(
+            get_block_names(&function),
+            vec!["entry", "left", "exit", "right"]
+        );
+        assert_eq!(count_jumps(&function), 1);
+
+        // right is now last, so it keeps its jmp back up to exit
+        let right = function.get_block_by_id(1).unwrap();
+        assert_eq!(
+            right.instrs.last().unwrap().get_labels_copy(),
+            Some(vec!["exit".to_string()])
+        );
+    }
+}
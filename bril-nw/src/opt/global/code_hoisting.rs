@@ -0,0 +1,403 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    rc::Rc,
+};
+
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::Instruction,
+    cfg::{
+        dataflow::{
+            anticipated_expressions::AnticipatedExpressions, available_expressions::Expression,
+        },
+        ControlFlowGraph,
+    },
+    opt::{local::LVNCanonicalExpression, GlobalOptimizationPass},
+};
+
+const TEMP_VAR_PFX: &str = "hoist";
+
+/*
+    Shrinks code by computing an expression once, in the nearest block dominating every block that
+    computes it, instead of in each of them.
+
+    An expression is only hoisted into a block H if
+    - it's anticipated at the end of H (see AnticipatedExpressions), so every path leaving H
+      computes it anyway and gets the same value
+    - each of its operands is a function arg or defined in a block dominating H, so they all hold
+      a value at the end of H
+    - none of its operands are defined again anywhere reachable from H, so every computation of it
+      gives the same value as the hoisted one
+    - the vars its computations write to aren't written anywhere else, so they can all be replaced
+      by the var the hoisted computation writes to
+
+    The hoisted computation goes right before H's terminator, and writes to the var the original
+    computations wrote to, or to a new temporary if they wrote to different vars. The original
+    computations are deleted, and their vars renamed to the hoisted one.
+*/
+pub struct CodeHoisting();
+
+// every computation of one expression
+struct Computations {
+    blocks: BTreeSet<usize>,
+    // any one of them
+    instr: Rc<Instruction>,
+    // the vars they write to
+    dests: BTreeSet<String>,
+}
+
+// an expression to compute in target instead of everywhere else
+struct Hoist {
+    target: usize,
+    expr: Expression,
+    computations: Computations,
+}
+
+impl Default for CodeHoisting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeHoisting {
+    pub fn new() -> Self {
+        CodeHoisting()
+    }
+
+    // the first expression worth hoisting, if any
+    fn find_hoist(function: &mut FunctionBlocks) -> Option<Hoist> {
+        let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+        let dom_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let analysis = AnticipatedExpressions::new(&cfg);
+        let results = analysis.analyze();
+
+        let mut computations: BTreeMap<Expression, Computations> = BTreeMap::new();
+        // var -> blocks defining it
+        let mut definitions: BTreeMap<&str, BTreeSet<usize>> = BTreeMap::new();
+        // var -> the expression computed into it, or None if anything else defines it
+        let mut defining_exprs: BTreeMap<&str, Option<Expression>> = BTreeMap::new();
+        for block in cfg.get_function().get_blocks() {
+            for instr in &block.instrs {
+                let Some(dest) = instr.get_dest() else {
+                    continue;
+                };
+                definitions.entry(dest).or_default().insert(block.get_id());
+
+                let expr = LVNCanonicalExpression::from_instr(instr);
+                let defining_expr = defining_exprs.entry(dest).or_insert(expr.clone());
+                if *defining_expr != expr {
+                    *defining_expr = None;
+                }
+
+                if let Some(expr) = expr {
+                    let expr_computations =
+                        computations.entry(expr).or_insert_with(|| Computations {
+                            blocks: BTreeSet::new(),
+                            instr: instr.clone(),
+                            dests: BTreeSet::new(),
+                        });
+                    expr_computations.blocks.insert(block.get_id());
+                    expr_computations.dests.insert(dest.to_string());
+                }
+            }
+        }
+
+        let args: HashSet<&str> = cfg
+            .get_function()
+            .get_args()
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+
+        for (expr, expr_computations) in computations {
+            let blocks = &expr_computations.blocks;
+            if blocks.len() < 2 {
+                continue;
+            }
+
+            let target = blocks
+                .iter()
+                .skip(1)
+                .try_fold(*blocks.first().unwrap(), |a, b| {
+                    dom_tree.nearest_common_dominator(a, *b)
+                });
+            let Some(target) = target else {
+                continue;
+            };
+
+            // already computed there, nothing to gain
+            if blocks.contains(&target) {
+                continue;
+            }
+
+            if !results.get_out(target).is_some_and(|f| f.contains(&expr)) {
+                continue;
+            }
+
+            let operands_defined = expr.get_args().iter().all(|arg| {
+                args.contains(arg.as_str())
+                    || definitions
+                        .get(arg.as_str())
+                        .is_some_and(|defs| defs.iter().any(|d| dom_tree.dominates(*d, target)))
+            });
+            if !operands_defined {
+                continue;
+            }
+
+            let reachable = find_blocks_reachable_from(&cfg, target);
+            let operands_unchanged = expr.get_args().iter().all(|arg| {
+                definitions
+                    .get(arg.as_str())
+                    .is_none_or(|defs| defs.is_disjoint(&reachable))
+            });
+            if !operands_unchanged {
+                continue;
+            }
+
+            let dests_replaceable = expr_computations.dests.iter().all(|dest| {
+                !args.contains(dest.as_str())
+                    && defining_exprs.get(dest.as_str()) == Some(&Some(expr.clone()))
+            });
+            if !dests_replaceable {
+                continue;
+            }
+
+            return Some(Hoist {
+                target,
+                expr,
+                computations: expr_computations,
+            });
+        }
+
+        None
+    }
+
+    // name is the var the hoisted computation writes to
+    fn apply_hoist(function: &mut FunctionBlocks, hoist: Hoist, name: String) {
+        for block in function.get_mut_blocks() {
+            block.instrs.retain(|instr| {
+                LVNCanonicalExpression::from_instr(instr).as_ref() != Some(&hoist.expr)
+            });
+
+            for instr in block.instrs.iter_mut() {
+                let uses_dest = instr.get_args().is_some_and(|args| {
+                    args.iter()
+                        .any(|arg| hoist.computations.dests.contains(arg))
+                });
+                if !uses_dest {
+                    continue;
+                }
+
+                let instr = Rc::make_mut(instr);
+                for arg in instr.get_args_mut().unwrap() {
+                    if hoist.computations.dests.contains(arg) {
+                        *arg = name.clone();
+                    }
+                }
+            }
+        }
+
+        let mut computation = (*hoist.computations.instr).clone();
+        computation.set_dest(name);
+
+        let block = function.get_mut_block_by_id(hoist.target).unwrap();
+        let idx = match block.instrs.last() {
+            Some(last) if last.is_jump() => block.instrs.len() - 1,
+            _ => block.instrs.len(),
+        };
+        block.instrs.insert(idx, Rc::new(computation));
+    }
+}
+
+impl GlobalOptimizationPass for CodeHoisting {
+    fn run(&mut self, function: &mut FunctionBlocks) {
+        // hoisting renames vars, which can change what else is worth hoisting. every hoist removes
+        // at least one instruction, so this stops
+        let mut num_temps = 0;
+        while let Some(hoist) = Self::find_hoist(function) {
+            let name = match hoist.computations.dests.iter().next() {
+                Some(dest) if hoist.computations.dests.len() == 1 => dest.clone(),
+                _ => {
+                    num_temps += 1;
+                    function.create_unique_var_name(&format!("{}.{}", TEMP_VAR_PFX, num_temps - 1))
+                }
+            };
+
+            Self::apply_hoist(function, hoist, name);
+        }
+    }
+}
+
+// blocks some path out of block_id goes through, including block_id itself if it's in a loop
+fn find_blocks_reachable_from(cfg: &ControlFlowGraph, block_id: usize) -> BTreeSet<usize> {
+    let mut reachable = BTreeSet::new();
+    let mut open_set: VecDeque<usize> = cfg.get_successors(block_id).iter().copied().collect();
+    while let Some(next) = open_set.pop_front() {
+        if reachable.insert(next) {
+            open_set.extend(cfg.get_successors(next).iter().copied());
+        }
+    }
+
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        basicblock::{FunctionBlocks, FunctionBlocksLoader},
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        opt::GlobalOptimizationPass,
+    };
+
+    use super::CodeHoisting;
+
+    fn add(dest: &str, a: &str, b: &str) -> Rc<Instruction> {
+        Instruction::new_value(
+            OpCode::Add,
+            dest.to_string(),
+            Type::Int,
+            vec![a.to_string(), b.to_string()],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn id(dest: &str, src: &str) -> Rc<Instruction> {
+        Instruction::new_value(
+            OpCode::Id,
+            dest.to_string(),
+            Type::Int,
+            vec![src.to_string()],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn print(arg: &str) -> Rc<Instruction> {
+        Instruction::new_effect(OpCode::Print, vec![arg.to_string()], vec![], vec![])
+    }
+
+    fn load(instrs: Vec<Rc<Instruction>>) -> FunctionBlocks {
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![
+                FunctionArg::new("cond".to_string(), Type::Bool),
+                FunctionArg::new("a".to_string(), Type::Int),
+            ],
+            instrs,
+        );
+
+        FunctionBlocksLoader::new(function).load().unwrap()
+    }
+
+    fn diamond(left: Vec<Rc<Instruction>>, right: Vec<Rc<Instruction>>) -> Vec<Rc<Instruction>> {
+        let mut instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_const(OpCode::Const, "b".to_string(), Type::Int, Value::Int(2)),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["left".to_string(), "right".to_string()],
+            ),
+            Instruction::new_label("left"),
+        ];
+        instrs.extend(left);
+        instrs.push(Instruction::new_effect(OpCode::Ret, vec![], vec![], vec![]));
+        instrs.push(Instruction::new_label("right"));
+        instrs.extend(right);
+        instrs.push(Instruction::new_effect(
+            OpCode::Print,
+            vec!["a".to_string()],
+            vec![],
+            vec![],
+        ));
+
+        instrs
+    }
+
+    fn count_instrs(blocks: &FunctionBlocks) -> usize {
+        blocks.get_blocks().iter().map(|b| b.instrs.len()).sum()
+    }
+
+    #[test]
+    fn test_hoist_into_branch() {
+        let mut blocks = load(diamond(
+            vec![add("x", "a", "b"), print("x")],
+            vec![add("y", "b", "a"), print("y")],
+        ));
+        let num_instrs = count_instrs(&blocks);
+        CodeHoisting::new().run(&mut blocks);
+
+        let entry = blocks.get_block_by_id(0).unwrap();
+        assert_eq!(entry.instrs.len(), 4);
+        assert_eq!(entry.instrs[2], add("hoist.0", "a", "b"));
+        assert!(entry.instrs[3].is_jump());
+
+        assert_eq!(
+            blocks.get_block_by_id(1).unwrap().instrs[1],
+            print("hoist.0")
+        );
+        assert_eq!(
+            blocks.get_block_by_id(2).unwrap().instrs[1],
+            print("hoist.0")
+        );
+        assert_eq!(count_instrs(&blocks), num_instrs - 1);
+    }
+
+    #[test]
+    fn test_hoist_keeps_dest() {
+        // both sides write x, so the hoisted computation can too
+        let mut blocks = load(diamond(
+            vec![add("x", "a", "b"), print("x")],
+            vec![add("x", "b", "a"), id("y", "x")],
+        ));
+        let num_instrs = count_instrs(&blocks);
+        CodeHoisting::new().run(&mut blocks);
+
+        let entry = blocks.get_block_by_id(0).unwrap();
+        assert_eq!(entry.instrs[2], add("x", "a", "b"));
+        assert_eq!(blocks.get_block_by_id(1).unwrap().instrs[1], print("x"));
+        assert_eq!(blocks.get_block_by_id(2).unwrap().instrs[1], id("y", "x"));
+        assert_eq!(count_instrs(&blocks), num_instrs - 1);
+    }
+
+    #[test]
+    fn test_no_hoist_when_dest_redefined() {
+        // x also holds something else on the right, so it can't be renamed
+        let instrs = diamond(
+            vec![add("x", "a", "b"), print("x")],
+            vec![add("x", "a", "b"), id("x", "a"), print("x")],
+        );
+        let mut blocks = load(instrs.clone());
+        CodeHoisting::new().run(&mut blocks);
+
+        let all_instrs: Vec<Rc<Instruction>> = blocks
+            .get_blocks()
+            .iter()
+            .flat_map(|b| b.instrs.iter().cloned())
+            .collect();
+        assert_eq!(all_instrs, instrs);
+    }
+
+    #[test]
+    fn test_no_hoist_when_not_anticipated() {
+        // the right side redefines a before computing a + b, so computing it early would be wrong
+        let instrs = diamond(
+            vec![add("x", "a", "b")],
+            vec![add("a", "b", "b"), add("y", "a", "b")],
+        );
+        let mut blocks = load(instrs.clone());
+        CodeHoisting::new().run(&mut blocks);
+
+        let all_instrs: Vec<Rc<Instruction>> = blocks
+            .get_blocks()
+            .iter()
+            .flat_map(|b| b.instrs.iter().cloned())
+            .collect();
+        assert_eq!(all_instrs, instrs);
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::{Instruction, OpCode},
+    cfg::{
+        dataflow::{
+            available_expressions::{AvailableExpressions, Expression},
+            reaching_definitions::InstrLocation,
+        },
+        ControlFlowGraph,
+    },
+    opt::{local::LVNCanonicalExpression, GlobalOptimizationPass},
+    purity::FunctionPurities,
+};
+
+const TEMP_VAR_PFX: &str = "cse";
+
+/*
+    Removes computations of expressions that are already available (see AvailableExpressions),
+    i.e. that every path to them has computed already.
+
+    The variable an earlier computation wrote to may have been overwritten since, and different
+    paths may have computed the expression into different variables. So every computation of a
+    redundant expression writes its result to a new temporary instead (one per expression,
+    numbered cse.0, cse.1, ...), and copies it to its original dest:
+
+        x: int = add a b;         cse.0: int = add a b;
+        ...                 =>    x: int = id cse.0;
+        y: int = add a b;         ...
+                                  y: int = id cse.0;
+
+    The temporary holds the expression's value wherever the expression is available, since the
+    last computation along every path wrote it, and none of the operands changed since.
+    Leftover copies are for copy propagation and dead code elimination to clean up.
+
+    Calls are only ever merged if function purities are given, and the function is pure.
+*/
+pub struct GlobalCommonSubexpressionElimination {
+    purity: Option<FunctionPurities>,
+}
+
+impl Default for GlobalCommonSubexpressionElimination {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlobalCommonSubexpressionElimination {
+    pub fn new() -> Self {
+        GlobalCommonSubexpressionElimination { purity: None }
+    }
+
+    pub fn with_purity(mut self, purity: FunctionPurities) -> Self {
+        self.purity = Some(purity);
+        self
+    }
+
+    fn get_expression(&self, instr: &Instruction) -> Option<Expression> {
+        LVNCanonicalExpression::from_instr_with_purity(instr, self.purity.as_ref())
+    }
+
+    // every computation of an expression that is available right before it, by expression
+    fn find_redundant_computations(
+        &self,
+        function: &mut FunctionBlocks,
+    ) -> BTreeMap<Expression, Vec<InstrLocation>> {
+        let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+        let analysis = match &self.purity {
+            Some(purity) => AvailableExpressions::with_purity(&cfg, purity),
+            None => AvailableExpressions::new(&cfg),
+        };
+        let results = analysis.analyze();
+
+        let mut result: BTreeMap<Expression, Vec<InstrLocation>> = BTreeMap::new();
+        for block in cfg.get_function().get_blocks() {
+            let facts = results
+                .get_instr_facts(&analysis, &cfg, block.get_id())
+                .unwrap();
+
+            for (instr_idx, instr) in block.instrs.iter().enumerate() {
+                let Some(expr) = self.get_expression(instr) else {
+                    continue;
+                };
+
+                if facts[instr_idx].contains(&expr) {
+                    result.entry(expr).or_default().push(InstrLocation {
+                        block_id: block.get_id(),
+                        instr_idx,
+                    });
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl GlobalOptimizationPass for GlobalCommonSubexpressionElimination {
+    fn run(&mut self, function: &mut FunctionBlocks) {
+        let redundant = self.find_redundant_computations(function);
+        if redundant.is_empty() {
+            return;
+        }
+
+        let mut temps: HashMap<Expression, String> = HashMap::new();
+        for (i, expr) in redundant.keys().enumerate() {
+            let temp = function.create_unique_var_name(&format!("{}.{}", TEMP_VAR_PFX, i));
+            temps.insert(expr.clone(), temp);
+        }
+
+        for block in function.get_mut_blocks() {
+            let block_id = block.get_id();
+            let mut instrs = Vec::with_capacity(block.instrs.len());
+
+            for (instr_idx, instr) in block.instrs.drain(..).enumerate() {
+                let expr = self.get_expression(&instr);
+                let Some((expr, temp)) = expr.and_then(|e| temps.get(&e).map(|t| (e, t))) else {
+                    instrs.push(instr);
+                    continue;
+                };
+
+                let location = InstrLocation {
+                    block_id,
+                    instr_idx,
+                };
+                if !redundant[&expr].contains(&location) {
+                    let mut computation = (*instr).clone();
+                    computation.set_dest(temp.clone());
+                    instrs.push(Rc::new(computation));
+                }
+
+                instrs.push(Instruction::new_value(
+                    OpCode::Id,
+                    instr.get_dest().unwrap().to_string(),
+                    instr.get_type().unwrap(),
+                    vec![temp.clone()],
+                    vec![],
+                    vec![],
+                ));
+            }
+
+            block.instrs = instrs;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basicblock::{FunctionBlocks, FunctionBlocksLoader},
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type},
+        opt::GlobalOptimizationPass,
+    };
+
+    use super::GlobalCommonSubexpressionElimination;
+
+    fn add(dest: &str, a: &str, b: &str) -> std::rc::Rc<Instruction> {
+        Instruction::new_value(
+            OpCode::Add,
+            dest.to_string(),
+            Type::Int,
+            vec![a.to_string(), b.to_string()],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn id(dest: &str, src: &str) -> std::rc::Rc<Instruction> {
+        Instruction::new_value(
+            OpCode::Id,
+            dest.to_string(),
+            Type::Int,
+            vec![src.to_string()],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn jump(label: &str) -> std::rc::Rc<Instruction> {
+        Instruction::new_effect(OpCode::Jump, vec![], vec![], vec![label.to_string()])
+    }
+
+    fn branch(cond: &str, if_true: &str, if_false: &str) -> std::rc::Rc<Instruction> {
+        Instruction::new_effect(
+            OpCode::Branch,
+            vec![cond.to_string()],
+            vec![],
+            vec![if_true.to_string(), if_false.to_string()],
+        )
+    }
+
+    fn print(var: &str) -> std::rc::Rc<Instruction> {
+        Instruction::new_effect(OpCode::Print, vec![var.to_string()], vec![], vec![])
+    }
+
+    // runs gcse over main(cond: bool, a: int, b: int) with the given body
+    fn run_gcse(instrs: Vec<std::rc::Rc<Instruction>>) -> FunctionBlocks {
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![
+                FunctionArg::new("cond".to_string(), Type::Bool),
+                FunctionArg::new("a".to_string(), Type::Int),
+                FunctionArg::new("b".to_string(), Type::Int),
+            ],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        GlobalCommonSubexpressionElimination::new().run(&mut blocks);
+
+        blocks
+    }
+
+    fn get_block_instrs(blocks: &FunctionBlocks) -> Vec<Vec<std::rc::Rc<Instruction>>> {
+        blocks
+            .get_blocks()
+            .iter()
+            .map(|block| block.instrs.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_gcse_diamond() {
+        // both sides of the branch compute a + b into different variables, so it's available
+        // after they meet. b + a is the same expression
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["cond".to_string()],
+                vec![],
+                vec!["left".to_string(), "right".to_string()],
+            ),
+            Instruction::new_label("left"),
+            add("x", "a", "b"),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["join".to_string()]),
+            Instruction::new_label("right"),
+            add("y", "b", "a"),
+            add("a", "a", "a"),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["join".to_string()]),
+            Instruction::new_label("join"),
+            add("z", "a", "b"),
+            Instruction::new_effect(OpCode::Print, vec!["z".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![
+                FunctionArg::new("cond".to_string(), Type::Bool),
+                FunctionArg::new("a".to_string(), Type::Int),
+                FunctionArg::new("b".to_string(), Type::Int),
+            ],
+            instrs.clone(),
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+
+        // a is overwritten on the right, so a + b isn't available in join
+        GlobalCommonSubexpressionElimination::new().run(&mut blocks);
+        assert_eq!(blocks.get_block_by_id(3).unwrap().instrs[1], instrs[10]);
+
+        // without the overwrite it is
+        let mut instrs = instrs;
+        instrs.remove(7);
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![
+                FunctionArg::new("cond".to_string(), Type::Bool),
+                FunctionArg::new("a".to_string(), Type::Int),
+                FunctionArg::new("b".to_string(), Type::Int),
+            ],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        GlobalCommonSubexpressionElimination::new().run(&mut blocks);
+
+        let left = blocks.get_block_by_id(1).unwrap();
+        assert_eq!(left.instrs[1], add("cse.0", "a", "b"));
+        assert_eq!(
+            left.instrs[2],
+            Instruction::new_value(
+                OpCode::Id,
+                "x".to_string(),
+                Type::Int,
+                vec!["cse.0".to_string()],
+                vec![],
+                vec![],
+            )
+        );
+
+        let right = blocks.get_block_by_id(2).unwrap();
+        assert_eq!(right.instrs[1], add("cse.0", "b", "a"));
+
+        let join = blocks.get_block_by_id(3).unwrap();
+        assert_eq!(join.instrs.len(), 3);
+        assert_eq!(
+            join.instrs[1],
+            Instruction::new_value(
+                OpCode::Id,
+                "z".to_string(),
+                Type::Int,
+                vec!["cse.0".to_string()],
+                vec![],
+                vec![],
+            )
+        );
+    }
+
+    #[test]
+    fn test_gcse_killed_on_one_path() {
+        // a + b is computed before the branch, but the left side overwrites a
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            add("x", "a", "b"),
+            branch("cond", "left", "right"),
+            Instruction::new_label("left"),
+            add("a", "a", "a"),
+            jump("join"),
+            Instruction::new_label("right"),
+            jump("join"),
+            Instruction::new_label("join"),
+            add("z", "a", "b"),
+            print("z"),
+        ];
+
+        let blocks = run_gcse(instrs.clone());
+        assert_eq!(blocks.get_block_by_id(3).unwrap().instrs[1], instrs[9]);
+        assert_eq!(blocks.get_block_by_id(0).unwrap().instrs[1], instrs[1]);
+    }
+
+    #[test]
+    fn test_gcse_dest_overwrites_operand() {
+        // a = add a b computes a + b with the old a, so c has to compute it again with the new
+        // one. it's d that can reuse c's value
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            add("a", "a", "b"),
+            add("c", "a", "b"),
+            jump("next"),
+            Instruction::new_label("next"),
+            add("d", "a", "b"),
+            print("d"),
+        ];
+
+        let blocks = run_gcse(instrs.clone());
+        assert_eq!(
+            get_block_instrs(&blocks),
+            vec![
+                vec![
+                    instrs[0].clone(),
+                    add("cse.0", "a", "b"),
+                    id("a", "cse.0"),
+                    add("cse.0", "a", "b"),
+                    id("c", "cse.0"),
+                    instrs[3].clone(),
+                ],
+                vec![instrs[4].clone(), id("d", "cse.0"), instrs[6].clone()],
+            ]
+        );
+
+        // on its own, the overwrite leaves nothing to eliminate
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            add("a", "a", "b"),
+            jump("next"),
+            Instruction::new_label("next"),
+            add("d", "a", "b"),
+            print("d"),
+        ];
+
+        let blocks = run_gcse(instrs.clone());
+        assert_eq!(
+            get_block_instrs(&blocks),
+            vec![instrs[0..3].to_vec(), instrs[3..].to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_gcse_commutative_across_blocks() {
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            add("x", "a", "b"),
+            jump("next"),
+            Instruction::new_label("next"),
+            add("y", "b", "a"),
+            print("y"),
+        ];
+
+        let blocks = run_gcse(instrs.clone());
+        assert_eq!(
+            get_block_instrs(&blocks),
+            vec![
+                vec![
+                    instrs[0].clone(),
+                    add("cse.0", "a", "b"),
+                    id("x", "cse.0"),
+                    instrs[2].clone(),
+                ],
+                vec![instrs[3].clone(), id("y", "cse.0"), instrs[5].clone()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gcse_loop_header() {
+        // a + b is computed before the loop, but the loop body overwrites a, so it isn't
+        // available at the header through the back edge
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            add("x", "a", "b"),
+            jump("header"),
+            Instruction::new_label("header"),
+            add("y", "a", "b"),
+            branch("cond", "body", "exit"),
+            Instruction::new_label("body"),
+            add("a", "a", "a"),
+            jump("header"),
+            Instruction::new_label("exit"),
+            print("y"),
+        ];
+
+        let blocks = run_gcse(instrs.clone());
+        assert_eq!(blocks.get_block_by_id(1).unwrap().instrs[1], instrs[4]);
+
+        // if the body leaves a alone, the value from before the loop is still good
+        let mut instrs = instrs;
+        instrs[7] = print("a");
+
+        let blocks = run_gcse(instrs);
+        assert_eq!(
+            blocks.get_block_by_id(1).unwrap().instrs[1],
+            id("y", "cse.0")
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use std::rc::Rc;
+
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::{Instruction, OpCode, Value},
+    cfg::{
+        dataflow::constant_propagation::{get_constant_value, ConstantPropagation, ConstantValue},
+        ControlFlowGraph,
+    },
+    opt::GlobalOptimizationPass,
+};
+
+/*
+    Uses constant propagation over the whole cfg to
+    - replace instructions that always compute the same constant with a const
+    - replace a br on a condition that is always true or always false with a jmp
+
+    The instructions feeding a folded one are left alone, dead code elimination removes them once
+    nothing uses them anymore. Blocks that become unreachable are left in place too, nothing here
+    removes them.
+
+    When a br becomes a jmp, the successor it no longer goes to loses an edge. Phis there drop the
+    operand coming from the branching block, so SSA input stays valid.
+*/
+pub struct ConstantFolding();
+
+impl Default for ConstantFolding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConstantFolding {
+    pub fn new() -> Self {
+        ConstantFolding()
+    }
+
+    // (block id, instr idx, replacement)
+    fn find_rewrites(function: &mut FunctionBlocks) -> Vec<(usize, usize, Rc<Instruction>)> {
+        let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+        let analysis = ConstantPropagation::new();
+        let results = analysis.analyze(&cfg);
+
+        let mut rewrites = Vec::new();
+        for block in cfg.get_function().get_blocks() {
+            let facts = results
+                .get_instr_facts(&analysis, &cfg, block.get_id())
+                .unwrap();
+
+            for (instr_idx, instr) in block.instrs.iter().enumerate() {
+                let rewrite = if instr.is_value() && !instr.has_side_effects() {
+                    let dest = instr.get_dest().unwrap();
+                    match get_constant_value(&facts[instr_idx + 1], dest) {
+                        ConstantValue::Constant(value) => Some(Instruction::new_const(
+                            OpCode::Const,
+                            dest.to_string(),
+                            instr.get_type().unwrap(),
+                            value,
+                        )),
+                        _ => None,
+                    }
+                } else if instr.get_op_code() == Some(OpCode::Branch) {
+                    let cond = &instr.get_args().unwrap()[0];
+                    let labels = instr.get_labels_copy().unwrap();
+                    match get_constant_value(&facts[instr_idx], cond) {
+                        ConstantValue::Constant(Value::Bool(taken)) => {
+                            let target = if taken { &labels[0] } else { &labels[1] };
+                            Some(Instruction::new_effect(
+                                OpCode::Jump,
+                                vec![],
+                                vec![],
+                                vec![target.clone()],
+                            ))
+                        }
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+
+                if let Some(rewrite) = rewrite {
+                    rewrites.push((block.get_id(), instr_idx, rewrite));
+                }
+            }
+        }
+
+        rewrites
+    }
+}
+
+impl GlobalOptimizationPass for ConstantFolding {
+    fn run(&mut self, function: &mut FunctionBlocks) {
+        for (block_id, instr_idx, rewrite) in Self::find_rewrites(function) {
+            let block = function.get_mut_block_by_id(block_id).unwrap();
+            let old = std::mem::replace(&mut block.instrs[instr_idx], rewrite.clone());
+
+            if old.get_op_code() == Some(OpCode::Branch) {
+                let taken = rewrite.get_labels_copy().unwrap();
+                for label in old.get_labels_copy().unwrap() {
+                    if !taken.contains(&label) {
+                        remove_phi_operands(function, &label, block_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// drops the operands coming from block from_id out of the phis at the start of block label
+fn remove_phi_operands(function: &mut FunctionBlocks, label: &str, from_id: usize) {
+    let Some(from_name) = function.get_block_name(from_id) else {
+        return;
+    };
+    let Some(block) = function
+        .get_block_idx_by_name(label)
+        .and_then(|id| function.get_mut_block_by_id(id))
+    else {
+        return;
+    };
+
+    for instr in block.instrs.iter_mut().filter(|i| i.is_phi()) {
+        let instr = Rc::make_mut(instr);
+        let labels = instr.get_labels_copy().unwrap();
+        let args = instr.get_args_copy();
+
+        let (args, labels): (Vec<String>, Vec<String>) = args
+            .into_iter()
+            .zip(labels)
+            .filter(|(_, label)| *label != from_name)
+            .unzip();
+        *instr.get_args_mut().unwrap() = args;
+        *instr.get_labels_mut().unwrap() = labels;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::types::{Function, FunctionArg, Instruction, OpCode, Type, Value},
+        opt::GlobalOptimizationPass,
+    };
+
+    use super::ConstantFolding;
+
+    fn constant(dest: &str, instr_type: Type, value: Value) -> Rc<Instruction> {
+        Instruction::new_const(OpCode::Const, dest.to_string(), instr_type, value)
+    }
+
+    fn value(op: OpCode, dest: &str, instr_type: Type, args: &[&str]) -> Rc<Instruction> {
+        Instruction::new_value(
+            op,
+            dest.to_string(),
+            instr_type,
+            args.iter().map(|a| a.to_string()).collect(),
+            vec![],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        // x is 6 on both sides of the branch, y is 1 on one side and the arg on the other
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            constant("a", Type::Int, Value::Int(4)),
+            constant("b", Type::Int, Value::Int(2)),
+            value(OpCode::Add, "c", Type::Int, &["a", "b"]),
+            value(OpCode::LessThan, "d", Type::Bool, &["b", "a"]),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["d".to_string()],
+                vec![],
+                vec!["left".to_string(), "right".to_string()],
+            ),
+            Instruction::new_label("left"),
+            value(OpCode::Id, "x", Type::Int, &["c"]),
+            constant("y", Type::Int, Value::Int(1)),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["join".to_string()]),
+            Instruction::new_label("right"),
+            value(OpCode::Mul, "x", Type::Int, &["b", "b"]),
+            value(OpCode::Add, "x", Type::Int, &["x", "b"]),
+            value(OpCode::Id, "y", Type::Int, &["arg"]),
+            Instruction::new_label("join"),
+            value(OpCode::Sub, "z", Type::Int, &["x", "a"]),
+            value(OpCode::Add, "w", Type::Int, &["y", "a"]),
+            Instruction::new_effect(OpCode::Print, vec!["z".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("arg".to_string(), Type::Int)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        ConstantFolding::new().run(&mut blocks);
+
+        let entry = blocks.get_block_by_id(0).unwrap();
+        assert_eq!(entry.instrs[3], constant("c", Type::Int, Value::Int(6)));
+        assert_eq!(
+            entry.instrs[4],
+            constant("d", Type::Bool, Value::Bool(true))
+        );
+        assert_eq!(
+            entry.instrs[5],
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["left".to_string()])
+        );
+
+        let right = blocks.get_block_by_id(2).unwrap();
+        assert_eq!(right.instrs[2], constant("x", Type::Int, Value::Int(6)));
+
+        let join = blocks.get_block_by_id(3).unwrap();
+        assert_eq!(join.instrs[1], constant("z", Type::Int, Value::Int(2)));
+        assert_eq!(
+            join.instrs[2],
+            value(OpCode::Add, "w", Type::Int, &["y", "a"])
+        );
+    }
+
+    #[test]
+    fn test_fold_branch_updates_phis() {
+        // the branch always goes to body, so end's phi can't come from entry anymore
+        let instrs = vec![
+            Instruction::new_label("entry"),
+            constant("c", Type::Bool, Value::Bool(true)),
+            constant("x.0", Type::Int, Value::Int(1)),
+            Instruction::new_effect(
+                OpCode::Branch,
+                vec!["c".to_string()],
+                vec![],
+                vec!["body".to_string(), "end".to_string()],
+            ),
+            Instruction::new_label("body"),
+            value(OpCode::Id, "x.1", Type::Int, &["arg"]),
+            Instruction::new_effect(OpCode::Jump, vec![], vec![], vec!["end".to_string()]),
+            Instruction::new_label("end"),
+            Instruction::new_value(
+                OpCode::Phi,
+                "x".to_string(),
+                Type::Int,
+                vec!["x.0".to_string(), "x.1".to_string()],
+                vec![],
+                vec!["entry".to_string(), "body".to_string()],
+            ),
+            Instruction::new_effect(OpCode::Print, vec!["x".to_string()], vec![], vec![]),
+        ];
+        let function = Function::new(
+            "main".to_string(),
+            Type::Unit,
+            vec![FunctionArg::new("arg".to_string(), Type::Int)],
+            instrs,
+        );
+        let mut blocks = FunctionBlocksLoader::new(function).load().unwrap();
+        ConstantFolding::new().run(&mut blocks);
+
+        let end = blocks.get_block_by_id(2).unwrap();
+        assert_eq!(
+            end.instrs[1],
+            Instruction::new_value(
+                OpCode::Phi,
+                "x".to_string(),
+                Type::Int,
+                vec!["x.1".to_string()],
+                vec![],
+                vec!["body".to_string()],
+            )
+        );
+    }
+}
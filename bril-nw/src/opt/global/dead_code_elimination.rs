@@ -1,15 +1,42 @@
-use std::{collections::HashSet, mem};
+use std::collections::HashSet;
 
-use crate::{basicblock::FunctionBlocks, opt::GlobalOptimizationPass};
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::OpCode,
+    opt::GlobalOptimizationPass,
+    points_to::PointsToAnalysis,
+    purity::{FunctionPurities, Purity},
+};
 
-pub struct DeadCodeElimination();
+pub struct DeadCodeElimination {
+    // calls to functions without side effects can be deleted too if this is given
+    purity: Option<FunctionPurities>,
+}
+
+impl Default for DeadCodeElimination {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeadCodeElimination {
+    pub fn new() -> Self {
+        DeadCodeElimination { purity: None }
+    }
+
+    pub fn with_purity(mut self, purity: FunctionPurities) -> Self {
+        self.purity = Some(purity);
+        self
+    }
+}
 
 impl GlobalOptimizationPass for DeadCodeElimination {
     fn run(&mut self, function: &mut FunctionBlocks) {
         loop {
             // delete unused vars until convergence
             // this is not the most efficient way to implement this, but it works
-            let any_deleted = delete_unused_vars(function);
+            let any_deleted = delete_unused_vars(function, self.purity.as_ref())
+                | delete_overwritten_stores(function, self.purity.as_ref());
             if !any_deleted {
                 break;
             }
@@ -18,7 +45,7 @@ impl GlobalOptimizationPass for DeadCodeElimination {
 }
 
 // returns true if any instructions were deleted. false otherwise
-fn delete_unused_vars(function: &mut FunctionBlocks) -> bool {
+fn delete_unused_vars(function: &mut FunctionBlocks, purity: Option<&FunctionPurities>) -> bool {
     let mut used_args: HashSet<String> = HashSet::new();
     let mut dests: HashSet<String> = HashSet::new();
 
@@ -38,21 +65,79 @@ fn delete_unused_vars(function: &mut FunctionBlocks) -> bool {
 
     // to find unused vars, we want to find elements in dests not in used_args
     let unused: HashSet<_> = dests.difference(&used_args).collect();
+    let mut any_deleted = false;
     for block in function.get_mut_blocks() {
-        let mut new_instrs = Vec::new();
-        mem::swap(&mut block.instrs, &mut new_instrs);
-        new_instrs = new_instrs
-            .into_iter()
-            .filter(|instr| {
-                instr.get_dest().is_none()
-                    || !unused.contains(&instr.get_dest().unwrap().to_string())
-            })
-            .collect();
+        let num_instrs = block.instrs.len();
+
+        // a call has to stay even if its result is never used, unless the function it calls
+        // doesn't do anything else
+        block.instrs.retain(|instr| {
+            let is_removable =
+                !instr.has_side_effects() || purity.is_some_and(|p| p.is_removable_call(instr));
+
+            instr.get_dest().is_none()
+                || !is_removable
+                || !unused.contains(&instr.get_dest().unwrap().to_string())
+        });
+
+        any_deleted |= block.instrs.len() != num_instrs;
+    }
+
+    any_deleted
+}
+
+/*
+    A store is dead if a later store in the same block writes through the same pointer variable,
+    and nothing in between can read what it stored: no load through a pointer that may alias it,
+    no free of one, and no call that may read memory it can reach. Blocks are walked backwards,
+    keeping the pointers that are stored through further down.
+*/
+// returns true if any stores were deleted. false otherwise
+fn delete_overwritten_stores(
+    function: &mut FunctionBlocks,
+    purity: Option<&FunctionPurities>,
+) -> bool {
+    let points_to = PointsToAnalysis::create(function);
+
+    let mut any_deleted = false;
+    for block in function.get_mut_blocks() {
+        let mut overwritten: Vec<String> = Vec::new();
+        let mut dead = vec![false; block.instrs.len()];
 
-        mem::swap(&mut block.instrs, &mut new_instrs);
+        for (instr_idx, instr) in block.instrs.iter().enumerate().rev() {
+            // stores further down use a different value of the var
+            if let Some(dest) = instr.get_dest() {
+                overwritten.retain(|ptr| ptr != dest);
+            }
+
+            let args = instr.get_args_copy();
+            match instr.get_op_code() {
+                Some(OpCode::Store) => {
+                    if overwritten.contains(&args[0]) {
+                        dead[instr_idx] = true;
+                    } else {
+                        overwritten.push(args[0].clone());
+                    }
+                }
+                Some(OpCode::Load | OpCode::Free) => {
+                    overwritten.retain(|ptr| !points_to.may_alias(ptr, &args[0]));
+                }
+                Some(OpCode::Call)
+                    if purity.is_none_or(|p| p.get_instr_purity(instr) != Purity::Pure) =>
+                {
+                    overwritten.retain(|ptr| !points_to.may_be_accessed_by_calls(ptr));
+                }
+                _ => (),
+            }
+        }
+
+        any_deleted |= dead.contains(&true);
+
+        let mut dead = dead.into_iter();
+        block.instrs.retain(|_| !dead.next().unwrap());
     }
 
-    unused.len() > 0
+    any_deleted
 }
 
 #[cfg(test)]
@@ -60,9 +145,13 @@ mod tests {
     use std::collections::HashMap;
 
     use crate::{
-        basicblock::{BasicBlock, FunctionBlocks},
-        bril::types::{Instruction, OpCode, Type, Value},
+        basicblock::{BasicBlock, FunctionBlocks, FunctionBlocksLoader},
+        bril::{
+            loader::load_bril,
+            types::{Instruction, OpCode, Type, Value},
+        },
         opt::GlobalOptimizationPass,
+        purity::FunctionPurities,
     };
 
     use super::DeadCodeElimination;
@@ -98,7 +187,7 @@ mod tests {
 
         let mut f = FunctionBlocks::new("test", vec![], vec![bb], HashMap::new(), HashMap::new());
 
-        let mut dce = DeadCodeElimination();
+        let mut dce = DeadCodeElimination::new();
         dce.run(&mut f);
 
         let updated_bb = &f.get_blocks()[0];
@@ -108,4 +197,104 @@ mod tests {
         assert_eq!(updated_bb.instrs[1].get_dest(), Some("b"));
         assert_eq!(updated_bb.instrs[2].get_dest(), Some("d"));
     }
+
+    #[test]
+    fn test_unused_calls() {
+        // neither result is used, but only square is pure
+        let program = load_bril(
+            r#"{
+            "functions": [
+                {
+                    "name": "main",
+                    "instrs": [
+                        { "op": "const", "dest": "a", "type": "int", "value": 2 },
+                        { "op": "call", "dest": "b", "type": "int", "args": ["a"], "funcs": ["square"] },
+                        { "op": "call", "dest": "c", "type": "int", "args": ["a"], "funcs": ["show"] }
+                    ]
+                },
+                {
+                    "name": "square",
+                    "args": [{ "name": "x", "type": "int" }],
+                    "type": "int",
+                    "instrs": [
+                        { "op": "mul", "dest": "r", "type": "int", "args": ["x", "x"] },
+                        { "op": "ret", "args": ["r"] }
+                    ]
+                },
+                {
+                    "name": "show",
+                    "args": [{ "name": "x", "type": "int" }],
+                    "type": "int",
+                    "instrs": [
+                        { "op": "print", "args": ["x"] },
+                        { "op": "ret", "args": ["x"] }
+                    ]
+                }
+            ]
+        }"#,
+        )
+        .unwrap();
+        let purities = FunctionPurities::create_from_program(&program);
+        let load_main = || {
+            FunctionBlocksLoader::new(program.functions[0].clone())
+                .load()
+                .unwrap()
+        };
+
+        let mut f = load_main();
+        DeadCodeElimination::new().run(&mut f);
+        assert_eq!(f.get_blocks()[0].instrs.len(), 3);
+
+        let mut f = load_main();
+        DeadCodeElimination::new().with_purity(purities).run(&mut f);
+        let dests: Vec<_> = f.get_blocks()[0]
+            .instrs
+            .iter()
+            .map(|i| i.get_dest().unwrap().to_string())
+            .collect();
+        assert_eq!(dests, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_overwritten_stores() {
+        // a and b are separate allocations, so loading from b doesn't read the first store to a.
+        // the first store to b is read by the load after it
+        let program = load_bril(
+            r#"{
+            "functions": [
+                {
+                    "name": "main",
+                    "instrs": [
+                        { "op": "const", "dest": "n", "type": "int", "value": 1 },
+                        { "op": "alloc", "dest": "a", "type": { "ptr": "int" }, "args": ["n"] },
+                        { "op": "alloc", "dest": "b", "type": { "ptr": "int" }, "args": ["n"] },
+                        { "op": "store", "args": ["a", "n"] },
+                        { "op": "store", "args": ["b", "n"] },
+                        { "op": "load", "dest": "x", "type": "int", "args": ["b"] },
+                        { "op": "store", "args": ["a", "x"] },
+                        { "op": "store", "args": ["b", "x"] },
+                        { "op": "load", "dest": "y", "type": "int", "args": ["a"] },
+                        { "op": "print", "args": ["y"] },
+                        { "op": "free", "args": ["a"] },
+                        { "op": "free", "args": ["b"] }
+                    ]
+                }
+            ]
+        }"#,
+        )
+        .unwrap();
+
+        let mut f = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+        DeadCodeElimination::new().run(&mut f);
+
+        let stores: Vec<_> = f.get_blocks()[0]
+            .instrs
+            .iter()
+            .filter(|i| i.get_op_code() == Some(OpCode::Store))
+            .map(|i| i.get_args_copy())
+            .collect();
+        assert_eq!(stores, vec![vec!["b", "n"], vec!["a", "x"], vec!["b", "x"]]);
+    }
 }
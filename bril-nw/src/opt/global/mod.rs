@@ -1,3 +1,11 @@
+mod block_layout;
+mod code_hoisting;
+mod common_subexpression_elimination;
+mod constant_folding;
 mod dead_code_elimination;
 
+pub use block_layout::BlockLayout;
+pub use code_hoisting::CodeHoisting;
+pub use common_subexpression_elimination::GlobalCommonSubexpressionElimination;
+pub use constant_folding::ConstantFolding;
 pub use dead_code_elimination::DeadCodeElimination;
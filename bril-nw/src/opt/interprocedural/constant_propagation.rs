@@ -0,0 +1,430 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    basicblock::{FunctionBlocks, FunctionBlocksLoader},
+    bril::types::{Function, Instruction, OpCode, Program},
+    callgraph::{CallGraph, MAIN_FUNCTION_NAME},
+    cfg::{
+        dataflow::constant_propagation::{get_constant_value, ConstantPropagation, ConstantValue},
+        ControlFlowGraph,
+    },
+    opt::ProgramOptimizationPass,
+};
+
+/*
+    Constant propagation across calls:
+    - an arg that is the same constant at every call site of a function becomes a const at the
+      start of the function, and the parameter is removed from the function and its call sites
+    - a call to a function that always returns the same constant keeps the call (for its side
+      effects), but its dest is assigned the constant instead
+
+    Each function's args and return value are found with the intraprocedural constant lattice,
+    optimistically: nothing is known about a function before its callers (or for the return value,
+    its rets) have been analyzed, and facts only move down the lattice as more call sites are
+    seen. Functions are analyzed in bottom-up call graph order until nothing changes, which
+    settles recursive functions too.
+
+    @main and functions only called by themselves (or their own recursive group), if at all, can
+    be called from the outside, so their parameters are left alone. Folding the new constants
+    into the rest of each function is left to ConstantFolding.
+*/
+pub struct InterproceduralConstantPropagation();
+
+// what's known about a function at its call sites
+#[derive(Clone, Debug, PartialEq)]
+struct FunctionSummary {
+    // the value of each parameter, merged over every call site
+    args: Vec<ConstantValue>,
+    // the value of every ret, merged
+    return_value: ConstantValue,
+}
+
+impl Default for InterproceduralConstantPropagation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterproceduralConstantPropagation {
+    pub fn new() -> Self {
+        InterproceduralConstantPropagation()
+    }
+
+    // function name -> summary
+    fn find_summaries(
+        program: &Program,
+        functions: &mut HashMap<String, FunctionBlocks>,
+    ) -> HashMap<String, FunctionSummary> {
+        let call_graph = CallGraph::create_from_program(program);
+
+        let mut summaries: HashMap<String, FunctionSummary> = HashMap::new();
+        for function in &program.functions {
+            // calls from within a recursive group only pass along what came in from outside it
+            let has_callers = !call_graph.get_outside_callers(&function.name).is_empty();
+            let initial = if function.name != MAIN_FUNCTION_NAME && has_callers {
+                ConstantValue::Undefined
+            } else {
+                ConstantValue::NotConstant
+            };
+
+            summaries.insert(
+                function.name.clone(),
+                FunctionSummary {
+                    args: vec![initial; function.args.len()],
+                    return_value: ConstantValue::Undefined,
+                },
+            );
+        }
+
+        let order: Vec<String> = call_graph
+            .get_bottom_up_order()
+            .into_iter()
+            .map(|name| name.to_string())
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for name in &order {
+                let function = functions.get_mut(name).unwrap();
+                let new_summaries = Self::analyze_function(function, &summaries);
+
+                for (name, new_summary) in new_summaries {
+                    let summary = summaries.get_mut(&name).unwrap();
+                    let merged = merge_summaries(summary, &new_summary);
+                    if *summary != merged {
+                        *summary = merged;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        summaries
+    }
+
+    // what the function tells about its callees' args (one summary per call site) and about its
+    // own return value
+    fn analyze_function(
+        function: &mut FunctionBlocks,
+        summaries: &HashMap<String, FunctionSummary>,
+    ) -> Vec<(String, FunctionSummary)> {
+        let function_name = function.get_name().clone();
+        let cfg = ControlFlowGraph::create_from_basic_blocks(function);
+        let analysis = create_analysis(cfg.get_function(), summaries);
+        let results = analysis.analyze(&cfg);
+
+        let mut result = Vec::new();
+        let mut return_value = ConstantValue::Undefined;
+        for block in cfg.get_function().get_blocks() {
+            let facts = results
+                .get_instr_facts(&analysis, &cfg, block.get_id())
+                .unwrap();
+
+            for (instr_idx, instr) in block.instrs.iter().enumerate() {
+                let args = instr.get_args_copy();
+                let arg_values: Vec<ConstantValue> = args
+                    .iter()
+                    .map(|arg| get_constant_value(&facts[instr_idx], arg))
+                    .collect();
+
+                if instr.is_ret() {
+                    if let Some(value) = arg_values.first() {
+                        return_value = return_value.merge(*value);
+                    }
+                } else if let Some(callee) = get_callee(instr) {
+                    let Some(summary) = summaries.get(&callee) else {
+                        continue;
+                    };
+
+                    // a call with the wrong number of args tells nothing about the parameters
+                    let args = if arg_values.len() == summary.args.len() {
+                        arg_values
+                    } else {
+                        vec![ConstantValue::NotConstant; summary.args.len()]
+                    };
+
+                    result.push((
+                        callee,
+                        FunctionSummary {
+                            args,
+                            return_value: ConstantValue::Undefined,
+                        },
+                    ));
+                }
+            }
+        }
+
+        result.push((
+            function_name,
+            FunctionSummary {
+                args: vec![ConstantValue::Undefined; function.get_args().len()],
+                return_value,
+            },
+        ));
+        result
+    }
+
+    fn rewrite_function(
+        function: &FunctionBlocks,
+        summaries: &HashMap<String, FunctionSummary>,
+    ) -> Rc<Function> {
+        let summary = &summaries[function.get_name()];
+
+        let mut args = Vec::new();
+        let mut instrs = Vec::new();
+        for (arg, value) in function.get_args().iter().zip(summary.args.iter()) {
+            match value {
+                ConstantValue::Constant(value) => instrs.push(Instruction::new_const(
+                    OpCode::Const,
+                    arg.name.clone(),
+                    arg.arg_type.clone(),
+                    *value,
+                )),
+                _ => args.push(arg.clone()),
+            }
+        }
+
+        for instr in function.get_blocks().iter().flat_map(|b| b.instrs.iter()) {
+            let Some(callee) = get_callee(instr).and_then(|callee| summaries.get(&callee)) else {
+                instrs.push(instr.clone());
+                continue;
+            };
+
+            let mut call_args = instr.get_args_copy();
+            if call_args.len() == callee.args.len() {
+                call_args = call_args
+                    .into_iter()
+                    .zip(callee.args.iter())
+                    .filter(|(_, value)| value.get_constant().is_none())
+                    .map(|(arg, _)| arg)
+                    .collect();
+            }
+            let funcs = instr.get_funcs_copy().unwrap();
+
+            match (instr.get_dest(), callee.return_value.get_constant()) {
+                (Some(dest), Some(value)) => {
+                    instrs.push(Instruction::new_effect(
+                        OpCode::Call,
+                        call_args,
+                        funcs,
+                        vec![],
+                    ));
+                    instrs.push(Instruction::new_const(
+                        OpCode::Const,
+                        dest.to_string(),
+                        instr.get_type().unwrap(),
+                        value,
+                    ));
+                }
+                (Some(dest), None) => instrs.push(Instruction::new_value(
+                    OpCode::Call,
+                    dest.to_string(),
+                    instr.get_type().unwrap(),
+                    call_args,
+                    funcs,
+                    vec![],
+                )),
+                (None, _) => instrs.push(Instruction::new_effect(
+                    OpCode::Call,
+                    call_args,
+                    funcs,
+                    vec![],
+                )),
+            }
+        }
+
+        Function::new(
+            function.get_name().clone(),
+            function.get_return_type(),
+            args,
+            instrs,
+        )
+    }
+}
+
+impl ProgramOptimizationPass for InterproceduralConstantPropagation {
+    fn run(&mut self, program: &mut Program) {
+        let mut functions = HashMap::new();
+        for function in &program.functions {
+            let Ok(blocks) = FunctionBlocksLoader::new(function.clone()).load() else {
+                return;
+            };
+
+            functions.insert(function.name.clone(), blocks);
+        }
+
+        let summaries = Self::find_summaries(program, &mut functions);
+        for function in program.functions.iter_mut() {
+            *function = Self::rewrite_function(&functions[&function.name], &summaries);
+        }
+    }
+}
+
+// the intraprocedural analysis for one function, with what's known so far about its args and the
+// functions it calls
+fn create_analysis(
+    function: &FunctionBlocks,
+    summaries: &HashMap<String, FunctionSummary>,
+) -> ConstantPropagation {
+    let summary = &summaries[function.get_name()];
+    let arg_values = function
+        .get_args()
+        .iter()
+        .zip(summary.args.iter())
+        .map(|(arg, value)| (arg.name.clone(), *value))
+        .collect();
+    let return_values = summaries
+        .iter()
+        .map(|(name, summary)| (name.clone(), summary.return_value))
+        .collect();
+
+    ConstantPropagation::with_call_values(arg_values, return_values)
+}
+
+fn get_callee(instr: &Instruction) -> Option<String> {
+    if !instr.is_call() {
+        return None;
+    }
+
+    instr.get_funcs_copy()?.into_iter().next()
+}
+
+fn merge_summaries(summary: &FunctionSummary, other: &FunctionSummary) -> FunctionSummary {
+    FunctionSummary {
+        args: summary
+            .args
+            .iter()
+            .zip(other.args.iter())
+            .map(|(a, b)| a.merge(*b))
+            .collect(),
+        return_value: summary.return_value.merge(other.return_value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basicblock::FunctionBlocksLoader,
+        bril::{loader::load_bril, types::Program},
+        opt::ProgramOptimizationPass,
+    };
+
+    use super::InterproceduralConstantPropagation;
+
+    // main calls scale twice with n = 3, and once with two different values of x.
+    // scale returns x * n, which is only a constant for the calls from inc
+    const PROGRAM: &str = r#"{
+        "functions": [
+            {
+                "name": "main",
+                "instrs": [
+                    { "op": "const", "dest": "a", "type": "int", "value": 1 },
+                    { "op": "const", "dest": "b", "type": "int", "value": 2 },
+                    { "op": "const", "dest": "n", "type": "int", "value": 3 },
+                    {
+                        "op": "call", "dest": "x", "type": "int",
+                        "args": ["a", "n"], "funcs": ["scale"]
+                    },
+                    {
+                        "op": "call", "dest": "y", "type": "int",
+                        "args": ["b", "n"], "funcs": ["scale"]
+                    },
+                    { "op": "call", "dest": "z", "type": "int", "args": ["b"], "funcs": ["inc"] },
+                    { "op": "print", "args": ["x", "y", "z"] }
+                ]
+            },
+            {
+                "name": "scale",
+                "args": [{ "name": "x", "type": "int" }, { "name": "n", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "mul", "dest": "r", "type": "int", "args": ["x", "n"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            },
+            {
+                "name": "inc",
+                "args": [{ "name": "x", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "const", "dest": "one", "type": "int", "value": 1 },
+                    { "op": "add", "dest": "r", "type": "int", "args": ["x", "one"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            }
+        ]
+    }"#;
+
+    fn to_string(program: &Program) -> String {
+        program
+            .functions
+            .iter()
+            .map(|f| {
+                FunctionBlocksLoader::new(f.clone())
+                    .load()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_interprocedural_constant_propagation() {
+        let mut program = load_bril(PROGRAM).unwrap();
+        InterproceduralConstantPropagation::new().run(&mut program);
+
+        let expected = [
+            "@main() {",
+            "    a: int = const 1",
+            "    b: int = const 2",
+            "    n: int = const 3",
+            "    x: int = call a  scale",
+            "    y: int = call b  scale",
+            "    call   inc",
+            "    z: int = const 3",
+            "    print x y z  ",
+            "}",
+            "@scale(x: int): int {",
+            "    n: int = const 3",
+            "    r: int = mul x n  ",
+            "    ret r  ",
+            "}",
+            "@inc(): int {",
+            "    x: int = const 2",
+            "    one: int = const 1",
+            "    r: int = add x one  ",
+            "    ret r  ",
+            "}",
+        ];
+        assert_eq!(to_string(&program), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn test_only_called_by_itself() {
+        // count always calls itself with 1, but nothing else calls it, so n could be anything
+        let text = r#"{
+            "functions": [
+                {
+                    "name": "main",
+                    "instrs": [{ "op": "print", "args": [] }]
+                },
+                {
+                    "name": "count",
+                    "args": [{ "name": "n", "type": "int" }],
+                    "instrs": [
+                        { "op": "print", "args": ["n"] },
+                        { "op": "const", "dest": "one", "type": "int", "value": 1 },
+                        { "op": "call", "args": ["one"], "funcs": ["count"] }
+                    ]
+                }
+            ]
+        }"#;
+        let mut program = load_bril(text).unwrap();
+        let before = to_string(&program);
+        InterproceduralConstantPropagation::new().run(&mut program);
+
+        assert_eq!(to_string(&program), before);
+    }
+}
@@ -0,0 +1,3 @@
+mod constant_propagation;
+
+pub use constant_propagation::InterproceduralConstantPropagation;
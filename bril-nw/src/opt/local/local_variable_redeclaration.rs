@@ -55,7 +55,11 @@ fn delete_unused_assignments(block: &mut BasicBlock) -> bool {
         // check for assigns
         let maybe_dest = instr.get_dest();
         if let Some(dest) = maybe_dest {
-            if last_def.contains_key(dest) {
+            // calls stay even when their result is overwritten
+            if last_def
+                .get(dest)
+                .is_some_and(|def| !def.has_side_effects())
+            {
                 // actually stage the instruction for deletion
                 instrs_to_delete.insert(Rc::as_ptr(last_def.get(dest).unwrap()));
             }
@@ -77,5 +81,5 @@ fn delete_unused_assignments(block: &mut BasicBlock) -> bool {
 
     mem::swap(&mut filtered_instrs, &mut block.instrs);
 
-    return true;
+    true
 }
@@ -1,33 +1,124 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::HashMap, fmt, rc::Rc};
 
 use crate::{
     basicblock::BasicBlock,
     bril::types::{Instruction, OpCode},
     opt::LocalOptimizationPass,
+    points_to::PointsToAnalysis,
+    purity::{FunctionPurities, Purity},
 };
 
 #[derive(Debug)]
 pub enum LVNError {
-    UndeclaredArgName,
+    // an arg with no value number in the block. it's either defined in an earlier block, or not
+    // defined at all, which the definite assignment warnings already point out
+    ArgFromOutsideBlock,
 }
 
 pub struct LocalValueNumbering {
     env: HashMap<String, usize>,
     table: HashMap<LVNCanonicalExpression, usize>,
     names: HashMap<usize, String>,
+    // calls to pure functions are numbered like any other value if this is given
+    purity: Option<FunctionPurities>,
+    // without this, every store, free or call is assumed to change what every load reads
+    points_to: Option<PointsToAnalysis>,
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-struct LVNCanonicalExpression {
+/*
+    An op applied to its operands, written the same way no matter how the instruction wrote it:
+    the operands of commutative ops are sorted, so `add a b` and `add b a` are the same expression.
+    A call's op includes the function it calls.
+
+    LVN uses value numbers as operands. Analyses over the whole cfg use variable names, since value
+    numbers only mean something within one block.
+*/
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct LVNCanonicalExpression<T = usize> {
     op: String,
-    args: Vec<usize>,
+    args: Vec<T>,
+}
+
+impl<T: Ord> LVNCanonicalExpression<T> {
+    pub fn new(op: OpCode, mut args: Vec<T>) -> Self {
+        if op.is_commutative() {
+            args.sort();
+        }
+
+        LVNCanonicalExpression {
+            op: op.to_string(),
+            args,
+        }
+    }
+
+    pub fn new_call(function_name: &str, args: Vec<T>) -> Self {
+        LVNCanonicalExpression {
+            op: format!("{} @{}", OpCode::Call, function_name),
+            args,
+        }
+    }
+
+    pub fn get_args(&self) -> &Vec<T> {
+        &self.args
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for LVNCanonicalExpression<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.op)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl LVNCanonicalExpression<String> {
+    // None for instrs that aren't a pure computation of their dest from their args
+    pub fn from_instr(instr: &Instruction) -> Option<Self> {
+        Self::from_instr_with_purity(instr, None)
+    }
+
+    // same as from_instr, but calls to functions purity knows to be pure are expressions too
+    pub fn from_instr_with_purity(
+        instr: &Instruction,
+        purity: Option<&FunctionPurities>,
+    ) -> Option<Self> {
+        if purity.is_some_and(|p| p.is_mergeable_call(instr)) {
+            let funcs = instr.get_funcs_copy()?;
+            return Some(Self::new_call(funcs.first()?, instr.get_args_copy()));
+        }
+
+        if !instr.is_value() || instr.has_side_effects() {
+            return None;
+        }
+
+        // a load depends on what's in memory, not just on its pointer
+        let op = instr.get_op_code().unwrap();
+        if op == OpCode::Id || op == OpCode::Phi || op == OpCode::Load {
+            return None;
+        }
+
+        Some(Self::new(op, instr.get_args_copy()))
+    }
 }
 
 impl LocalOptimizationPass for LocalValueNumbering {
     fn run(&mut self, block: &mut BasicBlock) {
         for instr in &mut block.instrs {
+            self.forget_clobbered_loads(instr);
+
             let canon_instr = self.canonicalize_instruction(instr);
-            if let None = canon_instr {
+            if canon_instr.is_none() {
+                // whatever value the dest held before is gone. the new one (e.g. from an alloc)
+                // isn't the same as any other, but later instrs can still be numbered with it
+                if let Some(dest) = instr.get_dest() {
+                    let ordinal = self.get_current_ordinal();
+                    self.env.insert(dest.to_string(), ordinal);
+                    self.names.insert(ordinal, dest.to_string());
+                }
+
                 continue;
             }
 
@@ -40,17 +131,67 @@ impl LocalOptimizationPass for LocalValueNumbering {
     }
 }
 
+impl Default for LocalValueNumbering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LocalValueNumbering {
     pub fn new() -> Self {
         LocalValueNumbering {
             env: HashMap::new(),
             table: HashMap::new(),
             names: HashMap::new(),
+            purity: None,
+            points_to: None,
         }
     }
 
+    pub fn with_purity(mut self, purity: FunctionPurities) -> Self {
+        self.purity = Some(purity);
+        self
+    }
+
+    // points_to has to be for the function the blocks are in
+    pub fn with_points_to(mut self, points_to: PointsToAnalysis) -> Self {
+        self.points_to = Some(points_to);
+        self
+    }
+
+    // loads are dropped from the table, but their value numbers stay taken
     fn get_current_ordinal(&self) -> usize {
-        self.table.len()
+        self.names.len()
+    }
+
+    // drops the loads whose result a store, free or call might change
+    fn forget_clobbered_loads(&mut self, instr: &Instruction) {
+        let args = instr.get_args_copy();
+        let points_to = self.points_to.as_ref();
+        let is_clobbered: Box<dyn Fn(&str) -> bool> = match instr.get_op_code() {
+            Some(OpCode::Store | OpCode::Free) => {
+                Box::new(move |ptr| points_to.is_none_or(|a| a.may_alias(ptr, &args[0])))
+            }
+            // calls that don't write memory leave every load alone
+            Some(OpCode::Call)
+                if self
+                    .purity
+                    .as_ref()
+                    .is_none_or(|p| p.get_instr_purity(instr) == Purity::Effectful) =>
+            {
+                Box::new(move |ptr| points_to.is_none_or(|a| a.may_be_accessed_by_calls(ptr)))
+            }
+            _ => return,
+        };
+
+        let load = OpCode::Load.to_string();
+        let names = &self.names;
+        self.table.retain(|expr, _| {
+            expr.op != load
+                || names
+                    .get(&expr.args[0])
+                    .is_some_and(|ptr| !is_clobbered(ptr))
+        });
     }
 
     fn register_canonicalized_instr(
@@ -87,21 +228,32 @@ impl LocalValueNumbering {
             return None;
         }
 
+        let is_mergeable_call = self
+            .purity
+            .as_ref()
+            .is_some_and(|p| p.is_mergeable_call(instr));
+        if instr.has_side_effects() && !is_mergeable_call {
+            // e.g. two calls with the same args don't necessarily return the same thing, unless
+            // the function is pure
+            return None;
+        }
+
         if instr.is_const() {
             // if const, add to the table if it doesn't already exist.
             let canon_instr = canonicalize_const_instr(instr);
             return Some(canon_instr);
         } else if instr.is_value() {
             let canon_instr = canonicalize_value_instr(&self.env, instr);
-            if let Err(_) = canon_instr {
-                // failed to canonicalize an instr. bail
+            if canon_instr.is_err() {
+                // uses a value from outside the block, so the instr starts a new value. this is
+                // expected for any var that lives across blocks, so it's not worth a warning
                 return None;
             }
 
             return Some(canon_instr.unwrap());
         }
 
-        return None;
+        None
     }
 
     fn reconstruct_instruction(
@@ -118,7 +270,7 @@ impl LocalValueNumbering {
         if !is_new_entry {
             // rewrite instruction to an id
             let existing_canonical_name = self.names.get(&ordinal);
-            if let None = existing_canonical_name {
+            if existing_canonical_name.is_none() {
                 // TODO: bad
                 return;
             }
@@ -139,12 +291,10 @@ impl LocalValueNumbering {
             let updated_args: Vec<String> = canon_instr
                 .args
                 .iter()
-                .map(|arg_ordinal| {
-                    let existing_canonical_name = self.names.get(&arg_ordinal);
-                    existing_canonical_name.map(|s| s.clone())
+                .filter_map(|arg_ordinal| {
+                    let existing_canonical_name = self.names.get(arg_ordinal);
+                    existing_canonical_name.cloned()
                 })
-                .filter(|o| o.is_some())
-                .map(|o| o.unwrap())
                 .collect();
 
             if updated_args.len() != canon_instr.args.len() {
@@ -181,26 +331,35 @@ fn canonicalize_value_instr(
     let mut arg_ordinals: Vec<usize> = Vec::with_capacity(instr.get_args().unwrap().len());
     for arg in instr.get_args().unwrap() {
         if !env.contains_key(arg) {
-            // TODO: bad
-            return Err(LVNError::UndeclaredArgName);
+            return Err(LVNError::ArgFromOutsideBlock);
         }
 
         let ordinal = env.get(arg).unwrap();
         arg_ordinals.push(*ordinal);
     }
 
-    Ok(LVNCanonicalExpression {
-        op: instr.get_op_code().unwrap().to_string(),
-        args: arg_ordinals,
-    })
+    if instr.is_call() {
+        let funcs = instr.get_funcs_copy().unwrap();
+        return Ok(LVNCanonicalExpression::new_call(&funcs[0], arg_ordinals));
+    }
+
+    Ok(LVNCanonicalExpression::new(
+        instr.get_op_code().unwrap(),
+        arg_ordinals,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        basicblock::BasicBlock,
-        bril::types::{Instruction, OpCode, Type, Value},
+        basicblock::{BasicBlock, FunctionBlocksLoader},
+        bril::{
+            loader::load_bril,
+            types::{Instruction, OpCode, Type, Value},
+        },
         opt::LocalOptimizationPass,
+        points_to::PointsToAnalysis,
+        purity::FunctionPurities,
     };
 
     use super::LocalValueNumbering;
@@ -251,4 +410,97 @@ mod tests {
             vec!["sum1".to_string(), "sum1".to_string()]
         );
     }
+
+    #[test]
+    fn test_pure_calls() {
+        // square is pure, so the second call gives the same result as the first
+        let program = load_bril(
+            r#"{
+            "functions": [
+                {
+                    "name": "main",
+                    "instrs": [
+                        { "op": "const", "dest": "a", "type": "int", "value": 2 },
+                        { "op": "call", "dest": "b", "type": "int", "args": ["a"], "funcs": ["square"] },
+                        { "op": "call", "dest": "c", "type": "int", "args": ["a"], "funcs": ["square"] },
+                        { "op": "print", "args": ["b", "c"] }
+                    ]
+                },
+                {
+                    "name": "square",
+                    "args": [{ "name": "x", "type": "int" }],
+                    "type": "int",
+                    "instrs": [
+                        { "op": "mul", "dest": "r", "type": "int", "args": ["x", "x"] },
+                        { "op": "ret", "args": ["r"] }
+                    ]
+                }
+            ]
+        }"#,
+        )
+        .unwrap();
+        let purities = FunctionPurities::create_from_program(&program);
+        let load_main = || {
+            let blocks = FunctionBlocksLoader::new(program.functions[0].clone()).load();
+            blocks.unwrap().get_blocks()[0].clone()
+        };
+
+        let mut bb = load_main();
+        LocalValueNumbering::new().run(&mut bb);
+        assert!(bb.instrs[2].is_call());
+
+        let mut bb = load_main();
+        LocalValueNumbering::new()
+            .with_purity(purities)
+            .run(&mut bb);
+        assert_eq!(bb.instrs[2].get_op_code(), Some(OpCode::Id));
+        assert_eq!(bb.instrs[2].get_args_copy(), vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn test_loads() {
+        // the store to b can't change what a holds, but the second store to a can
+        let program = load_bril(
+            r#"{
+            "functions": [
+                {
+                    "name": "main",
+                    "instrs": [
+                        { "op": "const", "dest": "n", "type": "int", "value": 1 },
+                        { "op": "alloc", "dest": "a", "type": { "ptr": "int" }, "args": ["n"] },
+                        { "op": "alloc", "dest": "b", "type": { "ptr": "int" }, "args": ["n"] },
+                        { "op": "store", "args": ["a", "n"] },
+                        { "op": "load", "dest": "x", "type": "int", "args": ["a"] },
+                        { "op": "store", "args": ["b", "n"] },
+                        { "op": "load", "dest": "y", "type": "int", "args": ["a"] },
+                        { "op": "store", "args": ["a", "y"] },
+                        { "op": "load", "dest": "z", "type": "int", "args": ["a"] },
+                        { "op": "print", "args": ["x", "y", "z"] },
+                        { "op": "free", "args": ["a"] },
+                        { "op": "free", "args": ["b"] }
+                    ]
+                }
+            ]
+        }"#,
+        )
+        .unwrap();
+        let blocks = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+        let ops = |bb: &BasicBlock| -> Vec<OpCode> {
+            bb.instrs.iter().filter_map(|i| i.get_op_code()).collect()
+        };
+
+        let mut bb = blocks.get_blocks()[0].clone();
+        LocalValueNumbering::new().run(&mut bb);
+        assert_eq!(ops(&bb)[6], OpCode::Load);
+
+        let mut bb = blocks.get_blocks()[0].clone();
+        LocalValueNumbering::new()
+            .with_points_to(PointsToAnalysis::create(&blocks))
+            .run(&mut bb);
+        assert_eq!(ops(&bb)[6], OpCode::Id);
+        assert_eq!(bb.instrs[6].get_args_copy(), vec!["x".to_string()]);
+        assert_eq!(ops(&bb)[8], OpCode::Load);
+    }
 }
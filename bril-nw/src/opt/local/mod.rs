@@ -2,4 +2,4 @@ mod local_variable_redeclaration;
 mod lvn;
 
 pub use local_variable_redeclaration::LocalVariableRedeclaration;
-pub use lvn::LocalValueNumbering;
+pub use lvn::{LVNCanonicalExpression, LocalValueNumbering};
@@ -1,6 +1,10 @@
-use crate::basicblock::{BasicBlock, FunctionBlocks};
+use crate::{
+    basicblock::{BasicBlock, FunctionBlocks},
+    bril::types::Program,
+};
 
 pub mod global;
+pub mod interprocedural;
 pub mod local;
 
 pub trait GlobalOptimizationPass {
@@ -10,3 +14,7 @@ pub trait GlobalOptimizationPass {
 pub trait LocalOptimizationPass {
     fn run(&mut self, block: &mut BasicBlock);
 }
+
+pub trait ProgramOptimizationPass {
+    fn run(&mut self, program: &mut Program);
+}
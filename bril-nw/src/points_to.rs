@@ -0,0 +1,460 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    basicblock::FunctionBlocks,
+    bril::types::{Instruction, OpCode, Type},
+    cfg::dataflow::reaching_definitions::InstrLocation,
+};
+
+// what a pointer can point into
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum MemoryObject {
+    // everything allocated by one alloc instruction, at any offset
+    Allocation(InstrLocation),
+    // memory the function didn't allocate itself: whatever its args and the results of calls
+    // point to, and the allocations it shares with other functions
+    External,
+}
+
+// the inclusion constraints of one function
+#[derive(Debug)]
+enum Constraint {
+    // dest may point to object
+    Address { dest: String, object: MemoryObject },
+    // dest may point to whatever src points to
+    Copy { dest: String, src: String },
+    // dest may point to whatever is stored in the objects ptr points to
+    Load { dest: String, ptr: String },
+    // the objects ptr points to may hold whatever value points to
+    Store { ptr: String, value: String },
+    // other functions can reach whatever value points to
+    Escape { value: String },
+}
+
+/*
+    Andersen-style points-to analysis: every instruction that moves a pointer becomes an inclusion
+    constraint between points-to sets (e.g. `q: ptr<int> = id p` means pts(q) contains pts(p)),
+    and the constraints are solved together until nothing changes. Like the analysis itself, the
+    result is flow-insensitive: it holds for every variable at every point in the function.
+
+    Each alloc instruction is one abstract object, no matter how many times it runs. ptradd is
+    treated conservatively: the result points into the same objects as the pointer it offsets, so
+    two pointers into different parts of one allocation may still alias.
+
+    Everything outside the function is a single External object. Allocations escape into it when
+    they are passed to a call, returned, or stored into memory that has escaped, after which any
+    function may read and write them.
+*/
+#[derive(Debug, Default)]
+pub struct PointsToAnalysis {
+    // var -> objects it may point to
+    points_to: HashMap<String, BTreeSet<MemoryObject>>,
+    // object -> objects the pointers stored in it may point to
+    contents: HashMap<MemoryObject, BTreeSet<MemoryObject>>,
+    // objects other functions can reach, always including External
+    escaped: BTreeSet<MemoryObject>,
+}
+
+impl PointsToAnalysis {
+    pub fn create(function: &FunctionBlocks) -> Self {
+        let constraints = create_constraints(function);
+
+        let mut result = PointsToAnalysis {
+            escaped: BTreeSet::from([MemoryObject::External]),
+            ..Default::default()
+        };
+        while result.apply_constraints(&constraints) | result.propagate_escapes(&constraints) {}
+
+        result
+    }
+
+    // empty for vars that aren't pointers
+    pub fn get_points_to(&self, var: &str) -> BTreeSet<MemoryObject> {
+        self.points_to.get(var).cloned().unwrap_or_default()
+    }
+
+    // whether the two pointers may point into the same object
+    pub fn may_alias(&self, a: &str, b: &str) -> bool {
+        let (Some(a), Some(b)) = (self.points_to.get(a), self.points_to.get(b)) else {
+            return false;
+        };
+
+        // External stands for every escaped allocation as well
+        let is_external =
+            |objects: &BTreeSet<MemoryObject>| objects.contains(&MemoryObject::External);
+        let has_escaped = |objects: &BTreeSet<MemoryObject>| {
+            objects.iter().any(|object| self.escaped.contains(object))
+        };
+
+        !a.is_disjoint(b)
+            || (is_external(a) && has_escaped(b))
+            || (is_external(b) && has_escaped(a))
+    }
+
+    // whether a call to some other function may read or write what the pointer points to
+    pub fn may_be_accessed_by_calls(&self, var: &str) -> bool {
+        self.points_to
+            .get(var)
+            .is_some_and(|objects| objects.iter().any(|object| self.escaped.contains(object)))
+    }
+
+    // returns true if any points-to set grew
+    fn apply_constraints(&mut self, constraints: &[Constraint]) -> bool {
+        let mut changed = false;
+        for constraint in constraints {
+            match constraint {
+                Constraint::Address { dest, object } => {
+                    changed |= self
+                        .points_to
+                        .entry(dest.clone())
+                        .or_default()
+                        .insert(*object);
+                }
+                Constraint::Copy { dest, src } => {
+                    let objects = self.get_points_to(src);
+                    changed |= add_all(self.points_to.entry(dest.clone()).or_default(), objects);
+                }
+                Constraint::Load { dest, ptr } => {
+                    let objects: BTreeSet<MemoryObject> = self
+                        .get_points_to(ptr)
+                        .iter()
+                        .filter_map(|object| self.contents.get(object))
+                        .flatten()
+                        .copied()
+                        .collect();
+                    changed |= add_all(self.points_to.entry(dest.clone()).or_default(), objects);
+                }
+                Constraint::Store { ptr, value } => {
+                    let objects = self.get_points_to(value);
+                    for object in self.get_points_to(ptr) {
+                        let contents = self.contents.entry(object).or_default();
+                        changed |= add_all(contents, objects.clone());
+                    }
+                }
+                Constraint::Escape { .. } => (),
+            }
+        }
+
+        changed
+    }
+
+    // returns true if any more objects escaped, or anything was added to an escaped object
+    fn propagate_escapes(&mut self, constraints: &[Constraint]) -> bool {
+        let mut changed = false;
+        for constraint in constraints {
+            if let Constraint::Escape { value } = constraint {
+                let objects = self.get_points_to(value);
+                changed |= add_all(&mut self.escaped, objects);
+            }
+        }
+
+        // whatever an escaped object holds can be reached from outside too
+        loop {
+            let reachable: BTreeSet<MemoryObject> = self
+                .escaped
+                .iter()
+                .filter_map(|object| self.contents.get(object))
+                .flatten()
+                .copied()
+                .collect();
+            if !add_all(&mut self.escaped, reachable) {
+                break;
+            }
+
+            changed = true;
+        }
+
+        // other functions can store any escaped pointer into any escaped object
+        for object in self.escaped.clone() {
+            let contents = self.contents.entry(object).or_default();
+            changed |= add_all(contents, self.escaped.clone());
+        }
+
+        changed
+    }
+}
+
+fn create_constraints(function: &FunctionBlocks) -> Vec<Constraint> {
+    let mut constraints = Vec::new();
+    for arg in function.get_args() {
+        if is_pointer(&arg.arg_type) {
+            constraints.push(Constraint::Address {
+                dest: arg.name.clone(),
+                object: MemoryObject::External,
+            });
+        }
+    }
+
+    for block in function.get_blocks() {
+        for (instr_idx, instr) in block.instrs.iter().enumerate() {
+            let location = InstrLocation {
+                block_id: block.get_id(),
+                instr_idx,
+            };
+
+            constraints.extend(create_instr_constraints(location, instr));
+        }
+    }
+
+    constraints
+}
+
+fn create_instr_constraints(location: InstrLocation, instr: &Instruction) -> Vec<Constraint> {
+    let Some(op) = instr.get_op_code() else {
+        return vec![];
+    };
+    let args = instr.get_args_copy();
+
+    // only pointer dests are tracked
+    let dest = instr
+        .get_dest()
+        .filter(|_| instr.get_type().is_some_and(|t| is_pointer(&t)))
+        .map(|dest| dest.to_string());
+
+    match (op, dest) {
+        (OpCode::Alloc, Some(dest)) => vec![Constraint::Address {
+            dest,
+            object: MemoryObject::Allocation(location),
+        }],
+        (OpCode::Id | OpCode::Phi, Some(dest)) => args
+            .into_iter()
+            .map(|src| Constraint::Copy {
+                dest: dest.clone(),
+                src,
+            })
+            .collect(),
+        (OpCode::PtrAdd, Some(dest)) => vec![Constraint::Copy {
+            dest,
+            src: args[0].clone(),
+        }],
+        (OpCode::Load, Some(dest)) => vec![Constraint::Load {
+            dest,
+            ptr: args[0].clone(),
+        }],
+        (OpCode::Store, _) => vec![Constraint::Store {
+            ptr: args[0].clone(),
+            value: args[1].clone(),
+        }],
+        (OpCode::Call, dest) => {
+            let mut constraints: Vec<Constraint> = args
+                .into_iter()
+                .map(|value| Constraint::Escape { value })
+                .collect();
+            if let Some(dest) = dest {
+                constraints.push(Constraint::Address {
+                    dest,
+                    object: MemoryObject::External,
+                });
+            }
+
+            constraints
+        }
+        (OpCode::Ret, _) => args
+            .into_iter()
+            .map(|value| Constraint::Escape { value })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn is_pointer(var_type: &Type) -> bool {
+    matches!(var_type, Type::Ptr(_))
+}
+
+// returns true if anything was added
+fn add_all(set: &mut BTreeSet<MemoryObject>, objects: BTreeSet<MemoryObject>) -> bool {
+    let len = set.len();
+    set.extend(objects);
+
+    set.len() != len
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{basicblock::FunctionBlocksLoader, bril::loader::load_bril};
+
+    use super::{MemoryObject, PointsToAnalysis};
+
+    // a and b are separate allocations. c points into a, and d is loaded from the pointer stored
+    // in b, which is c. e comes from the caller, and f is passed to a call
+    const PROGRAM: &str = r#"{
+        "functions": [
+            {
+                "name": "main",
+                "args": [{ "name": "e", "type": { "ptr": "int" } }],
+                "instrs": [
+                    { "op": "const", "dest": "n", "type": "int", "value": 4 },
+                    { "op": "alloc", "dest": "a", "type": { "ptr": "int" }, "args": ["n"] },
+                    {
+                        "op": "alloc", "dest": "b", "type": { "ptr": { "ptr": "int" } },
+                        "args": ["n"]
+                    },
+                    { "op": "alloc", "dest": "f", "type": { "ptr": "int" }, "args": ["n"] },
+                    { "op": "ptradd", "dest": "c", "type": { "ptr": "int" }, "args": ["a", "n"] },
+                    { "op": "store", "args": ["b", "c"] },
+                    { "op": "load", "dest": "d", "type": { "ptr": "int" }, "args": ["b"] },
+                    { "op": "call", "args": ["f"], "funcs": ["use"] },
+                    { "op": "free", "args": ["a"] },
+                    { "op": "free", "args": ["b"] },
+                    { "op": "free", "args": ["f"] }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_points_to() {
+        let program = load_bril(PROGRAM).unwrap();
+        let function = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+        let analysis = PointsToAnalysis::create(&function);
+
+        assert_eq!(analysis.get_points_to("c"), analysis.get_points_to("a"));
+        assert_eq!(analysis.get_points_to("d"), analysis.get_points_to("a"));
+        assert_eq!(analysis.get_points_to("a").len(), 1);
+        assert!(analysis
+            .get_points_to("e")
+            .contains(&MemoryObject::External));
+        assert!(analysis.get_points_to("n").is_empty());
+
+        assert!(analysis.may_alias("a", "c"));
+        assert!(analysis.may_alias("a", "d"));
+        assert!(!analysis.may_alias("a", "b"));
+        assert!(!analysis.may_alias("a", "e"));
+        assert!(analysis.may_alias("e", "f"));
+
+        assert!(!analysis.may_be_accessed_by_calls("a"));
+        assert!(analysis.may_be_accessed_by_calls("f"));
+    }
+
+    fn create_analysis(program: &str) -> PointsToAnalysis {
+        let program = load_bril(program).unwrap();
+        let function = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+
+        PointsToAnalysis::create(&function)
+    }
+
+    #[test]
+    fn test_distinct_allocations() {
+        // a and b never meet, no matter how they're copied or offset
+        let analysis = create_analysis(
+            r#"{
+                "functions": [
+                    {
+                        "name": "main",
+                        "instrs": [
+                            { "op": "const", "dest": "n", "type": "int", "value": 4 },
+                            { "op": "alloc", "dest": "a", "type": { "ptr": "int" }, "args": ["n"] },
+                            { "op": "alloc", "dest": "b", "type": { "ptr": "int" }, "args": ["n"] },
+                            { "op": "id", "dest": "a2", "type": { "ptr": "int" }, "args": ["a"] },
+                            {
+                                "op": "ptradd", "dest": "b2", "type": { "ptr": "int" },
+                                "args": ["b", "n"]
+                            },
+                            { "op": "free", "args": ["a"] },
+                            { "op": "free", "args": ["b"] }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        assert!(!analysis.may_alias("a", "b"));
+        assert!(!analysis.may_alias("a2", "b"));
+        assert!(!analysis.may_alias("a", "b2"));
+        assert!(!analysis.may_alias("a2", "b2"));
+        assert!(analysis.may_alias("a", "a2"));
+        // n isn't a pointer, so it doesn't alias anything
+        assert!(!analysis.may_alias("n", "n"));
+    }
+
+    #[test]
+    fn test_ptradd_aliases_base() {
+        let analysis = create_analysis(
+            r#"{
+                "functions": [
+                    {
+                        "name": "main",
+                        "instrs": [
+                            { "op": "const", "dest": "n", "type": "int", "value": 4 },
+                            { "op": "const", "dest": "one", "type": "int", "value": 1 },
+                            { "op": "alloc", "dest": "p", "type": { "ptr": "int" }, "args": ["n"] },
+                            {
+                                "op": "ptradd", "dest": "q", "type": { "ptr": "int" },
+                                "args": ["p", "one"]
+                            },
+                            {
+                                "op": "ptradd", "dest": "r", "type": { "ptr": "int" },
+                                "args": ["q", "one"]
+                            },
+                            { "op": "free", "args": ["p"] }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        assert_eq!(analysis.get_points_to("q"), analysis.get_points_to("p"));
+        assert_eq!(analysis.get_points_to("r"), analysis.get_points_to("p"));
+        // offsets aren't tracked, so even r and q may alias
+        assert!(analysis.may_alias("p", "q"));
+        assert!(analysis.may_alias("p", "r"));
+        assert!(analysis.may_alias("q", "r"));
+    }
+
+    #[test]
+    fn test_may_be_accessed_by_calls() {
+        // b is passed to a call, and c is stored into b after that, so both escape. a and d stay
+        // local, even though d is stored into a. e comes from the caller and r from a call
+        let analysis = create_analysis(
+            r#"{
+                "functions": [
+                    {
+                        "name": "main",
+                        "args": [{ "name": "e", "type": { "ptr": "int" } }],
+                        "instrs": [
+                            { "op": "const", "dest": "n", "type": "int", "value": 4 },
+                            {
+                                "op": "alloc", "dest": "a", "type": { "ptr": { "ptr": "int" } },
+                                "args": ["n"]
+                            },
+                            {
+                                "op": "alloc", "dest": "b", "type": { "ptr": { "ptr": "int" } },
+                                "args": ["n"]
+                            },
+                            { "op": "alloc", "dest": "c", "type": { "ptr": "int" }, "args": ["n"] },
+                            { "op": "alloc", "dest": "d", "type": { "ptr": "int" }, "args": ["n"] },
+                            { "op": "call", "args": ["b"], "funcs": ["use"] },
+                            { "op": "store", "args": ["b", "c"] },
+                            { "op": "store", "args": ["a", "d"] },
+                            {
+                                "op": "call", "dest": "r", "type": { "ptr": "int" },
+                                "args": [], "funcs": ["make"]
+                            },
+                            { "op": "free", "args": ["a"] },
+                            { "op": "free", "args": ["b"] },
+                            { "op": "free", "args": ["c"] },
+                            { "op": "free", "args": ["d"] }
+                        ]
+                    }
+                ]
+            }"#,
+        );
+
+        assert!(!analysis.may_be_accessed_by_calls("a"));
+        assert!(analysis.may_be_accessed_by_calls("b"));
+        assert!(analysis.may_be_accessed_by_calls("c"));
+        assert!(!analysis.may_be_accessed_by_calls("d"));
+        assert!(analysis.may_be_accessed_by_calls("e"));
+        assert!(analysis.may_be_accessed_by_calls("r"));
+        assert!(!analysis.may_be_accessed_by_calls("n"));
+
+        // anything from outside may be an escaped allocation, but never a local one
+        assert!(analysis.may_alias("r", "c"));
+        assert!(analysis.may_alias("e", "b"));
+        assert!(!analysis.may_alias("e", "d"));
+    }
+}
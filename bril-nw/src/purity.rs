@@ -0,0 +1,219 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    basicblock::FunctionBlocksLoader,
+    bril::types::{Function, Instruction, OpCode, Program},
+    callgraph::CallGraph,
+    cfg::{traversal::EdgeKind, ControlFlowGraph},
+};
+
+/*
+    What calling a function can do, from least to most:
+    - Pure: only computes its return value from its args. A call whose result isn't used can be
+      removed, and two calls with the same args give the same result
+    - ReadOnly: also reads memory, so the result depends on what's in memory at the time of the
+      call. A call whose result isn't used can still be removed
+    - Effectful: prints, writes memory, or might never return, so every call has to stay
+*/
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Purity {
+    Pure,
+    ReadOnly,
+    Effectful,
+}
+
+impl Purity {
+    // what a sequence doing both can do
+    pub fn merge(self, other: Purity) -> Purity {
+        self.max(other)
+    }
+}
+
+/*
+    The purity of every function in a program, found bottom-up over the call graph: a function is
+    at most as pure as the instructions in it and the functions it calls.
+
+    Whether a function always returns is guessed conservatively: functions that loop, or that are
+    recursive, might not, so they are Effectful. So are calls to functions the program doesn't
+    define, since nothing is known about them.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct FunctionPurities {
+    purities: HashMap<String, Purity>,
+}
+
+impl FunctionPurities {
+    pub fn create_from_program(program: &Program) -> Self {
+        let call_graph = CallGraph::create_from_program(program);
+        let functions: HashMap<&str, &Rc<Function>> = program
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f))
+            .collect();
+
+        let mut result = FunctionPurities::default();
+        for name in call_graph.get_bottom_up_order() {
+            let function = functions[name];
+
+            // callees in the same recursive group aren't known yet, but they make the function
+            // recursive anyway
+            let purity = if call_graph.is_recursive(name) || may_loop(function) {
+                Purity::Effectful
+            } else {
+                function
+                    .instrs
+                    .iter()
+                    .map(|instr| result.get_instr_purity(instr))
+                    .fold(Purity::Pure, Purity::merge)
+            };
+
+            result.purities.insert(name.to_string(), purity);
+        }
+
+        result
+    }
+
+    // functions that aren't in the program are Effectful
+    pub fn get_purity(&self, function_name: &str) -> Purity {
+        self.purities
+            .get(function_name)
+            .copied()
+            .unwrap_or(Purity::Effectful)
+    }
+
+    // for a call, the purity of the function it calls
+    pub fn get_instr_purity(&self, instr: &Instruction) -> Purity {
+        match instr.get_op_code() {
+            Some(OpCode::Print | OpCode::Alloc | OpCode::Free | OpCode::Store) => Purity::Effectful,
+            Some(OpCode::Load) => Purity::ReadOnly,
+            Some(OpCode::Call) => instr
+                .get_funcs_copy()
+                .and_then(|funcs| funcs.first().map(|f| self.get_purity(f)))
+                .unwrap_or(Purity::Effectful),
+            _ => Purity::Pure,
+        }
+    }
+
+    // whether a call can be deleted if nothing uses its result
+    pub fn is_removable_call(&self, instr: &Instruction) -> bool {
+        instr.is_call() && self.get_instr_purity(instr) <= Purity::ReadOnly
+    }
+
+    // whether two calls with the same args always give the same result, so one can reuse the other's
+    pub fn is_mergeable_call(&self, instr: &Instruction) -> bool {
+        instr.is_call() && instr.is_value() && self.get_instr_purity(instr) == Purity::Pure
+    }
+}
+
+// whether the function's cfg has a cycle. functions that can't be loaded are assumed to have one
+fn may_loop(function: &Rc<Function>) -> bool {
+    let Ok(mut blocks) = FunctionBlocksLoader::new(function.clone()).load() else {
+        return true;
+    };
+
+    let cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+    cfg.classify_edges()
+        .values()
+        .any(|kind| *kind == EdgeKind::Back)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bril::loader::load_bril;
+
+    use super::{FunctionPurities, Purity};
+
+    // square is pure, and so is sum_squares, which only calls it. show prints, and loop and fact
+    // might never return. peek only reads memory, and poke writes it. main calls show
+    const PROGRAM: &str = r#"{
+        "functions": [
+            {
+                "name": "main",
+                "instrs": [
+                    { "op": "const", "dest": "a", "type": "int", "value": 2 },
+                    { "op": "call", "dest": "s", "type": "int", "args": ["a", "a"], "funcs": ["sum_squares"] },
+                    { "op": "call", "args": ["s"], "funcs": ["show"] }
+                ]
+            },
+            {
+                "name": "square",
+                "args": [{ "name": "x", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "mul", "dest": "r", "type": "int", "args": ["x", "x"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            },
+            {
+                "name": "sum_squares",
+                "args": [{ "name": "x", "type": "int" }, { "name": "y", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "call", "dest": "a", "type": "int", "args": ["x"], "funcs": ["square"] },
+                    { "op": "call", "dest": "b", "type": "int", "args": ["y"], "funcs": ["square"] },
+                    { "op": "add", "dest": "r", "type": "int", "args": ["a", "b"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            },
+            {
+                "name": "show",
+                "args": [{ "name": "x", "type": "int" }],
+                "instrs": [
+                    { "op": "print", "args": ["x"] }
+                ]
+            },
+            {
+                "name": "peek",
+                "args": [{ "name": "p", "type": { "ptr": "int" } }],
+                "type": "int",
+                "instrs": [
+                    { "op": "load", "dest": "v", "type": "int", "args": ["p"] },
+                    { "op": "ret", "args": ["v"] }
+                ]
+            },
+            {
+                "name": "poke",
+                "args": [{ "name": "p", "type": { "ptr": "int" } }, { "name": "v", "type": "int" }],
+                "instrs": [
+                    { "op": "store", "args": ["p", "v"] }
+                ]
+            },
+            {
+                "name": "loop",
+                "instrs": [
+                    { "label": "top" },
+                    { "op": "jmp", "labels": ["top"] }
+                ]
+            },
+            {
+                "name": "fact",
+                "args": [{ "name": "n", "type": "int" }],
+                "type": "int",
+                "instrs": [
+                    { "op": "call", "dest": "r", "type": "int", "args": ["n"], "funcs": ["fact"] },
+                    { "op": "ret", "args": ["r"] }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_function_purities() {
+        let program = load_bril(PROGRAM).unwrap();
+        let purities = FunctionPurities::create_from_program(&program);
+
+        assert_eq!(purities.get_purity("square"), Purity::Pure);
+        assert_eq!(purities.get_purity("sum_squares"), Purity::Pure);
+        assert_eq!(purities.get_purity("show"), Purity::Effectful);
+        assert_eq!(purities.get_purity("main"), Purity::Effectful);
+        assert_eq!(purities.get_purity("peek"), Purity::ReadOnly);
+        assert_eq!(purities.get_purity("poke"), Purity::Effectful);
+        assert_eq!(purities.get_purity("loop"), Purity::Effectful);
+        assert_eq!(purities.get_purity("fact"), Purity::Effectful);
+        assert_eq!(purities.get_purity("missing"), Purity::Effectful);
+
+        let main = &program.functions[0];
+        assert!(purities.is_mergeable_call(&main.instrs[1]));
+        assert!(!purities.is_removable_call(&main.instrs[2]));
+    }
+}
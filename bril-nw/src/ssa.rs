@@ -1,15 +1,39 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{HashMap, HashSet},
+    fmt,
     rc::Rc,
 };
 
-use itertools::Itertools;
-
 use crate::{
     bril::types::{Instruction, InstructionScaffold, OpCode, Type},
-    cfg::{graph::DominatorTree, ControlFlowGraph},
+    cfg::{ControlFlowGraph, DominatorTree},
 };
 
+// phi arg for a var with no definition along that edge. this is what the reference bril tools use
+pub const UNDEFINED_VAR_NAME: &str = "__undefined";
+
+// a use of a var that gets renamed, where no definition of it dominates the use. the use keeps
+// the var's old name, which nothing defines anymore
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenameFailure {
+    pub block_id: usize,
+    pub block_name: String,
+    pub instr: Rc<Instruction>,
+    pub var: String,
+}
+
+impl fmt::Display for RenameFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} could not be renamed in .{}, no definition of it dominates {}",
+            self.var,
+            self.block_name,
+            self.instr.to_string().trim()
+        )
+    }
+}
+
 struct SSAStack {
     stack: Vec<String>,
     next_name_id: usize,
@@ -19,12 +43,13 @@ struct SSABuilder<'a> {
     cfg: &'a mut ControlFlowGraph<'a>,
     dom_tree: &'a DominatorTree,
     all_vars: HashMap<String, HashSet<(usize, Type)>>,
+    // vars declared more than once, counting phi nodes. only these need new names
+    vars_to_rename: HashSet<String>,
     staged_phi_nodes: HashMap<usize, HashMap<String, InstructionScaffold>>,
 
     rename_vars_stacks: HashMap<String, SSAStack>, // for each var, have a stack of renamed vars
 
-    // mostly for dev/debug purposes. vec of (block id, var name that couldn't be renamed)
-    rename_failures: Vec<(usize, String)>,
+    rename_failures: Vec<RenameFailure>,
 }
 
 impl SSAStack {
@@ -63,6 +88,7 @@ impl<'a> SSABuilder<'a> {
             cfg,
             dom_tree,
             all_vars: HashMap::new(),
+            vars_to_rename: HashSet::new(),
             staged_phi_nodes: HashMap::new(),
 
             rename_vars_stacks: HashMap::new(),
@@ -76,10 +102,12 @@ impl<'a> SSABuilder<'a> {
         ssa_builder
     }
 
-    pub fn convert_to_ssa_form(mut self) {
+    pub fn convert_to_ssa_form(mut self) -> Vec<RenameFailure> {
         self.insert_phi_nodes();
         self.rename_vars();
         self.finalize_phi_nodes();
+
+        self.rename_failures
     }
 
     fn find_all_vars(&mut self) -> HashMap<String, HashSet<(usize, Type)>> {
@@ -92,7 +120,7 @@ impl<'a> SSABuilder<'a> {
                     let var_type = instr.get_type().unwrap();
 
                     r.entry(dest.to_string())
-                        .or_insert(HashSet::from([(block.get_id(), var_type)]))
+                        .or_insert(HashSet::from([(block.get_id(), var_type.clone())]))
                         .insert((block.get_id(), var_type));
                 }
             }
@@ -101,59 +129,111 @@ impl<'a> SSABuilder<'a> {
         r
     }
 
+    // vars read in some block before that block declares them
+    fn find_non_local_vars(&self) -> HashSet<String> {
+        let mut result = HashSet::new();
+
+        for block in self.cfg.get_function().get_blocks() {
+            let mut declared_in_block: HashSet<&str> = HashSet::new();
+            for instr in &block.instrs {
+                for arg in instr.get_args().into_iter().flatten() {
+                    if !declared_in_block.contains(arg.as_str()) {
+                        result.insert(arg.clone());
+                    }
+                }
+
+                if let Some(dest) = instr.get_dest() {
+                    declared_in_block.insert(dest);
+                }
+            }
+        }
+
+        result
+    }
+
     fn insert_phi_nodes(&mut self) {
         let mut staged_phi_nodes: HashMap<usize, HashMap<String, InstructionScaffold>> =
             HashMap::new();
 
+        let non_local_vars = self.find_non_local_vars();
+
+        let dominance_frontiers = self.cfg.find_dominance_frontiers(self.dom_tree);
         for (var, block_ids_declaring_var) in self.all_vars.iter_mut() {
-            let mut phi_insertion_candidate_blocks = VecDeque::from(
+            // a var that never lives across blocks never needs a phi (semi-pruned ssa). without
+            // this, a var declared in a loop would get a phi in the loop header with no value
+            // coming in from outside the loop
+            if !non_local_vars.contains(var) {
+                continue;
+            }
+
+            // a var is expected to have the same type everywhere it's declared
+            let var_type = block_ids_declaring_var.iter().next().unwrap().1.clone();
+
+            // every block in the iterated dominance frontier of the declaring blocks needs a phi
+            let phi_block_ids = dominance_frontiers.get_iterated(
                 block_ids_declaring_var
                     .iter()
-                    .cloned()
-                    .collect::<Vec<(usize, Type)>>(),
+                    .map(|(block_id, _)| *block_id),
             );
 
-            while !phi_insertion_candidate_blocks.is_empty() {
-                let (block_id, var_type) = phi_insertion_candidate_blocks.pop_front().unwrap();
-                let dom_frontier = self.cfg.get_dominance_frontier(self.dom_tree, block_id);
-                for dom_frontier_block_id in dom_frontier {
-                    // if we already added a phi node for this var into this block, don't do so again
-                    if staged_phi_nodes
-                        .entry(dom_frontier_block_id)
-                        .or_insert(HashMap::new())
-                        .get(var)
-                        .map_or(false, |_| true)
-                    {
-                        continue;
-                    }
+            for phi_block_id in phi_block_ids {
+                let phi = Instruction::new_value(
+                    OpCode::Phi,
+                    var.clone(),
+                    var_type.clone(),
+                    vec![], // to be filled in later after variable renaming
+                    vec![],
+                    vec![],
+                );
 
-                    let phi = Instruction::new_value(
-                        OpCode::Phi,
-                        var.clone(),
-                        var_type,
-                        vec![], // to be filled in later after variable renaming
-                        vec![],
-                        vec![],
-                    );
+                staged_phi_nodes
+                    .entry(phi_block_id)
+                    .or_default()
+                    .insert(var.clone(), (&phi).into());
 
-                    staged_phi_nodes
-                        .get_mut(&dom_frontier_block_id)
-                        .unwrap()
-                        .insert(var.clone(), (&phi).into());
+                // the phi block now declares the var too
+                block_ids_declaring_var.insert((phi_block_id, var_type.clone()));
+            }
+        }
 
-                    block_ids_declaring_var.insert((dom_frontier_block_id, var_type));
+        self.vars_to_rename = self.find_vars_to_rename(&staged_phi_nodes);
+        self.staged_phi_nodes = staged_phi_nodes;
+    }
 
-                    // this dom frontier block now declares v so we need to add it to the queue
-                    phi_insertion_candidate_blocks.push_back((dom_frontier_block_id, var_type));
-                }
+    fn find_vars_to_rename(
+        &self,
+        staged_phi_nodes: &HashMap<usize, HashMap<String, InstructionScaffold>>,
+    ) -> HashSet<String> {
+        let mut num_declarations: HashMap<&str, usize> = HashMap::new();
+        for block in self.cfg.get_function().get_blocks() {
+            for dest in block.instrs.iter().filter_map(|i| i.get_dest()) {
+                *num_declarations.entry(dest).or_default() += 1;
             }
         }
 
-        self.staged_phi_nodes = staged_phi_nodes;
+        for var in staged_phi_nodes
+            .values()
+            .flat_map(|phi_nodes| phi_nodes.keys())
+        {
+            *num_declarations.entry(var).or_default() += 1;
+        }
+
+        num_declarations
+            .into_iter()
+            .filter(|(_, n)| *n > 1)
+            .map(|(var, _)| var.to_string())
+            .collect()
     }
 
     fn rename_vars(&mut self) {
-        self.rename_vars_rec(0);
+        // function args are defined on entry, under their own names
+        for arg in self.cfg.get_function().get_args() {
+            get_or_create_arg_name_stack(&mut self.rename_vars_stacks, arg.name.clone())
+                .stack
+                .push(arg.name.clone());
+        }
+
+        self.rename_vars_rec(self.dom_tree.get_root());
     }
 
     // this function should only be called from rename_vars
@@ -177,7 +257,7 @@ impl<'a> SSABuilder<'a> {
             let arg_name_stack =
                 get_or_create_arg_name_stack(&mut self.rename_vars_stacks, staged_phi_var.clone());
 
-            let new_dest = arg_name_stack.create_new_name(&staged_phi_var);
+            let new_dest = arg_name_stack.create_new_name(staged_phi_var);
             let num_names_created_for_var = num_names_created
                 .entry(staged_phi_var.to_string())
                 .or_insert(0);
@@ -187,6 +267,7 @@ impl<'a> SSABuilder<'a> {
         }
 
         // do the same as phi nodes above while also renaming args
+        let block_name = block.get_name();
         for instr in &mut block.instrs {
             let mut new_instr = instr.as_ref().clone();
             let maybe_new_instr_args = new_instr.get_args_mut();
@@ -196,8 +277,18 @@ impl<'a> SSABuilder<'a> {
                         get_or_create_arg_name_stack(&mut self.rename_vars_stacks, arg.clone());
 
                     if arg_name_stack.is_empty() {
-                        self.rename_failures.push((block_id, arg.clone()));
-                        break;
+                        // vars declared once keep their name. anything else has lost its
+                        // definition, including vars that aren't declared anywhere
+                        if self.vars_to_rename.contains(arg) || !self.all_vars.contains_key(arg) {
+                            self.rename_failures.push(RenameFailure {
+                                block_id,
+                                block_name: block_name.clone(),
+                                instr: instr.clone(),
+                                var: arg.clone(),
+                            });
+                        }
+
+                        continue;
                     }
 
                     *arg = arg_name_stack.peek().unwrap().clone();
@@ -211,7 +302,7 @@ impl<'a> SSABuilder<'a> {
                     old_dest.to_string(),
                 );
 
-                if self.all_vars.get(old_dest).map_or(0, |h| h.len()) > 1 {
+                if self.vars_to_rename.contains(old_dest) {
                     let new_dest = arg_name_stack.create_new_name(old_dest);
 
                     let num_names_created_for_var =
@@ -242,33 +333,25 @@ impl<'a> SSABuilder<'a> {
                 let arg_name_stack =
                     get_or_create_arg_name_stack(&mut self.rename_vars_stacks, var_name.clone());
 
-                // TODO: means the var isn't defined so maybe a better error would be good here
                 let current_block_name = self
                     .cfg
                     .get_mut_function()
                     .get_block_name(block_id)
                     .unwrap();
 
-                instr
-                    .get_args_mut()
-                    .unwrap()
-                    .push(arg_name_stack.peek().unwrap().clone());
+                // the var isn't defined along this edge, e.g. it's first defined inside a loop
+                // and this is the edge into the loop
+                let phi_arg = arg_name_stack
+                    .peek()
+                    .map_or(UNDEFINED_VAR_NAME.to_string(), |name| name.clone());
+
+                instr.get_args_mut().unwrap().push(phi_arg);
                 instr.get_labels_mut().unwrap().push(current_block_name);
             }
         }
 
-        // this chain seems a little excessive lol
-        let sorted_dominated_nodes = self
-            .dom_tree
-            .0
-            .get(&block_id)
-            .unwrap_or(&HashSet::new())
-            .clone()
-            .into_iter()
-            .sorted()
-            .collect::<Vec<usize>>();
-
-        for dominated_id in sorted_dominated_nodes {
+        let dom_tree = self.dom_tree;
+        for dominated_id in dom_tree.children(block_id).iter().copied() {
             self.rename_vars_rec(dominated_id);
         }
 
@@ -288,7 +371,7 @@ impl<'a> SSABuilder<'a> {
                 .get_mut_block_by_id(*block_id)
                 .unwrap();
 
-            let label = if block.instrs.get(0).map_or(false, |i| i.is_label()) {
+            let label = if block.instrs.first().is_some_and(|i| i.is_label()) {
                 // if the first instr in the block is a label
                 Some(block.instrs[0].clone())
             } else {
@@ -300,8 +383,8 @@ impl<'a> SSABuilder<'a> {
                 .map(|i| i.into())
                 .collect::<Vec<Rc<Instruction>>>();
 
-            let combined_arr = if label.is_some() {
-                let mut r = vec![label.unwrap()];
+            let combined_arr = if let Some(label) = label {
+                let mut r = vec![label];
                 r.append(&mut phi_arr);
                 r.extend_from_slice(&block.instrs[1..]);
                 r
@@ -318,9 +401,13 @@ impl<'a> SSABuilder<'a> {
     }
 }
 
-pub fn convert_to_ssa_form<'a>(cfg: &'a mut ControlFlowGraph<'a>, dom_tree: &'a DominatorTree) {
+// returns the uses that could not be renamed
+pub fn convert_to_ssa_form<'a>(
+    cfg: &'a mut ControlFlowGraph<'a>,
+    dom_tree: &'a DominatorTree,
+) -> Vec<RenameFailure> {
     let ssa_builder = SSABuilder::new(cfg, dom_tree);
-    ssa_builder.convert_to_ssa_form();
+    ssa_builder.convert_to_ssa_form()
 }
 
 fn get_or_create_arg_name_stack(
@@ -366,7 +453,7 @@ mod tests {
             let dominators = cfg.find_dominators();
             let dom_tree = cfg.create_dominator_tree(&dominators);
 
-            super::convert_to_ssa_form(&mut cfg, &dom_tree);
+            assert_eq!(super::convert_to_ssa_form(&mut cfg, &dom_tree), vec![]);
         }
 
         let expected_contents = load_bril_from_test_dir(expected_file);
@@ -388,4 +475,38 @@ mod tests {
     fn test_loop() {
         run_bril_ssa_comparison("loop_orig.json", "loop_ssa.json");
     }
+
+    #[test]
+    fn test_local_var() {
+        // t is only ever used in the block declaring it, so it doesn't get a phi in the loop header
+        run_bril_ssa_comparison("local_var_orig.json", "local_var_ssa.json");
+    }
+
+    #[test]
+    fn test_undefined_phi_arg() {
+        // x is first defined inside the loop, so nothing defines it on the edge into the loop
+        run_bril_ssa_comparison("undefined_orig.json", "undefined_ssa.json");
+    }
+
+    #[test]
+    fn test_rename_failure() {
+        // x gets renamed, but the print in entry comes before any definition of it
+        let contents = load_bril_from_test_dir("use_before_def_orig.json");
+        let program = load_bril(&contents).unwrap();
+        let mut blocks = FunctionBlocksLoader::new(program.functions[0].clone())
+            .load()
+            .unwrap();
+
+        let mut cfg = ControlFlowGraph::create_from_basic_blocks(&mut blocks);
+        let dom_tree = cfg.create_dominator_tree(&cfg.find_dominators());
+        let rename_failures = super::convert_to_ssa_form(&mut cfg, &dom_tree);
+
+        assert_eq!(rename_failures.len(), 1);
+        assert_eq!(rename_failures[0].block_name, "entry");
+        assert_eq!(rename_failures[0].var, "x");
+        assert_eq!(
+            rename_failures[0].to_string(),
+            "x could not be renamed in .entry, no definition of it dominates print x"
+        );
+    }
 }
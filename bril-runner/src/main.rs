@@ -1,8 +1,25 @@
 extern crate bril_nw;
 extern crate clap;
 
-use bril_nw::{basicblock, bril, cfg, ssa};
-use std::{fs, path::Path, process};
+use bril_nw::{
+    basicblock, bril, callgraph,
+    cfg::{
+        self,
+        dataflow::{
+            def_use::DefUseChains,
+            defined_variables::DefinedVariables,
+            reaching_definitions::{Definition, DefinitionSite, InstrLocation},
+            report::{self, DataFlowReport},
+        },
+    },
+    ssa,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
 
 use clap::{arg, command};
 
@@ -10,7 +27,13 @@ struct CompilerConfig {
     file_name: String,
     display_blocks: bool,
     display_cfg: bool,
+    normalize_cfg: bool,
     convert_to_ssa: bool,
+    display_chains: bool,
+    dot_output: Option<String>,
+    dot_show_instrs: bool,
+    analysis: Option<String>,
+    json_output: bool,
 }
 
 fn main() {
@@ -18,7 +41,7 @@ fn main() {
 
     let in_file_path = Path::new(cmd_line.file_name.as_str());
     if !in_file_path.exists() {
-        println!(
+        eprintln!(
             "bril-runner: error: Input file {} not found.",
             cmd_line.file_name
         );
@@ -28,7 +51,7 @@ fn main() {
 
     let contents = fs::read_to_string(in_file_path);
     if let Err(e) = contents {
-        println!("Error reading file: {:?}", e);
+        eprintln!("Error reading file: {:?}", e);
         process::exit(1);
     }
 
@@ -38,23 +61,36 @@ fn main() {
     drop(contents);
 
     if let Err(e) = loaded_bril {
-        println!("Error occurred parsing BRIL {:?}", e);
+        eprintln!("Error occurred parsing BRIL {:?}", e);
         process::exit(1);
     }
 
     let loaded_bril = loaded_bril.unwrap();
 
+    if cmd_line.dot_output.as_deref() == Some("callgraph") {
+        let call_graph = callgraph::CallGraph::create_from_program(&loaded_bril);
+        write_dot_file(in_file_path, "callgraph", &call_graph.to_dot());
+    }
+
+    let mut reports: Vec<DataFlowReport> = Vec::new();
     for func in loaded_bril.functions {
         let loader = basicblock::FunctionBlocksLoader::new(func.clone());
         let maybe_bb = loader.load();
         if let Err(errs) = maybe_bb {
-            println!("Errors occurred loading function: {}", errs.join("\n"));
+            eprintln!("Errors occurred loading function: {}", errs.join("\n"));
             continue;
         }
 
         let mut bb = maybe_bb.unwrap();
+        if cmd_line.normalize_cfg {
+            cfg::normalize::normalize(&mut bb);
+        }
 
         let mut cfg = cfg::ControlFlowGraph::create_from_basic_blocks(&mut bb);
+        for undefined_use in DefinedVariables::new(&cfg).find_undefined_uses() {
+            eprintln!("// warning: @{}: {}", func.name, undefined_use);
+        }
+
         if cmd_line.display_cfg {
             println!("// cfg: {}", cfg);
         }
@@ -62,24 +98,143 @@ fn main() {
         let dominators = cfg.find_dominators();
         let dom_tree = cfg.create_dominator_tree(&dominators);
         if cmd_line.display_cfg {
-            println!("// domtree: {:?}", dom_tree.0);
+            let immediate_dominators: BTreeMap<_, _> =
+                dom_tree.get_immediate_dominators().iter().collect();
+            println!("// idoms: {:?}", immediate_dominators);
+        }
+
+        if cmd_line.display_chains {
+            print_def_use_chains(&cfg);
+        }
+
+        if let Some(analysis) = &cmd_line.analysis {
+            let report = report::create_report(analysis, &cfg).unwrap();
+            if !cmd_line.json_output {
+                print!("{}", report.to_annotated_string());
+            }
+            reports.push(report);
+        }
+
+        match cmd_line.dot_output.as_deref() {
+            Some("cfg") => {
+                let loop_forest = cfg.find_natural_loops(&dom_tree);
+                let clusters = cfg::dot::create_loop_clusters(cfg.get_function(), &loop_forest);
+                let dot = cfg.to_dot_with_clusters(cmd_line.dot_show_instrs, &clusters);
+                write_dot_file(in_file_path, &format!("{}.cfg", func.name), &dot);
+            }
+            Some("domtree") => {
+                let dot = dom_tree.to_dot(cfg.get_function());
+                write_dot_file(in_file_path, &format!("{}.domtree", func.name), &dot);
+            }
+            _ => (),
         }
 
         if cmd_line.convert_to_ssa {
-            ssa::convert_to_ssa_form(&mut cfg, &dom_tree);
+            for rename_failure in ssa::convert_to_ssa_form(&mut cfg, &dom_tree) {
+                eprintln!("// warning: @{}: {}", func.name, rename_failure);
+            }
         }
 
         if cmd_line.display_blocks {
             println!("{}", bb);
         }
     }
+
+    if let (Some(analysis), true) = (&cmd_line.analysis, cmd_line.json_output) {
+        println!("{}", report::reports_to_json(analysis, &reports).pretty(2));
+    }
+}
+
+// uses are printed as block id:instr index, definitions as var@block id:instr index
+fn print_def_use_chains(cfg: &cfg::ControlFlowGraph) {
+    let chains = DefUseChains::create(cfg);
+
+    let mut use_def_lines = Vec::new();
+    let mut def_use_lines = Vec::new();
+    for block in cfg.get_function().get_blocks() {
+        for (instr_idx, instr) in block.instrs.iter().enumerate() {
+            let location = InstrLocation {
+                block_id: block.get_id(),
+                instr_idx,
+            };
+
+            let used_vars: BTreeSet<String> = instr.get_args_copy().into_iter().collect();
+            for var in used_vars {
+                let definitions = chains.get_definitions(location, &var);
+                use_def_lines.push(format!(
+                    "//   {} {} <- {}",
+                    location,
+                    var,
+                    definitions
+                        .iter()
+                        .map(|d| d.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ));
+            }
+
+            if let Some(dest) = instr.get_dest() {
+                let definition = Definition {
+                    var: dest.to_string(),
+                    site: DefinitionSite::Instr(location),
+                };
+                let uses = chains.get_uses(&definition);
+                def_use_lines.push(format!(
+                    "//   {} -> {}",
+                    definition,
+                    uses.iter()
+                        .map(|u| u.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ));
+            }
+        }
+    }
+
+    println!("// use-def chains:");
+    use_def_lines.iter().for_each(|line| println!("{}", line));
+    println!("// def-use chains:");
+    def_use_lines.iter().for_each(|line| println!("{}", line));
+}
+
+// writes <input file stem>.<name>.dot next to the input file
+fn write_dot_file(in_file_path: &Path, name: &str, dot: &str) {
+    let file_stem = in_file_path
+        .file_stem()
+        .map_or("out".to_string(), |s| s.to_string_lossy().to_string());
+
+    let mut out_path = PathBuf::from(in_file_path.parent().unwrap_or_else(|| Path::new("")));
+    out_path.push(format!("{}.{}.dot", file_stem, name));
+
+    if let Err(e) = fs::write(&out_path, dot) {
+        eprintln!("Error writing {}: {:?}", out_path.display(), e);
+    }
 }
 
 fn parse_cmd_line() -> CompilerConfig {
     let m = command!()
         .arg(arg!(-b --"blocks" "Display loaded blocks in BRIL notation"))
         .arg(arg!(-g --"graphs" "Display Control Flow Graph and related structures"))
+        .arg(arg!(-n --"normalize" "Give each function a single entry and exit block"))
         .arg(arg!(-s --"ssa" "Convert loaded blocks into SSA form before displaying"))
+        .arg(arg!(-c --"chains" "Display use-def and def-use chains"))
+        .arg(
+            arg!(--"dot" <KIND> "Write a Graphviz DOT file per function, or one for the call graph")
+                .required(false)
+                .possible_values(["cfg", "domtree", "callgraph"]),
+        )
+        .arg(arg!(--"dot-instrs" "Include instructions in DOT control flow graphs"))
+        .arg(
+            arg!(--"analyze" <ANALYSIS> "Display the facts a dataflow analysis finds for each block")
+                .required(false)
+                .possible_values(report::ANALYSIS_NAMES),
+        )
+        .arg(
+            arg!(--"format" <FORMAT> "Output format for --analyze")
+                .required(false)
+                .possible_values(["text", "json"])
+                .default_value("text"),
+        )
         .arg(arg!([NAME] "File to compile").required(true))
         .get_matches();
 
@@ -89,6 +244,12 @@ fn parse_cmd_line() -> CompilerConfig {
         file_name,
         display_blocks: m.is_present("blocks"),
         display_cfg: m.is_present("graphs"),
+        normalize_cfg: m.is_present("normalize"),
         convert_to_ssa: m.is_present("ssa"),
+        display_chains: m.is_present("chains"),
+        dot_output: m.value_of("dot").map(|s| s.to_string()),
+        dot_show_instrs: m.is_present("dot-instrs"),
+        analysis: m.value_of("analyze").map(|s| s.to_string()),
+        json_output: m.value_of("format") == Some("json"),
     }
 }